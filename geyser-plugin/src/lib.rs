@@ -0,0 +1,309 @@
+//! Geyser plugin that runs the parser inside a validator or RPC node. Load it with a config
+//! such as:
+//!
+//! ```json
+//! {
+//!     "libpath": "/path/to/libsolana_parser_geyser_plugin.so",
+//!     "output": "/var/log/solana-parser/transactions.log",
+//!     "programs": ["675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8"],
+//!     "queue_size": 10000
+//! }
+//! ```
+//!
+//! Each parsed transaction is written to `output` as one line of JSON, in the layout described
+//! in `docs/json-output.md`. `output` defaults to stdout and an empty or missing `programs`
+//! parses every non-vote transaction. At most `queue_size` transactions wait for the writer,
+//! once it falls that far behind new transactions are dropped and counted rather than stalling
+//! replay.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use agave_geyser_plugin_interface::geyser_plugin_interface::{
+    GeyserPlugin, GeyserPluginError, ReplicaTransactionInfoVersions, Result,
+};
+use serde_json::Value;
+use solana_parser::pipeline;
+use solana_sdk::clock::Slot;
+use solana_sdk::pubkey::Pubkey;
+
+const DEFAULT_QUEUE_SIZE: usize = 10_000;
+
+#[derive(Debug, Default)]
+pub struct ParserPlugin {
+    programs: HashSet<Pubkey>,
+    queue_size: usize,
+    /// Parsed transactions go to a writer thread so the sink never stalls replay.
+    sender: Option<SyncSender<String>>,
+    writer: Option<JoinHandle<()>>,
+    /// Transactions dropped because the writer's queue was full.
+    dropped: AtomicU64,
+}
+
+impl ParserPlugin {
+    fn read_config(&mut self, config_file: &str) -> Result<Box<dyn Write + Send>> {
+        let contents = fs::read_to_string(config_file)?;
+        let config = serde_json::from_str::<Value>(&contents)
+            .map_err(|e| GeyserPluginError::ConfigFileReadError { msg: e.to_string() })?;
+
+        for program in config["programs"].as_array().into_iter().flatten() {
+            let program = program
+                .as_str()
+                .and_then(|program| Pubkey::from_str(program).ok())
+                .ok_or_else(|| GeyserPluginError::ConfigFileReadError {
+                    msg: format!("Invalid program {}", program),
+                })?;
+            self.programs.insert(program);
+        }
+
+        self.queue_size = match &config["queue_size"] {
+            Value::Null => DEFAULT_QUEUE_SIZE,
+            queue_size => queue_size
+                .as_u64()
+                .filter(|&queue_size| queue_size > 0)
+                .ok_or_else(|| GeyserPluginError::ConfigFileReadError {
+                    msg: format!("Invalid queue_size {}", queue_size),
+                })? as usize,
+        };
+
+        Ok(match config["output"].as_str() {
+            Some(output) => Box::new(
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(output)?,
+            ),
+            None => Box::new(io::stdout()),
+        })
+    }
+
+    /// Hands a line to the writer without blocking, dropping it when the queue is full.
+    fn queue(&self, line: String) -> Result<()> {
+        let Some(sender) = self.sender.as_ref() else {
+            return Ok(());
+        };
+        match sender.try_send(line) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped.is_power_of_two() {
+                    log::warn!("Writer queue full, {} parsed transactions dropped so far", dropped);
+                }
+                Ok(())
+            }
+            Err(e @ TrySendError::Disconnected(_)) => Err(GeyserPluginError::Custom(Box::new(e))),
+        }
+    }
+}
+
+impl GeyserPlugin for ParserPlugin {
+    fn name(&self) -> &'static str {
+        "solana-parser"
+    }
+
+    fn on_load(&mut self, config_file: &str, _is_reload: bool) -> Result<()> {
+        let output = self.read_config(config_file)?;
+        let (sender, lines) = mpsc::sync_channel::<String>(self.queue_size);
+
+        let writer = thread::Builder::new()
+            .name("solana-parser-writer".to_string())
+            .spawn(move || {
+                let mut output = BufWriter::new(output);
+                while let Ok(line) = lines.recv() {
+                    // Everything already queued is written before flushing.
+                    let written = std::iter::once(line)
+                        .chain(lines.try_iter())
+                        .try_for_each(|line| writeln!(output, "{}", line))
+                        .and_then(|_| output.flush());
+                    if let Err(e) = written {
+                        log::error!("Error writing parsed transaction: {:?}", e);
+                    }
+                }
+            })?;
+
+        self.sender = Some(sender);
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn on_unload(&mut self) {
+        // Dropping the sender ends the writer once it has drained the queue.
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        let dropped = self.dropped.load(Ordering::Relaxed);
+        if dropped > 0 {
+            log::warn!("{} parsed transactions were dropped because the writer fell behind", dropped);
+        }
+    }
+
+    fn notify_transaction(&self, transaction: ReplicaTransactionInfoVersions, slot: Slot) -> Result<()> {
+        let (is_vote, sanitized_transaction, meta, index) = match transaction {
+            ReplicaTransactionInfoVersions::V0_0_1(info) => {
+                (info.is_vote, info.transaction, info.transaction_status_meta, None)
+            }
+            ReplicaTransactionInfoVersions::V0_0_2(info) => {
+                (info.is_vote, info.transaction, info.transaction_status_meta, Some(info.index))
+            }
+        };
+        if is_vote || meta.status.is_err() {
+            return Ok(());
+        }
+        if !self.programs.is_empty()
+            && !sanitized_transaction
+                .message()
+                .account_keys()
+                .iter()
+                .any(|key| self.programs.contains(key))
+        {
+            return Ok(());
+        }
+
+        // This runs in the validator's replay path, a parser bug must not take the node down.
+        let parsed = panic::catch_unwind(AssertUnwindSafe(|| {
+            pipeline::parse_sanitized_transaction(sanitized_transaction, meta)
+        }));
+        let mut parsed = match parsed {
+            Ok(parsed) => parsed,
+            Err(_) => {
+                log::error!("Parser panicked on transaction {}", sanitized_transaction.signature());
+                return Ok(());
+            }
+        };
+        parsed.slot = Some(slot);
+        parsed.transaction_index = index;
+        let line = serde_json::to_string(&parsed).map_err(|e| GeyserPluginError::Custom(Box::new(e)))?;
+        self.queue(line)
+    }
+
+    fn account_data_notifications_enabled(&self) -> bool {
+        false
+    }
+
+    fn transaction_notifications_enabled(&self) -> bool {
+        true
+    }
+}
+
+/// # Safety
+///
+/// Called by the validator's plugin manager, which takes ownership of the returned plugin.
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPlugin {
+    let plugin: Box<dyn GeyserPlugin> = Box::new(ParserPlugin::default());
+    Box::into_raw(plugin)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc;
+    use agave_geyser_plugin_interface::geyser_plugin_interface::{
+        GeyserPlugin, ReplicaTransactionInfoV2, ReplicaTransactionInfoVersions,
+    };
+    use serde_json::{json, Value};
+    use solana_sdk::instruction::Instruction;
+    use solana_sdk::message::Message;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::{SanitizedTransaction, Transaction};
+    use solana_transaction_status_client_types::TransactionStatusMeta;
+    use super::ParserPlugin;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("solana-parser-geyser-{}-{}", name, Pubkey::new_unique()))
+    }
+
+    fn load(config: Value) -> ParserPlugin {
+        let config_file = temp_path("config.json");
+        fs::write(&config_file, config.to_string()).unwrap();
+        let mut plugin = ParserPlugin::default();
+        plugin.on_load(config_file.to_str().unwrap(), false).unwrap();
+        fs::remove_file(config_file).unwrap();
+        plugin
+    }
+
+    /// Calls `program` with no accounts, paid for by a fresh payer.
+    fn transaction(program: &Pubkey) -> SanitizedTransaction {
+        let instruction = Instruction::new_with_bytes(*program, &[], vec![]);
+        let message = Message::new(&[instruction], Some(&Pubkey::new_unique()));
+        SanitizedTransaction::from_transaction_for_tests(Transaction::new_unsigned(message))
+    }
+
+    fn notify(plugin: &ParserPlugin, transaction: &SanitizedTransaction, is_vote: bool, index: usize) {
+        let meta = TransactionStatusMeta {
+            fee: 5000,
+            pre_balances: vec![1_000_000_000, 1],
+            post_balances: vec![999_995_000, 1],
+            ..TransactionStatusMeta::default()
+        };
+        let info = ReplicaTransactionInfoV2 {
+            signature: transaction.signature(),
+            is_vote,
+            transaction,
+            transaction_status_meta: &meta,
+            index,
+        };
+        plugin
+            .notify_transaction(ReplicaTransactionInfoVersions::V0_0_2(&info), 7)
+            .unwrap();
+    }
+
+    #[test]
+    fn writes_transactions_calling_the_configured_programs() {
+        let (program, output) = (Pubkey::new_unique(), temp_path("output.log"));
+        let mut plugin = load(json!({
+            "output": output.to_str().unwrap(),
+            "programs": [program.to_string()],
+        }));
+        notify(&plugin, &transaction(&program), true, 0);
+        notify(&plugin, &transaction(&Pubkey::new_unique()), false, 1);
+        notify(&plugin, &transaction(&program), false, 2);
+        // Unloading drains the queue into the output.
+        plugin.on_unload();
+
+        let written = fs::read_to_string(&output).unwrap();
+        fs::remove_file(output).unwrap();
+        let lines: Vec<Value> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["slot"], 7);
+        assert_eq!(lines[0]["transaction_index"], 2);
+        assert_eq!(lines[0]["balance_changes"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        for config in [json!({"programs": ["not a pubkey"]}), json!({"queue_size": 0})] {
+            let config_file = temp_path("config.json");
+            fs::write(&config_file, config.to_string()).unwrap();
+            let loaded = ParserPlugin::default().on_load(config_file.to_str().unwrap(), false);
+            fs::remove_file(config_file).unwrap();
+            assert!(loaded.is_err(), "{}", config);
+        }
+    }
+
+    #[test]
+    fn drops_lines_once_the_queue_is_full() {
+        let (sender, lines) = mpsc::sync_channel(1);
+        let plugin = ParserPlugin { sender: Some(sender), ..ParserPlugin::default() };
+
+        plugin.queue("first".to_string()).unwrap();
+        plugin.queue("second".to_string()).unwrap();
+        plugin.queue("third".to_string()).unwrap();
+        assert_eq!(plugin.dropped.load(Ordering::Relaxed), 2);
+        assert_eq!(lines.try_iter().collect::<Vec<_>>(), ["first"]);
+
+        // A writer that has gone away is an error rather than a drop.
+        drop(lines);
+        assert!(plugin.queue("fourth".to_string()).is_err());
+        assert_eq!(plugin.dropped.load(Ordering::Relaxed), 2);
+    }
+}
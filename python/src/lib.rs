@@ -0,0 +1,170 @@
+//! Python bindings, built with [maturin](https://www.maturin.rs):
+//!
+//! ```sh
+//! cd python && maturin develop --release
+//! ```
+//!
+//! ```python
+//! import solana_parser
+//!
+//! parsed = solana_parser.parse_transaction(response)  # a getTransaction str or dict
+//! for swap in parsed["swaps"]:
+//!     print(swap["token_in"], swap["amount_in"], swap["token_out"], swap["amount_out"])
+//!
+//! results = solana_parser.parse_transactions(responses)
+//! ```
+//!
+//! Results are dicts in the layout described in `docs/json-output.md`. Each input is a
+//! getTransaction response, the JSON-RPC envelope or just its `result`, as the `file` command
+//! reads them. Lookup tables are not fetched, so v0 transactions need `loadedAddresses` in
+//! their meta, which getTransaction always includes.
+
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use pyo3::exceptions::{PyTypeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use solana_parser::offline;
+use solana_parser::pipeline::{self, ParsedTransaction};
+
+/// Pools for an explicit `threads`, built on first use and kept for later calls.
+static POOLS: Mutex<BTreeMap<usize, Arc<ThreadPool>>> = Mutex::new(BTreeMap::new());
+
+/// Parses one getTransaction response, a JSON string or the dict `json.loads` gives for one.
+/// Returns `None` for a `null` result or a transaction that fails to decode.
+#[pyfunction]
+fn parse_transaction(py: Python<'_>, transaction: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+    let json = to_json(py, transaction)?;
+    let parsed = parse(&json).map_err(PyValueError::new_err)?;
+    to_python(py, parsed.as_ref())
+}
+
+/// Parses a list of getTransaction responses on `threads` threads, all cores if not given,
+/// without holding the GIL. Returns a list in the same order, with `None` where
+/// `parse_transaction` would return it or where the parser panicked on that transaction.
+#[pyfunction]
+#[pyo3(signature = (transactions, threads=None))]
+fn parse_transactions(
+    py: Python<'_>,
+    transactions: Vec<Bound<'_, PyAny>>,
+    threads: Option<usize>,
+) -> PyResult<Vec<PyObject>> {
+    let json = transactions
+        .iter()
+        .map(|transaction| to_json(py, transaction))
+        .collect::<PyResult<Vec<String>>>()?;
+
+    let parse_all = || parse_each(&json, parse);
+    let parsed = match threads {
+        Some(threads) => {
+            let pool = thread_pool(threads)?;
+            py.allow_threads(|| pool.install(parse_all))
+        }
+        None => py.allow_threads(parse_all),
+    };
+
+    parsed
+        .into_iter()
+        .enumerate()
+        .map(|(index, parsed)| {
+            let parsed = parsed.map_err(|e| PyValueError::new_err(format!("transaction {}: {}", index, e)))?;
+            to_python(py, parsed.as_ref())
+        })
+        .collect()
+}
+
+type Parsed = Result<Option<ParsedTransaction>, String>;
+
+/// Runs `parse` on every input in parallel, a panic only loses the result of the input it
+/// panicked on.
+fn parse_each(json: &[String], parse: impl Fn(&str) -> Parsed + Sync) -> Vec<Parsed> {
+    json.par_iter()
+        .enumerate()
+        .map(|(index, json)| {
+            panic::catch_unwind(AssertUnwindSafe(|| parse(json))).unwrap_or_else(|_| {
+                eprintln!("Error: Parser panicked on transaction {}", index);
+                Ok(None)
+            })
+        })
+        .collect()
+}
+
+fn thread_pool(threads: usize) -> PyResult<Arc<ThreadPool>> {
+    let mut pools = POOLS.lock().unwrap();
+    if let Some(pool) = pools.get(&threads) {
+        return Ok(pool.clone());
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    Ok(pools.entry(threads).or_insert(Arc::new(pool)).clone())
+}
+
+/// The entry point `main.rs` uses for saved responses, limited to one transaction.
+fn parse(json: &str) -> Parsed {
+    let txs = offline::transactions_from_json(json).map_err(|e| e.to_string())?;
+    match txs.as_slice() {
+        [] => Ok(None),
+        [tx] => Ok(pipeline::parse_confirmed_transaction(tx, None)),
+        _ => Err(format!("expected one transaction, found {}", txs.len())),
+    }
+}
+
+fn to_json(py: Python<'_>, transaction: &Bound<'_, PyAny>) -> PyResult<String> {
+    if let Ok(json) = transaction.downcast::<PyString>() {
+        return Ok(json.to_str()?.to_string());
+    }
+    if transaction.is_instance_of::<PyDict>() {
+        return py.import("json")?.call_method1("dumps", (transaction,))?.extract();
+    }
+    Err(PyTypeError::new_err("expected a JSON string or a dict"))
+}
+
+/// Goes through Python's `json` so `u64` amounts stay exact, Python ints have no limit.
+fn to_python(py: Python<'_>, parsed: Option<&ParsedTransaction>) -> PyResult<PyObject> {
+    match parsed {
+        Some(parsed) => {
+            let json = serde_json::to_string(parsed).map_err(|e| PyValueError::new_err(e.to_string()))?;
+            Ok(py.import("json")?.call_method1("loads", (json,))?.unbind())
+        }
+        None => Ok(py.None()),
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "solana_parser")]
+fn solana_parser_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(parse_transaction, m)?)?;
+    m.add_function(wrap_pyfunction!(parse_transactions, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, parse_each};
+
+    #[test]
+    fn a_panic_only_loses_its_own_transaction() {
+        let json: Vec<String> = ["null", "panic", "not json", "null"].iter().map(|json| json.to_string()).collect();
+        let parsed = parse_each(&json, |json| {
+            if json == "panic" {
+                panic!("parser bug");
+            }
+            Err(json.to_string())
+        });
+        assert_eq!(
+            parsed.into_iter().map(|parsed| parsed.map(|parsed| parsed.is_some())).collect::<Vec<_>>(),
+            [Err("null".to_string()), Ok(false), Err("not json".to_string()), Err("null".to_string())]
+        );
+    }
+
+    #[test]
+    fn parses_at_most_one_transaction() {
+        assert!(parse(r#"{"jsonrpc": "2.0", "result": null, "id": 1}"#).unwrap().is_none());
+        assert!(parse("not json").is_err());
+    }
+}
@@ -0,0 +1,389 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use solana_sdk::program_option::COption;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_instruction::SystemInstruction;
+use spl_token::instruction::{AuthorityType, TokenInstruction};
+use crate::tx_parser::{FlatInstruction, KnownInstruction};
+
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountEventKind {
+    /// System `CreateAccount`, `owner` is the program that will own the new account.
+    CreateAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        funder: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        lamports: u64,
+        space: u64,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+    },
+    /// Any of the token program's `InitializeAccount`, `InitializeAccount2` or `InitializeAccount3`.
+    InitializeAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+    },
+    /// Associated Token Account program `Create` or `CreateIdempotent`, the latter is a no-op
+    /// when the account already exists but still names its wallet and mint.
+    CreateAssociatedTokenAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        funder: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        wallet: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        idempotent: bool,
+    },
+    CloseAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        destination: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+    },
+    SetAuthority {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde(with = "authority_type")]
+        authority_type: AuthorityType,
+        #[serde_as(as = "DisplayFromStr")]
+        current_authority: Pubkey,
+        #[serde_as(as = "Option<DisplayFromStr>")]
+        new_authority: Option<Pubkey>,
+    },
+    /// `Approve` or `ApproveChecked`, only the latter carries the mint and decimals.
+    Approve {
+        #[serde_as(as = "DisplayFromStr")]
+        source: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        delegate: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+        amount: u64,
+        #[serde_as(as = "Option<DisplayFromStr>")]
+        mint: Option<Pubkey>,
+        decimals: Option<u8>,
+    },
+    Revoke {
+        #[serde_as(as = "DisplayFromStr")]
+        source: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+    },
+    FreezeAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        authority: Pubkey,
+    },
+    ThawAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        authority: Pubkey,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountEvent {
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    pub kind: AccountEventKind,
+}
+
+/// `AuthorityType` has no serde support, it is written by name, e.g. `"AccountOwner"`.
+mod authority_type {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use spl_token::instruction::AuthorityType;
+
+    pub fn serialize<S: Serializer>(authority_type: &AuthorityType, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{:?}", authority_type))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<AuthorityType, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "MintTokens" => Ok(AuthorityType::MintTokens),
+            "FreezeAccount" => Ok(AuthorityType::FreezeAccount),
+            "AccountOwner" => Ok(AuthorityType::AccountOwner),
+            "CloseAccount" => Ok(AuthorityType::CloseAccount),
+            other => Err(de::Error::unknown_variant(
+                other,
+                &["MintTokens", "FreezeAccount", "AccountOwner", "CloseAccount"],
+            )),
+        }
+    }
+}
+
+pub fn parse_account_events(
+    instructions: &[FlatInstruction],
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Vec<AccountEvent> {
+    let mut account_events = Vec::<AccountEvent>::new();
+
+    for instruction in instructions.iter() {
+        if let Some(kind) = process_instruction(instruction, account_keys) {
+            account_events.push(AccountEvent {
+                instruction_index: instruction.instruction_index,
+                inner_instruction_index: instruction.inner_instruction_index,
+                kind,
+            });
+        }
+    }
+
+    account_events
+}
+
+pub fn process_instruction(
+    instruction: &FlatInstruction,
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Option<AccountEventKind> {
+    let program = account_keys.get(&instruction.program_id_index)?;
+    let account = |position: usize| -> Option<Pubkey> {
+        account_keys.get(instruction.accounts.get(position)?).cloned()
+    };
+
+    match instruction.known(program)? {
+        KnownInstruction::Token(token_instruction) => match token_instruction {
+            TokenInstruction::InitializeAccount => Some(AccountEventKind::InitializeAccount {
+                account: account(0)?,
+                mint: account(1)?,
+                owner: account(2)?,
+            }),
+            TokenInstruction::InitializeAccount2 { owner }
+            | TokenInstruction::InitializeAccount3 { owner } => {
+                Some(AccountEventKind::InitializeAccount {
+                    account: account(0)?,
+                    mint: account(1)?,
+                    owner,
+                })
+            }
+            TokenInstruction::CloseAccount => Some(AccountEventKind::CloseAccount {
+                account: account(0)?,
+                destination: account(1)?,
+                owner: account(2)?,
+            }),
+            TokenInstruction::SetAuthority {
+                authority_type,
+                new_authority,
+            } => Some(AccountEventKind::SetAuthority {
+                account: account(0)?,
+                authority_type,
+                current_authority: account(1)?,
+                new_authority: match new_authority {
+                    COption::Some(new_authority) => Some(new_authority),
+                    COption::None => None,
+                },
+            }),
+            TokenInstruction::Approve { amount } => Some(AccountEventKind::Approve {
+                source: account(0)?,
+                delegate: account(1)?,
+                owner: account(2)?,
+                amount,
+                mint: None,
+                decimals: None,
+            }),
+            TokenInstruction::ApproveChecked { amount, decimals } => {
+                Some(AccountEventKind::Approve {
+                    source: account(0)?,
+                    delegate: account(2)?,
+                    owner: account(3)?,
+                    amount,
+                    mint: Some(account(1)?),
+                    decimals: Some(decimals),
+                })
+            }
+            TokenInstruction::Revoke => Some(AccountEventKind::Revoke {
+                source: account(0)?,
+                owner: account(1)?,
+            }),
+            TokenInstruction::FreezeAccount => Some(AccountEventKind::FreezeAccount {
+                account: account(0)?,
+                mint: account(1)?,
+                authority: account(2)?,
+            }),
+            TokenInstruction::ThawAccount => Some(AccountEventKind::ThawAccount {
+                account: account(0)?,
+                mint: account(1)?,
+                authority: account(2)?,
+            }),
+            _ => None,
+        },
+        KnownInstruction::AssociatedToken { idempotent } => {
+            Some(AccountEventKind::CreateAssociatedTokenAccount {
+                funder: account(0)?,
+                account: account(1)?,
+                wallet: account(2)?,
+                mint: account(3)?,
+                idempotent,
+            })
+        }
+        KnownInstruction::System(SystemInstruction::CreateAccount {
+            lamports,
+            space,
+            owner,
+        }) => Some(AccountEventKind::CreateAccount {
+            funder: account(0)?,
+            account: account(1)?,
+            lamports,
+            space,
+            owner,
+        }),
+        KnownInstruction::System(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use solana_sdk::program_option::COption;
+    use solana_sdk::pubkey::Pubkey;
+    use spl_token::instruction::{AuthorityType, TokenInstruction};
+    use crate::tx_parser::FlatInstruction;
+    use crate::{ASSOCIATED_TOKEN_PROGRAM, TOKEN_PROGRAM};
+    use super::{parse_account_events, AccountEventKind};
+
+    const ACCOUNTS: u8 = 5;
+
+    /// Account keys where each of the instruction's accounts sits at the index of its position,
+    /// with `program` after them.
+    fn account_keys(program: &str) -> HashMap<u8, Pubkey> {
+        let mut account_keys: HashMap<u8, Pubkey> = (0..ACCOUNTS).map(|index| (index, Pubkey::new_unique())).collect();
+        account_keys.insert(ACCOUNTS, Pubkey::from_str(program).unwrap());
+        account_keys
+    }
+
+    fn parse(data: Vec<u8>, account_keys: &HashMap<u8, Pubkey>) -> Option<AccountEventKind> {
+        let instruction = FlatInstruction {
+            instruction_index: 0,
+            inner_instruction_index: None,
+            stack_height: None,
+            program_id_index: ACCOUNTS,
+            accounts: (0..ACCOUNTS).collect(),
+            data,
+            parsed: None,
+        };
+        let mut events = parse_account_events(&[instruction], account_keys);
+        assert!(events.len() <= 1);
+        events.pop().map(|event| event.kind)
+    }
+
+    #[test]
+    fn initialize_account_takes_the_owner_from_the_accounts_or_the_arguments() {
+        let keys = account_keys(TOKEN_PROGRAM);
+        let owner = Pubkey::new_unique();
+
+        for (instruction, expected_owner) in [
+            (TokenInstruction::InitializeAccount, keys[&2]),
+            (TokenInstruction::InitializeAccount2 { owner }, owner),
+            (TokenInstruction::InitializeAccount3 { owner }, owner),
+        ] {
+            let Some(AccountEventKind::InitializeAccount { account, mint, owner }) = parse(instruction.pack(), &keys)
+            else {
+                panic!("expected an initialize account event");
+            };
+            assert_eq!((account, mint, owner), (keys[&0], keys[&1], expected_owner));
+        }
+    }
+
+    #[test]
+    fn close_account() {
+        let keys = account_keys(TOKEN_PROGRAM);
+
+        let Some(AccountEventKind::CloseAccount { account, destination, owner }) =
+            parse(TokenInstruction::CloseAccount.pack(), &keys)
+        else {
+            panic!("expected a close account event");
+        };
+        assert_eq!((account, destination, owner), (keys[&0], keys[&1], keys[&2]));
+    }
+
+    #[test]
+    fn set_authority_with_and_without_a_new_authority() {
+        let keys = account_keys(TOKEN_PROGRAM);
+        let new_owner = Pubkey::new_unique();
+
+        for (authority_type, new_authority) in [
+            (AuthorityType::AccountOwner, COption::Some(new_owner)),
+            (AuthorityType::CloseAccount, COption::None),
+        ] {
+            let data = TokenInstruction::SetAuthority { authority_type: authority_type.clone(), new_authority }.pack();
+            let Some(AccountEventKind::SetAuthority {
+                account,
+                authority_type: decoded_authority_type,
+                current_authority,
+                new_authority: decoded_new_authority,
+            }) = parse(data, &keys)
+            else {
+                panic!("expected a set authority event");
+            };
+            assert_eq!((account, current_authority), (keys[&0], keys[&1]));
+            assert_eq!(decoded_authority_type, authority_type);
+            assert_eq!(decoded_new_authority, Option::from(new_authority));
+        }
+    }
+
+    #[test]
+    fn serializes_the_authority_type_by_name() {
+        let keys = account_keys(TOKEN_PROGRAM);
+        let data = TokenInstruction::SetAuthority {
+            authority_type: AuthorityType::MintTokens,
+            new_authority: COption::None,
+        }
+        .pack();
+        let event = parse(data, &keys).unwrap();
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "set_authority");
+        assert_eq!(json["authority_type"], "MintTokens");
+        assert_eq!(json["new_authority"], serde_json::Value::Null);
+        let AccountEventKind::SetAuthority { authority_type, .. } = serde_json::from_value(json).unwrap() else {
+            panic!("expected a set authority event");
+        };
+        assert_eq!(authority_type, AuthorityType::MintTokens);
+    }
+
+    #[test]
+    fn create_associated_token_account_names_the_wallet_and_mint() {
+        let keys = account_keys(ASSOCIATED_TOKEN_PROGRAM);
+
+        // `Create` predates the instruction enum and may come without data.
+        for (data, expected_idempotent) in [(vec![], false), (vec![0], false), (vec![1], true)] {
+            let Some(AccountEventKind::CreateAssociatedTokenAccount { funder, account, wallet, mint, idempotent }) =
+                parse(data, &keys)
+            else {
+                panic!("expected a create associated token account event");
+            };
+            assert_eq!((funder, account, wallet, mint), (keys[&0], keys[&1], keys[&2], keys[&3]));
+            assert_eq!(idempotent, expected_idempotent);
+        }
+
+        // RecoverNested
+        assert!(parse(vec![2], &keys).is_none());
+    }
+
+    #[test]
+    fn ignores_token_instructions_without_an_account_event() {
+        let keys = account_keys(TOKEN_PROGRAM);
+        assert!(parse(TokenInstruction::Transfer { amount: 1 }.pack(), &keys).is_none());
+        // Too few accounts for a close.
+        let mut keys = keys;
+        keys.remove(&2);
+        assert!(parse(TokenInstruction::CloseAccount.pack(), &keys).is_none());
+    }
+}
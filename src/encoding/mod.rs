@@ -0,0 +1,711 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde_json::Value;
+use solana_sdk::bs58;
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::v0::MessageAddressTableLookup;
+use solana_sdk::message::{legacy, v0, MessageHeader, VersionedMessage};
+use solana_sdk::program_option::COption;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::system_instruction::SystemInstruction;
+use solana_sdk::transaction::VersionedTransaction;
+use solana_transaction_status_client_types::option_serializer::OptionSerializer;
+use solana_transaction_status_client_types::ParsedAccountSource;
+use solana_transaction_status_client_types::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, TransactionStatusMeta, UiAddressTableLookup,
+    UiCompiledInstruction, UiInnerInstructions, UiInstruction, UiLoadedAddresses, UiMessage,
+    UiParsedInstruction, UiTransactionStatusMeta,
+};
+use spl_token::instruction::{AuthorityType, TokenInstruction};
+use crate::tx_parser::{self, FlatInstruction, KnownInstruction};
+use crate::{ASSOCIATED_TOKEN_PROGRAM, SYSTEM_PROGRAM, TOKEN_PROGRAM};
+
+/// A transaction in the shape the parsers expect, whatever encoding it was fetched in. Inner
+/// instructions in `meta` are `UiInstruction::Compiled`, except those whose program is not in the
+/// transaction, which are left as they were so the flattening skips them without moving the
+/// ones after. `meta.loaded_addresses` is filled in for `jsonParsed` responses whose account
+/// list already included them.
+#[derive(Debug, Clone)]
+pub struct DecodedTransaction {
+    pub signatures: Vec<Signature>,
+    pub message: VersionedMessage,
+    pub meta: UiTransactionStatusMeta,
+    /// The RPC's decoding of the `jsonParsed` instructions, by instruction and inner
+    /// instruction index. Their compiled form in `message` or `meta` has no data.
+    pub parsed_instructions: HashMap<(u8, Option<u8>), KnownInstruction<'static>>,
+}
+
+impl DecodedTransaction {
+    /// `tx_parser::flatten_instructions` with the RPC's decoding attached where it has one.
+    pub fn instructions(&self) -> Vec<FlatInstruction> {
+        let mut instructions =
+            tx_parser::flatten_instructions(self.message.instructions(), &self.meta.inner_instructions);
+        for instruction in instructions.iter_mut() {
+            let position = (instruction.instruction_index, instruction.inner_instruction_index);
+            instruction.parsed = self.parsed_instructions.get(&position).cloned();
+        }
+        instructions
+    }
+}
+
+/// Accepts `Base58`, `Base64`, `Json` and `JsonParsed` responses.
+pub fn decode_transaction(tx: &EncodedTransactionWithStatusMeta) -> Option<DecodedTransaction> {
+    let mut meta = tx.meta.clone()?;
+    let mut parsed_instructions = HashMap::new();
+
+    let (signatures, message) = match &tx.transaction {
+        EncodedTransaction::Json(ui_transaction) => {
+            let signatures = ui_transaction
+                .signatures
+                .iter()
+                .map(|signature| Signature::from_str(signature).ok())
+                .collect::<Option<Vec<Signature>>>()?;
+            let message = match &ui_transaction.message {
+                UiMessage::Raw(raw_message) => {
+                    let instructions = raw_message
+                        .instructions
+                        .iter()
+                        .map(compile_ui_instruction)
+                        .collect::<Option<Vec<CompiledInstruction>>>()?;
+                    build_message(
+                        raw_message.header,
+                        parse_keys(&raw_message.account_keys)?,
+                        &raw_message.recent_blockhash,
+                        instructions,
+                        raw_message.address_table_lookups.as_ref(),
+                    )?
+                }
+                UiMessage::Parsed(parsed_message) => {
+                    let all_keys = parsed_message
+                        .account_keys
+                        .iter()
+                        .map(|account| Pubkey::from_str(&account.pubkey).ok())
+                        .collect::<Option<Vec<Pubkey>>>()?;
+                    let static_accounts = parsed_message
+                        .account_keys
+                        .iter()
+                        .filter(|account| account.source != Some(ParsedAccountSource::LookupTable))
+                        .collect::<Vec<_>>();
+                    let loaded_accounts = parsed_message
+                        .account_keys
+                        .iter()
+                        .filter(|account| account.source == Some(ParsedAccountSource::LookupTable));
+
+                    let mut loaded_addresses = UiLoadedAddresses::default();
+                    for account in loaded_accounts {
+                        match account.writable {
+                            true => loaded_addresses.writable.push(account.pubkey.clone()),
+                            false => loaded_addresses.readonly.push(account.pubkey.clone()),
+                        }
+                    }
+                    meta.loaded_addresses = OptionSerializer::Some(loaded_addresses);
+
+                    let header = MessageHeader {
+                        num_required_signatures: static_accounts
+                            .iter()
+                            .filter(|account| account.signer)
+                            .count() as u8,
+                        num_readonly_signed_accounts: static_accounts
+                            .iter()
+                            .filter(|account| account.signer && !account.writable)
+                            .count() as u8,
+                        num_readonly_unsigned_accounts: static_accounts
+                            .iter()
+                            .filter(|account| !account.signer && !account.writable)
+                            .count() as u8,
+                    };
+                    let mut instructions = vec![];
+                    for (instruction_index, instruction) in parsed_message.instructions.iter().enumerate() {
+                        let (compiled, parsed) = compile_instruction(instruction, &all_keys)?;
+                        if let Some(parsed) = parsed {
+                            parsed_instructions.insert((instruction_index as u8, None), parsed);
+                        }
+                        instructions.push(compiled);
+                    }
+                    build_message(
+                        header,
+                        all_keys[..static_accounts.len()].to_vec(),
+                        &parsed_message.recent_blockhash,
+                        instructions,
+                        parsed_message.address_table_lookups.as_ref(),
+                    )?
+                }
+            };
+            (signatures, message)
+        }
+        encoded => {
+            let transaction = encoded.decode()?;
+            (transaction.signatures, transaction.message)
+        }
+    };
+
+    if let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions {
+        let account_keys = all_account_keys(&message, &meta)?;
+        let mut compiled_inner_instructions = vec![];
+        for inner_instruction in inner_instructions.iter() {
+            let mut instructions = vec![];
+            for (position, instruction) in inner_instruction.instructions.iter().enumerate() {
+                let Some((compiled, parsed)) = compile_instruction(instruction, &account_keys) else {
+                    eprintln!("Error: skipping inner instruction whose program is not in the transaction");
+                    instructions.push(instruction.clone());
+                    continue;
+                };
+                if let Some(parsed) = parsed {
+                    parsed_instructions.insert((inner_instruction.index, Some(position as u8)), parsed);
+                }
+                let stack_height = instruction_stack_height(instruction);
+                instructions.push(UiInstruction::Compiled(UiCompiledInstruction::from(&compiled, stack_height)));
+            }
+            compiled_inner_instructions.push(UiInnerInstructions {
+                index: inner_instruction.index,
+                instructions,
+            });
+        }
+        meta.inner_instructions = OptionSerializer::Some(compiled_inner_instructions);
+    }
+
+    Some(DecodedTransaction {
+        signatures,
+        message,
+        meta,
+        parsed_instructions,
+    })
+}
+
+/// For transactions that never went through JSON-RPC, such as Geyser streams.
+pub fn decode_native_transaction(
+    transaction: VersionedTransaction,
+    meta: TransactionStatusMeta,
+) -> DecodedTransaction {
+    DecodedTransaction {
+        signatures: transaction.signatures,
+        message: transaction.message,
+        meta: meta.into(),
+        parsed_instructions: HashMap::new(),
+    }
+}
+
+fn parse_keys(keys: &[String]) -> Option<Vec<Pubkey>> {
+    keys.iter().map(|key| Pubkey::from_str(key).ok()).collect()
+}
+
+fn build_message(
+    header: MessageHeader,
+    account_keys: Vec<Pubkey>,
+    recent_blockhash: &str,
+    instructions: Vec<CompiledInstruction>,
+    address_table_lookups: Option<&Vec<UiAddressTableLookup>>,
+) -> Option<VersionedMessage> {
+    let recent_blockhash = Hash::from_str(recent_blockhash).ok()?;
+
+    // Legacy messages have no `addressTableLookups` field at all.
+    let Some(address_table_lookups) = address_table_lookups else {
+        return Some(VersionedMessage::Legacy(legacy::Message {
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+        }));
+    };
+
+    let address_table_lookups = address_table_lookups
+        .iter()
+        .map(|lookup| {
+            Some(MessageAddressTableLookup {
+                account_key: Pubkey::from_str(&lookup.account_key).ok()?,
+                writable_indexes: lookup.writable_indexes.clone(),
+                readonly_indexes: lookup.readonly_indexes.clone(),
+            })
+        })
+        .collect::<Option<Vec<MessageAddressTableLookup>>>()?;
+
+    Some(VersionedMessage::V0(v0::Message {
+        header,
+        account_keys,
+        recent_blockhash,
+        instructions,
+        address_table_lookups,
+    }))
+}
+
+fn all_account_keys(message: &VersionedMessage, meta: &UiTransactionStatusMeta) -> Option<Vec<Pubkey>> {
+    let mut account_keys = message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded_addresses) = &meta.loaded_addresses {
+        account_keys.extend(parse_keys(&loaded_addresses.writable)?);
+        account_keys.extend(parse_keys(&loaded_addresses.readonly)?);
+    }
+    Some(account_keys)
+}
+
+fn instruction_stack_height(instruction: &UiInstruction) -> Option<u32> {
+    match instruction {
+        UiInstruction::Compiled(compiled) => compiled.stack_height,
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => parsed.stack_height,
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(partially_decoded)) => {
+            partially_decoded.stack_height
+        }
+    }
+}
+
+fn compile_ui_instruction(instruction: &UiCompiledInstruction) -> Option<CompiledInstruction> {
+    Some(CompiledInstruction {
+        program_id_index: instruction.program_id_index,
+        accounts: instruction.accounts.clone(),
+        data: bs58::decode(&instruction.data).into_vec().ok()?,
+    })
+}
+
+fn key_index(account_keys: &[Pubkey], key: &Pubkey) -> Option<u8> {
+    account_keys.iter().position(|account_key| account_key == key).map(|index| index as u8)
+}
+
+/// Turns any form of `UiInstruction` into a `CompiledInstruction` against `account_keys`,
+/// along with the RPC's decoding when it parsed the instruction. Parsed instructions have no
+/// data, and those the parsers have no use for keep only their program, as do partially
+/// decoded ones that fail to compile, so the positions of the instructions around them are
+/// unchanged. `None` if the program is not in `account_keys`.
+fn compile_instruction(
+    instruction: &UiInstruction,
+    account_keys: &[Pubkey],
+) -> Option<(CompiledInstruction, Option<KnownInstruction<'static>>)> {
+    let program_only = |program_id_index: u8| CompiledInstruction {
+        program_id_index,
+        accounts: vec![],
+        data: vec![],
+    };
+
+    match instruction {
+        UiInstruction::Compiled(compiled) => Some((compile_ui_instruction(compiled)?, None)),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(partially_decoded)) => {
+            let program_id_index = key_index(account_keys, &Pubkey::from_str(&partially_decoded.program_id).ok()?)?;
+            let compiled = partially_decoded
+                .accounts
+                .iter()
+                .map(|account| key_index(account_keys, &Pubkey::from_str(account).ok()?))
+                .collect::<Option<Vec<u8>>>()
+                .zip(bs58::decode(&partially_decoded.data).into_vec().ok())
+                .map(|(accounts, data)| CompiledInstruction {
+                    program_id_index,
+                    accounts,
+                    data,
+                });
+            if compiled.is_none() {
+                eprintln!("Error: skipping {} instruction that failed to compile", partially_decoded.program_id);
+            }
+            Some((compiled.unwrap_or_else(|| program_only(program_id_index)), None))
+        }
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(parsed)) => {
+            let program_id = Pubkey::from_str(&parsed.program_id).ok()?;
+            let program_id_index = key_index(account_keys, &program_id)?;
+            let known = known_from_parsed(&program_id, &parsed.parsed).and_then(|(known, accounts)| {
+                let accounts = accounts
+                    .iter()
+                    .map(|account| key_index(account_keys, account))
+                    .collect::<Option<Vec<u8>>>()?;
+                Some((known, accounts))
+            });
+            Some(match known {
+                Some((known, accounts)) => (
+                    CompiledInstruction {
+                        program_id_index,
+                        accounts,
+                        data: vec![],
+                    },
+                    Some(known),
+                ),
+                None => (program_only(program_id_index), None),
+            })
+        }
+    }
+}
+
+fn info_key(info: &Value, field: &str) -> Option<Pubkey> {
+    Pubkey::from_str(info.get(field)?.as_str()?).ok()
+}
+
+fn info_amount(info: &Value, field: &str) -> Option<u64> {
+    match info.get(field)? {
+        Value::String(amount) => amount.parse().ok(),
+        amount => amount.as_u64(),
+    }
+}
+
+fn info_token_amount(info: &Value) -> Option<(u64, u8)> {
+    let token_amount = info.get("tokenAmount")?;
+    Some((
+        info_amount(token_amount, "amount")?,
+        token_amount.get("decimals")?.as_u64()? as u8,
+    ))
+}
+
+/// The token, system and associated token account instructions the RPC parsed for
+/// `jsonParsed` responses, with the accounts they name in the order the program reads them.
+fn known_from_parsed(program_id: &Pubkey, parsed: &Value) -> Option<(KnownInstruction<'static>, Vec<Pubkey>)> {
+    let instruction_type = parsed.get("type")?.as_str()?;
+    let info = parsed.get("info")?;
+    let key = |field: &str| info_key(info, field);
+    // Multisig authorities are reported under their own field name.
+    let authority = |field: &str, multisig_field: &str| key(field).or_else(|| key(multisig_field));
+
+    if *program_id == Pubkey::from_str(TOKEN_PROGRAM).unwrap() {
+        let (instruction, accounts) = match instruction_type {
+            "transfer" => (
+                TokenInstruction::Transfer { amount: info_amount(info, "amount")? },
+                vec![key("source")?, key("destination")?, authority("authority", "multisigAuthority")?],
+            ),
+            "transferChecked" => {
+                let (amount, decimals) = info_token_amount(info)?;
+                (
+                    TokenInstruction::TransferChecked { amount, decimals },
+                    vec![
+                        key("source")?,
+                        key("mint")?,
+                        key("destination")?,
+                        authority("authority", "multisigAuthority")?,
+                    ],
+                )
+            }
+            "initializeAccount" => (
+                TokenInstruction::InitializeAccount,
+                vec![key("account")?, key("mint")?, key("owner")?],
+            ),
+            "initializeAccount2" => (
+                TokenInstruction::InitializeAccount2 { owner: key("owner")? },
+                vec![key("account")?, key("mint")?],
+            ),
+            "initializeAccount3" => (
+                TokenInstruction::InitializeAccount3 { owner: key("owner")? },
+                vec![key("account")?, key("mint")?],
+            ),
+            "closeAccount" => (
+                TokenInstruction::CloseAccount,
+                vec![key("account")?, key("destination")?, authority("owner", "multisigOwner")?],
+            ),
+            "setAuthority" => {
+                let authority_type = match info.get("authorityType")?.as_str()? {
+                    "mintTokens" => AuthorityType::MintTokens,
+                    "freezeAccount" => AuthorityType::FreezeAccount,
+                    "accountOwner" => AuthorityType::AccountOwner,
+                    "closeAccount" => AuthorityType::CloseAccount,
+                    _ => return None,
+                };
+                (
+                    TokenInstruction::SetAuthority {
+                        authority_type,
+                        new_authority: key("newAuthority").map_or(COption::None, COption::Some),
+                    },
+                    vec![
+                        key("account").or_else(|| key("mint"))?,
+                        authority("authority", "multisigAuthority")?,
+                    ],
+                )
+            }
+            "approve" => (
+                TokenInstruction::Approve { amount: info_amount(info, "amount")? },
+                vec![key("source")?, key("delegate")?, authority("owner", "multisigOwner")?],
+            ),
+            "approveChecked" => {
+                let (amount, decimals) = info_token_amount(info)?;
+                (
+                    TokenInstruction::ApproveChecked { amount, decimals },
+                    vec![key("source")?, key("mint")?, key("delegate")?, authority("owner", "multisigOwner")?],
+                )
+            }
+            "revoke" => (
+                TokenInstruction::Revoke,
+                vec![key("source")?, authority("owner", "multisigOwner")?],
+            ),
+            "freezeAccount" => (
+                TokenInstruction::FreezeAccount,
+                vec![
+                    key("account")?,
+                    key("mint")?,
+                    authority("freezeAuthority", "multisigFreezeAuthority")?,
+                ],
+            ),
+            "thawAccount" => (
+                TokenInstruction::ThawAccount,
+                vec![
+                    key("account")?,
+                    key("mint")?,
+                    authority("freezeAuthority", "multisigFreezeAuthority")?,
+                ],
+            ),
+            "mintTo" => (
+                TokenInstruction::MintTo { amount: info_amount(info, "amount")? },
+                vec![key("mint")?, key("account")?, authority("mintAuthority", "multisigMintAuthority")?],
+            ),
+            "burn" => (
+                TokenInstruction::Burn { amount: info_amount(info, "amount")? },
+                vec![key("account")?, key("mint")?, authority("authority", "multisigAuthority")?],
+            ),
+            "syncNative" => (TokenInstruction::SyncNative, vec![key("account")?]),
+            _ => return None,
+        };
+        return Some((KnownInstruction::Token(instruction), accounts));
+    }
+
+    if *program_id == Pubkey::from_str(SYSTEM_PROGRAM).unwrap() {
+        let (instruction, accounts) = match instruction_type {
+            "createAccount" => (
+                SystemInstruction::CreateAccount {
+                    lamports: info_amount(info, "lamports")?,
+                    space: info_amount(info, "space")?,
+                    owner: key("owner")?,
+                },
+                vec![key("source")?, key("newAccount")?],
+            ),
+            "transfer" => (
+                SystemInstruction::Transfer { lamports: info_amount(info, "lamports")? },
+                vec![key("source")?, key("destination")?],
+            ),
+            "assign" => (SystemInstruction::Assign { owner: key("owner")? }, vec![key("account")?]),
+            "allocate" => (
+                SystemInstruction::Allocate { space: info_amount(info, "space")? },
+                vec![key("account")?],
+            ),
+            _ => return None,
+        };
+        return Some((KnownInstruction::System(instruction), accounts));
+    }
+
+    if *program_id == Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM).unwrap() {
+        let idempotent = match instruction_type {
+            "create" => false,
+            "createIdempotent" => true,
+            _ => return None,
+        };
+        let accounts = vec![
+            key("source")?,
+            key("account")?,
+            key("wallet")?,
+            key("mint")?,
+            key("systemProgram")?,
+            key("tokenProgram")?,
+        ];
+        return Some((KnownInstruction::AssociatedToken { idempotent }, accounts));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use serde_json::json;
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+    use solana_sdk::message::Message;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+    use crate::{offline, pipeline};
+    use super::*;
+
+    fn token_balance(account_index: usize, mint: &Pubkey, owner: &Pubkey, amount: u64) -> Value {
+        json!({
+            "accountIndex": account_index,
+            "mint": mint.to_string(),
+            "owner": owner.to_string(),
+            "programId": TOKEN_PROGRAM,
+            "uiTokenAmount": {
+                "amount": amount.to_string(),
+                "decimals": 6,
+                "uiAmount": amount as f64 / 1e6,
+                "uiAmountString": (amount as f64 / 1e6).to_string(),
+            },
+        })
+    }
+
+    #[test]
+    fn json_parsed_instructions_parse_like_raw_ones() {
+        let (payer, wallet, recipient, mint) =
+            (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (source, destination, new_account) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let program = Pubkey::new_unique();
+        let (token_program, system_program, ata_program) = (
+            Pubkey::from_str(TOKEN_PROGRAM).unwrap(),
+            Pubkey::from_str(SYSTEM_PROGRAM).unwrap(),
+            Pubkey::from_str(ASSOCIATED_TOKEN_PROGRAM).unwrap(),
+        );
+
+        let create_idempotent = Instruction::new_with_bytes(
+            ata_program,
+            &[1],
+            vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(new_account, false),
+                AccountMeta::new_readonly(wallet, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(system_program, false),
+                AccountMeta::new_readonly(token_program, false),
+            ],
+        );
+        let swap = Instruction::new_with_bytes(
+            program,
+            &[7],
+            vec![
+                AccountMeta::new(source, false),
+                AccountMeta::new(destination, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(token_program, false),
+            ],
+        );
+        let sol_transfer = system_instruction::transfer(&payer, &recipient, 1_000);
+        let message = Message::new(&[create_idempotent, swap, sol_transfer], Some(&payer));
+        let keys = message.account_keys.clone();
+        let index = |key: &Pubkey| keys.iter().position(|k| k == key).unwrap();
+        let transaction = Transaction::new_unsigned(message);
+
+        let transfer_checked =
+            spl_token::instruction::transfer_checked(&token_program, &source, &mint, &destination, &payer, &[], 2_500_000, 6)
+                .unwrap();
+        let transfer =
+            spl_token::instruction::transfer(&token_program, &source, &destination, &payer, &[], 1_000_000).unwrap();
+        let compiled = |instruction: &Instruction| {
+            json!({
+                "programIdIndex": index(&instruction.program_id),
+                "accounts": instruction.accounts.iter().map(|account| index(&account.pubkey)).collect::<Vec<_>>(),
+                "data": bs58::encode(&instruction.data).into_string(),
+                "stackHeight": 2,
+            })
+        };
+        // A self-CPI, which fails to compile in the jsonParsed form below.
+        let log = Instruction::new_with_bytes(program, &[9], vec![AccountMeta::new_readonly(source, false)]);
+
+        let meta = |inner_instructions: Value| {
+            json!({
+                "err": null,
+                "status": {"Ok": null},
+                "fee": 5000,
+                "preBalances": keys.iter().map(|key| if *key == payer { 1_000_000_000u64 } else { 1 }).collect::<Vec<_>>(),
+                "postBalances": keys.iter().map(|key| match key {
+                    key if *key == payer => 999_993_000u64,
+                    key if *key == recipient => 1_001,
+                    _ => 1,
+                }).collect::<Vec<_>>(),
+                "innerInstructions": inner_instructions,
+                "logMessages": [],
+                "preTokenBalances": [
+                    token_balance(index(&source), &mint, &payer, 5_000_000),
+                    token_balance(index(&destination), &mint, &wallet, 0),
+                ],
+                "postTokenBalances": [
+                    token_balance(index(&source), &mint, &payer, 1_500_000),
+                    token_balance(index(&destination), &mint, &wallet, 3_500_000),
+                ],
+                "rewards": [],
+                "loadedAddresses": {"writable": [], "readonly": []},
+            })
+        };
+        let envelope = |transaction: Value, meta: Value| {
+            json!({"slot": 1, "blockTime": null, "transaction": transaction, "meta": meta}).to_string()
+        };
+
+        let raw = envelope(
+            json!([
+                base64::engine::general_purpose::STANDARD.encode(bincode::serialize(&transaction).unwrap()),
+                "base64",
+            ]),
+            meta(json!([{"index": 1, "instructions": [
+                compiled(&log),
+                compiled(&transfer_checked),
+                compiled(&log),
+                compiled(&transfer),
+            ]}])),
+        );
+
+        let parsed_instruction = |program: &str, program_id: &Pubkey, instruction_type: &str, info: Value| {
+            json!({
+                "program": program,
+                "programId": program_id.to_string(),
+                "parsed": {"type": instruction_type, "info": info},
+                "stackHeight": null,
+            })
+        };
+        let account_keys: Vec<Value> = keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                json!({
+                    "pubkey": key.to_string(),
+                    "writable": transaction.message.is_maybe_writable(i, None),
+                    "signer": transaction.message.is_signer(i),
+                    "source": "transaction",
+                })
+            })
+            .collect();
+        let json_parsed = envelope(
+            json!({
+                "signatures": [transaction.signatures[0].to_string()],
+                "message": {
+                    "accountKeys": account_keys,
+                    "recentBlockhash": transaction.message.recent_blockhash.to_string(),
+                    "instructions": [
+                        parsed_instruction("spl-associated-token-account", &ata_program, "createIdempotent", json!({
+                            "source": payer.to_string(),
+                            "account": new_account.to_string(),
+                            "wallet": wallet.to_string(),
+                            "mint": mint.to_string(),
+                            "systemProgram": system_program.to_string(),
+                            "tokenProgram": token_program.to_string(),
+                        })),
+                        {
+                            "programId": program.to_string(),
+                            "accounts": [source.to_string(), destination.to_string(), mint.to_string(), token_program.to_string()],
+                            "data": bs58::encode([7]).into_string(),
+                            "stackHeight": null,
+                        },
+                        parsed_instruction("system", &system_program, "transfer", json!({
+                            "source": payer.to_string(),
+                            "destination": recipient.to_string(),
+                            "lamports": 1_000,
+                        })),
+                    ],
+                },
+            }),
+            meta(json!([{"index": 1, "instructions": [
+                // A program missing from the account keys, which a node should never return.
+                {
+                    "programId": Pubkey::new_unique().to_string(),
+                    "accounts": [source.to_string()],
+                    "data": bs58::encode([9]).into_string(),
+                    "stackHeight": 2,
+                },
+                parsed_instruction("spl-token", &token_program, "transferChecked", json!({
+                    "source": source.to_string(),
+                    "mint": mint.to_string(),
+                    "destination": destination.to_string(),
+                    "authority": payer.to_string(),
+                    "tokenAmount": {"amount": "2500000", "decimals": 6, "uiAmount": 2.5, "uiAmountString": "2.5"},
+                })),
+                {
+                    "programId": program.to_string(),
+                    "accounts": [Pubkey::new_unique().to_string()],
+                    "data": bs58::encode([9]).into_string(),
+                    "stackHeight": 2,
+                },
+                parsed_instruction("spl-token", &token_program, "transfer", json!({
+                    "source": source.to_string(),
+                    "destination": destination.to_string(),
+                    "authority": payer.to_string(),
+                    "amount": "1000000",
+                })),
+            ]}])),
+        );
+
+        let parse = |json: &str| {
+            let txs = offline::transactions_from_json(json).unwrap();
+            pipeline::parse_confirmed_transaction(&txs[0], None).unwrap()
+        };
+        let raw = parse(&raw);
+        let json_parsed = parse(&json_parsed);
+
+        assert_eq!(serde_json::to_value(&json_parsed).unwrap(), serde_json::to_value(&raw).unwrap());
+        // The instructions that failed to compile keep their positions.
+        let inner_indexes: Vec<Option<u8>> = json_parsed.transfers.iter().map(|transfer| transfer.inner_instruction_index).collect();
+        assert_eq!(inner_indexes, [Some(1), Some(3)]);
+        assert_eq!(json_parsed.account_events.len(), 1);
+        assert!(json_parsed.balance_changes.iter().any(|change| change.account == recipient && change.change() == 1_000));
+    }
+}
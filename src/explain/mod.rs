@@ -0,0 +1,521 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::str::FromStr;
+use chrono::DateTime;
+use solana_sdk::hash::hashv;
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status_client_types::EncodedConfirmedTransactionWithStatusMeta;
+use crate::encoding::{self, DecodedTransaction};
+use crate::instruction_tree::{InstructionNode, InstructionTree};
+use crate::lookup_table::AddressLookupTableResolver;
+use crate::pipeline::{self, ParsedTransaction};
+use crate::pump_parser::PumpEventKind;
+use crate::tx_parser::{self, KnownInstruction, ANCHOR_EVENT_IX_TAG};
+use crate::{
+    ASSOCIATED_TOKEN_PROGRAM, METEORA_DLMM, METEORA_POOLS, PUMP_FUN, PUMP_SWAP, RAYDIUM_CLMM, RAYDIUM_CPMM,
+    RAYDIUM_V4, SYSTEM_PROGRAM, TOKEN_PROGRAM, VOTE_PROGRAM,
+};
+#[cfg(feature = "rpc")]
+use crate::rpc::ResilientRpcClient;
+
+const COMPUTE_BUDGET_PROGRAM: &str = "ComputeBudget111111111111111111111111111111";
+const MEMO_PROGRAM: &str = "MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr";
+#[cfg(feature = "rpc")]
+const METADATA_PROGRAM: &str = "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s";
+
+/// Mints whose symbol is known without fetching their metadata.
+const KNOWN_SYMBOLS: [(&str, &str); 2] = [
+    ("So11111111111111111111111111111111111111112", "WSOL"),
+    ("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v", "USDC"),
+];
+
+/// Instructions of the supported Anchor programs. Anchor prefixes instruction data with the
+/// first 8 bytes of `sha256("global:<name>")`, so the name does not depend on the program.
+const ANCHOR_INSTRUCTIONS: [&str; 24] = [
+    "add_liquidity",
+    "add_liquidity_by_strategy",
+    "buy",
+    "claim_fee",
+    "close_position",
+    "create",
+    "create_pool",
+    "decrease_liquidity_v2",
+    "deposit",
+    "increase_liquidity_v2",
+    "initialize",
+    "initialize_lb_pair",
+    "initialize_permissionless_pool",
+    "initialize_position",
+    "migrate",
+    "open_position_v2",
+    "remove_liquidity",
+    "remove_liquidity_by_range",
+    "sell",
+    "swap",
+    "swap_base_input",
+    "swap_base_output",
+    "swap_v2",
+    "withdraw",
+];
+
+/// A transaction laid out for reading rather than for machines: signers, the instruction tree
+/// with program and instruction names, transfers and the net balance change of every wallet.
+/// Meant for debugging the parsers, the layout is not stable.
+pub struct Explanation {
+    transaction: DecodedTransaction,
+    parsed: ParsedTransaction,
+    instruction_tree: InstructionTree,
+    /// Token symbols by mint, mints without one are shown by address. Starts with wrapped SOL,
+    /// USDC and tokens the transaction created on Pump.fun.
+    pub symbols: HashMap<String, String>,
+}
+
+impl Explanation {
+    /// `None` if the transaction has no meta or fails to decode.
+    pub fn new(
+        tx: &EncodedConfirmedTransactionWithStatusMeta,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+    ) -> Option<Self> {
+        let transaction = encoding::decode_transaction(&tx.transaction)?;
+        let mut parsed = pipeline::parse_decoded_transaction(&transaction, lookup_table_resolver);
+        parsed.slot = Some(tx.slot);
+        parsed.block_time = tx.block_time;
+
+        let account_keys =
+            tx_parser::get_all_account_keys(Some(&transaction.meta), &transaction.message, lookup_table_resolver);
+        let instructions = transaction.instructions();
+        let instruction_tree = InstructionTree::build(&instructions, &account_keys);
+
+        let mut symbols: HashMap<String, String> = KNOWN_SYMBOLS
+            .iter()
+            .map(|(mint, symbol)| (mint.to_string(), symbol.to_string()))
+            .collect();
+        for pump_event in parsed.pump_events.iter() {
+            if let PumpEventKind::Create { mint, symbol, .. } = &pump_event.kind {
+                symbols.insert(mint.to_string(), symbol.clone());
+            }
+        }
+
+        Some(Explanation { transaction, parsed, instruction_tree, symbols })
+    }
+
+    /// Mints the transaction moved that have no symbol yet, for `fetch_symbols`.
+    pub fn unknown_mints(&self) -> Vec<Pubkey> {
+        let mints = self
+            .parsed
+            .transfers
+            .iter()
+            .map(|transfer| &transfer.token_balance_diff.mint)
+            .chain(self.parsed.balance_changes.iter().map(|balance_change| &balance_change.mint));
+        let mut unknown_mints: Vec<Pubkey> = vec![];
+        for mint in mints {
+            if self.symbols.contains_key(mint) {
+                continue;
+            }
+            // `"SOL"` for lamports does not parse.
+            if let Ok(mint) = Pubkey::from_str(mint) {
+                if !unknown_mints.contains(&mint) {
+                    unknown_mints.push(mint);
+                }
+            }
+        }
+        unknown_mints
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let meta = &self.transaction.meta;
+        writeln!(out, "Transaction {}", self.parsed.signature)?;
+        if let Some(slot) = self.parsed.slot {
+            writeln!(out, "Slot:       {}", slot)?;
+        }
+        if let Some(block_time) = self.parsed.block_time.and_then(|block_time| DateTime::from_timestamp(block_time, 0)) {
+            writeln!(out, "Block time: {}", block_time.format("%Y-%m-%d %H:%M:%S UTC"))?;
+        }
+        match &meta.err {
+            Some(err) => writeln!(out, "Status:     failed, {}", err)?,
+            None => writeln!(out, "Status:     success")?,
+        }
+        writeln!(out, "Fee:        {} SOL", format_amount(meta.fee as i128, 9))?;
+
+        let signer_count = self.transaction.message.header().num_required_signatures as usize;
+        let signers = self.transaction.message.static_account_keys().iter().take(signer_count);
+        for (position, signer) in signers.enumerate() {
+            match position {
+                0 => writeln!(out, "Fee payer:  {}", signer)?,
+                1 => writeln!(out, "Signers:    {}", signer)?,
+                _ => writeln!(out, "            {}", signer)?,
+            }
+        }
+
+        writeln!(out)?;
+        writeln!(out, "Instructions:")?;
+        for (node, ancestors) in self.instruction_tree.walk() {
+            let name = instruction_name(node).unwrap_or_else(|| "unknown".to_string());
+            writeln!(
+                out,
+                "{:indent$}{:<6} {}: {}",
+                "",
+                instruction_path(node.instruction_index, node.inner_instruction_index),
+                program_label(&node.program_id),
+                name,
+                indent = 2 + 2 * ancestors.len()
+            )?;
+        }
+
+        if !self.parsed.transfers.is_empty() {
+            writeln!(out)?;
+            writeln!(out, "Transfers:")?;
+            for transfer in self.parsed.transfers.iter() {
+                writeln!(
+                    out,
+                    "  {:<6} {} {}  {} -> {}",
+                    instruction_path(transfer.instruction_index, transfer.inner_instruction_index),
+                    transfer.token_balance_diff.token_amount,
+                    self.symbol(&transfer.token_balance_diff.mint),
+                    transfer.from_user_account,
+                    transfer.to_user_account
+                )?;
+            }
+        }
+
+        self.write_net_balance_changes(out)
+    }
+
+    /// One row per wallet and token, token accounts are summed under their owner.
+    fn write_net_balance_changes(&self, out: &mut impl Write) -> io::Result<()> {
+        // (wallet, mint, decimals, change)
+        let mut rows: Vec<(String, &str, u8, i128)> = vec![];
+        for balance_change in self.parsed.balance_changes.iter() {
+            let wallet = balance_change.owner.clone().unwrap_or_else(|| balance_change.account.to_string());
+            match rows.iter_mut().find(|(row_wallet, mint, _, _)| *row_wallet == wallet && *mint == balance_change.mint) {
+                Some(row) => row.3 += balance_change.change(),
+                None => rows.push((wallet, &balance_change.mint, balance_change.decimals, balance_change.change())),
+            }
+        }
+        rows.retain(|(_, _, _, change)| *change != 0);
+        if rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows: Vec<(String, &str, String)> = rows
+            .into_iter()
+            .map(|(wallet, mint, decimals, change)| {
+                let change = format_amount(change, decimals);
+                let change = if change.starts_with('-') { change } else { format!("+{}", change) };
+                (wallet, self.symbol(mint), change)
+            })
+            .collect();
+        let wallet_width = rows.iter().map(|(wallet, _, _)| wallet.len()).max().unwrap_or(0);
+        let token_width = rows.iter().map(|(_, token, _)| token.len()).max().unwrap_or(0).max("Token".len());
+        let change_width = rows.iter().map(|(_, _, change)| change.len()).max().unwrap_or(0).max("Change".len());
+
+        writeln!(out)?;
+        writeln!(out, "Net balance changes per wallet:")?;
+        writeln!(
+            out,
+            "  {:<wallet_width$}  {:<token_width$}  {:>change_width$}",
+            "Wallet", "Token", "Change"
+        )?;
+        for (wallet, token, change) in rows {
+            writeln!(out, "  {:<wallet_width$}  {:<token_width$}  {:>change_width$}", wallet, token, change)?;
+        }
+        Ok(())
+    }
+
+    fn symbol<'a>(&'a self, mint: &'a str) -> &'a str {
+        self.symbols.get(mint).map(String::as_str).unwrap_or(mint)
+    }
+}
+
+/// Symbols from the Metaplex metadata accounts of `mints`, mints without metadata are left out.
+#[cfg(feature = "rpc")]
+pub fn fetch_symbols(rpc_client: &ResilientRpcClient, mints: &[Pubkey]) -> io::Result<HashMap<String, String>> {
+    let metadata_program = Pubkey::from_str(METADATA_PROGRAM).unwrap();
+    let mut symbols = HashMap::new();
+    // getMultipleAccounts takes at most 100 addresses.
+    for mints in mints.chunks(100) {
+        let metadata_accounts: Vec<Pubkey> = mints
+            .iter()
+            .map(|mint| {
+                Pubkey::find_program_address(&[b"metadata", metadata_program.as_ref(), mint.as_ref()], &metadata_program).0
+            })
+            .collect();
+        let accounts = rpc_client
+            .get_multiple_accounts(&metadata_accounts)
+            .map_err(|e| io::Error::other(format!("Error fetching token metadata: {:?}", e)))?;
+        for (mint, account) in mints.iter().zip(accounts) {
+            if let Some(symbol) = account.and_then(|account| read_metadata_symbol(&account.data)) {
+                symbols.insert(mint.to_string(), symbol);
+            }
+        }
+    }
+    Ok(symbols)
+}
+
+/// Metadata accounts start with a key byte, the update authority and the mint, then the name
+/// and symbol as Borsh strings padded with NUL bytes.
+#[cfg(feature = "rpc")]
+fn read_metadata_symbol(data: &[u8]) -> Option<String> {
+    let read_string = |offset: usize| -> Option<(String, usize)> {
+        let length = u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+        let bytes = data.get(offset + 4..offset + 4 + length)?;
+        let string = String::from_utf8_lossy(bytes).trim_end_matches('\0').trim().to_string();
+        Some((string, offset + 4 + length))
+    };
+    let (_name, offset) = read_string(1 + 32 + 32)?;
+    let (symbol, _) = read_string(offset)?;
+    (!symbol.is_empty()).then_some(symbol)
+}
+
+/// `3` for a top level instruction, `3.1` for the second instruction it invoked, the same
+/// indexes the JSON output uses.
+fn instruction_path(instruction_index: u8, inner_instruction_index: Option<u8>) -> String {
+    match inner_instruction_index {
+        Some(inner_instruction_index) => format!("{}.{}", instruction_index, inner_instruction_index),
+        None => instruction_index.to_string(),
+    }
+}
+
+fn program_label(program_id: &Pubkey) -> String {
+    let label = match program_id.to_string().as_str() {
+        SYSTEM_PROGRAM => "System Program",
+        TOKEN_PROGRAM => "Token Program",
+        ASSOCIATED_TOKEN_PROGRAM => "Associated Token Program",
+        COMPUTE_BUDGET_PROGRAM => "Compute Budget Program",
+        MEMO_PROGRAM => "Memo Program",
+        VOTE_PROGRAM => "Vote Program",
+        RAYDIUM_V4 => "Raydium AMM v4",
+        RAYDIUM_CPMM => "Raydium CPMM",
+        RAYDIUM_CLMM => "Raydium CLMM",
+        METEORA_DLMM => "Meteora DLMM",
+        METEORA_POOLS => "Meteora Pools",
+        PUMP_FUN => "Pump.fun",
+        PUMP_SWAP => "PumpSwap",
+        _ => return program_id.to_string(),
+    };
+    label.to_string()
+}
+
+fn instruction_name(node: &InstructionNode) -> Option<String> {
+    let data = node.data.as_slice();
+    let name = match node.program_id.to_string().as_str() {
+        SYSTEM_PROGRAM | TOKEN_PROGRAM => {
+            return match node.known()? {
+                KnownInstruction::System(instruction) => Some(variant_name(&instruction)),
+                KnownInstruction::Token(instruction) => Some(variant_name(&instruction)),
+                KnownInstruction::AssociatedToken { .. } => None,
+            }
+        }
+        MEMO_PROGRAM => return Some(format!("{:?}", String::from_utf8_lossy(data))),
+        ASSOCIATED_TOKEN_PROGRAM => match node.known() {
+            Some(KnownInstruction::AssociatedToken { idempotent: false }) => "Create",
+            Some(KnownInstruction::AssociatedToken { idempotent: true }) => "CreateIdempotent",
+            _ if data.first() == Some(&2) => "RecoverNested",
+            _ => return None,
+        },
+        COMPUTE_BUDGET_PROGRAM => match data.first()? {
+            1 => "RequestHeapFrame",
+            2 => "SetComputeUnitLimit",
+            3 => "SetComputeUnitPrice",
+            4 => "SetLoadedAccountsDataSizeLimit",
+            _ => return None,
+        },
+        RAYDIUM_V4 => match data.first()? {
+            0 => "Initialize",
+            1 => "Initialize2",
+            3 => "Deposit",
+            4 => "Withdraw",
+            9 => "SwapBaseIn",
+            11 => "SwapBaseOut",
+            _ => return None,
+        },
+        _ => return anchor_instruction_name(data),
+    };
+    Some(name.to_string())
+}
+
+fn anchor_instruction_name(data: &[u8]) -> Option<String> {
+    let discriminator = data.get(..8)?;
+    if discriminator == ANCHOR_EVENT_IX_TAG {
+        return Some("Event".to_string());
+    }
+    let name = ANCHOR_INSTRUCTIONS
+        .iter()
+        .find(|name| &hashv(&[b"global:", name.as_bytes()]).to_bytes()[..8] == discriminator)?;
+    // swap_base_input -> SwapBaseInput, like the native programs' names.
+    Some(
+        name.split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map(|first| first.to_ascii_uppercase().to_string() + chars.as_str()).unwrap_or_default()
+            })
+            .collect(),
+    )
+}
+
+/// `Transfer` from `Transfer { lamports: 10 }`.
+fn variant_name(instruction: &impl std::fmt::Debug) -> String {
+    format!("{:?}", instruction).chars().take_while(|c| c.is_ascii_alphanumeric()).collect()
+}
+
+/// A raw amount in UI units, exact where `f64` would round. Decimals beyond what `u128` can
+/// scale by are shown in exponent notation instead.
+fn format_amount(amount: i128, decimals: u8) -> String {
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    let Some(scale) = 10u128.checked_pow(decimals as u32) else {
+        return format!("{}{}e-{}", sign, amount, decimals);
+    };
+    let fraction = format!("{:0width$}", amount % scale, width = decimals as usize);
+    let fraction = fraction[..decimals as usize].trim_end_matches('0');
+    if fraction.is_empty() {
+        format!("{}{}", sign, amount / scale)
+    } else {
+        format!("{}{}.{}", sign, amount / scale, fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use solana_sdk::hash::hashv;
+    use crate::offline;
+    use crate::tx_parser::ANCHOR_EVENT_IX_TAG;
+    use super::{anchor_instruction_name, format_amount, Explanation};
+
+    const SENDER: &str = "AWxggjuZRmWULwxwPeM6ZZxRtdDdekVq22mFRx2QbW7U";
+    const RECIPIENT: &str = "FciD4i2WPEYinnKaCzFZAPTUsRxTCpJM6FyQmezmkkoj";
+    const MINT: &str = "FqUwnBMN1shpeqKVm7W5fN73tvrjVr19TQFFgkoFFzhq";
+
+    /// A token transfer of 2.5 from `SENDER` to `RECIPIENT`, after setting a compute unit limit.
+    fn explanation() -> Explanation {
+        let token_balance = |account_index: u8, owner: &str, amount: &str, ui_amount: f64| {
+            json!({
+                "accountIndex": account_index,
+                "mint": MINT,
+                "owner": owner,
+                "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                "uiTokenAmount": { "amount": amount, "decimals": 6, "uiAmount": ui_amount, "uiAmountString": "" },
+            })
+        };
+        let response = json!({
+            "slot": 123,
+            "blockTime": 1700000000,
+            "transaction": {
+                "signatures": ["1111111111111111111111111111111111111111111111111111111111111111"],
+                "message": {
+                    "header": { "numRequiredSignatures": 1, "numReadonlySignedAccounts": 0, "numReadonlyUnsignedAccounts": 2 },
+                    "accountKeys": [
+                        SENDER,
+                        "3XyEru3CMmGyFogjqZLGR57ZfwBgVvzAea7CR62ZibPT",
+                        "QngDp1HjgnPaZfkvAJkTQNFzbiwEQJTG4WuVyD4aNcp",
+                        "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                        "ComputeBudget111111111111111111111111111111",
+                    ],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    "instructions": [
+                        // SetComputeUnitLimit { units: 200_000 }
+                        { "programIdIndex": 4, "accounts": [], "data": "K1FDJ7" },
+                        // Transfer { amount: 2_500_000 }
+                        { "programIdIndex": 3, "accounts": [1, 2, 0], "data": "3gFAscqFEt1m" },
+                    ],
+                },
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [1000000000, 2039280, 2039280, 1, 1],
+                "postBalances": [999995000, 2039280, 2039280, 1, 1],
+                "innerInstructions": [],
+                "preTokenBalances": [token_balance(1, SENDER, "5000000", 5.0), token_balance(2, RECIPIENT, "0", 0.0)],
+                "postTokenBalances": [
+                    token_balance(1, SENDER, "2500000", 2.5),
+                    token_balance(2, RECIPIENT, "2500000", 2.5),
+                ],
+                "loadedAddresses": { "writable": [], "readonly": [] },
+            },
+        });
+        let txs = offline::transactions_from_json(&response.to_string()).unwrap();
+        Explanation::new(&txs[0], None).unwrap()
+    }
+
+    #[test]
+    fn writes_the_instruction_tree_transfers_and_net_balance_changes() {
+        let mut explanation = explanation();
+        explanation.symbols.insert(MINT.to_string(), "TKN".to_string());
+        let mut out = vec![];
+        explanation.write(&mut out).unwrap();
+
+        let expected = format!(
+            "Transaction 1111111111111111111111111111111111111111111111111111111111111111
+Slot:       123
+Block time: 2023-11-14 22:13:20 UTC
+Status:     success
+Fee:        0.000005 SOL
+Fee payer:  {sender}
+
+Instructions:
+  0      Compute Budget Program: SetComputeUnitLimit
+  1      Token Program: Transfer
+
+Transfers:
+  1      2.5 TKN  {sender} -> {recipient}
+
+Net balance changes per wallet:
+  Wallet                                        Token     Change
+  {sender}  SOL    -0.000005
+  {sender}  TKN         -2.5
+  {recipient}  TKN         +2.5
+",
+            sender = SENDER,
+            recipient = RECIPIENT,
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn names_anchor_instructions_by_discriminator() {
+        let discriminator = |name: &str| hashv(&[b"global:", name.as_bytes()]).to_bytes()[..8].to_vec();
+
+        assert_eq!(anchor_instruction_name(&discriminator("swap_base_input")).as_deref(), Some("SwapBaseInput"));
+        assert_eq!(anchor_instruction_name(&[discriminator("buy"), vec![0; 16]].concat()).as_deref(), Some("Buy"));
+        assert_eq!(anchor_instruction_name(&ANCHOR_EVENT_IX_TAG).as_deref(), Some("Event"));
+        assert_eq!(anchor_instruction_name(&discriminator("not_an_instruction")), None);
+        assert_eq!(anchor_instruction_name(&[1, 2, 3]), None);
+    }
+
+    #[test]
+    fn formats_raw_amounts_exactly() {
+        assert_eq!(format_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_amount(-5_000, 9), "-0.000005");
+        assert_eq!(format_amount(100, 0), "100");
+        assert_eq!(format_amount(u64::MAX as i128, 9), "18446744073.709551615");
+        assert_eq!(format_amount(i128::MAX, 38), "1.70141183460469231731687303715884105727");
+        // 10^39 does not fit a u128.
+        assert_eq!(format_amount(-15, 39), "-15e-39");
+        assert_eq!(format_amount(1, u8::MAX), "1e-255");
+    }
+
+    /// Key, update authority and mint, then the name and symbol padded to their maximum length.
+    #[cfg(feature = "rpc")]
+    fn metadata(name: &str, symbol: &str) -> Vec<u8> {
+        let string = |value: &str, len: usize| {
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.resize(len, 0);
+            [(len as u32).to_le_bytes().as_slice(), &bytes].concat()
+        };
+        [vec![4], vec![7; 64], string(name, 32), string(symbol, 10), string("https://example.com", 200)].concat()
+    }
+
+    #[cfg(feature = "rpc")]
+    #[test]
+    fn reads_the_symbol_from_metadata_accounts() {
+        use super::read_metadata_symbol;
+
+        assert_eq!(read_metadata_symbol(&metadata("Coin", "COIN")).as_deref(), Some("COIN"));
+        assert_eq!(read_metadata_symbol(&metadata("Coin", " ")), None);
+        assert_eq!(read_metadata_symbol(&metadata("Coin", "COIN")[..100]), None);
+        assert_eq!(read_metadata_symbol(&[4; 10]), None);
+    }
+}
@@ -0,0 +1,165 @@
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+use arrow_array::{
+    ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMillisecondArray, UInt32Array, UInt64Array,
+    UInt8Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+use super::{SwapRow, TransferRow};
+
+pub(super) fn create_writer(file: File, schema: SchemaRef) -> io::Result<ArrowWriter<File>> {
+    let properties = WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .build();
+    ArrowWriter::try_new(file, schema, Some(properties)).map_err(io::Error::other)
+}
+
+/// Parquet has no seconds unit, block times are stored in milliseconds.
+fn block_time_type() -> DataType {
+    DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into()))
+}
+
+/// Columns shared by every row, ahead of the row's own.
+fn context_fields() -> Vec<Field> {
+    vec![
+        Field::new("signature", DataType::Utf8, false),
+        Field::new("slot", DataType::UInt64, true),
+        Field::new("block_time", block_time_type(), true),
+        Field::new("transaction_index", DataType::UInt64, true),
+    ]
+}
+
+fn strings<'a>(values: impl Iterator<Item = &'a str>) -> ArrayRef {
+    Arc::new(StringArray::from_iter_values(values))
+}
+
+pub(super) fn swap_schema() -> SchemaRef {
+    let mut fields = context_fields();
+    fields.extend([
+        Field::new("swap_index", DataType::UInt32, false),
+        Field::new("wallet", DataType::Utf8, false),
+        Field::new("token_in", DataType::Utf8, false),
+        Field::new("token_out", DataType::Utf8, false),
+        Field::new("amount_in", DataType::Float64, false),
+        Field::new("amount_out", DataType::Float64, false),
+        Field::new("raw_amount_in", DataType::UInt64, false),
+        Field::new("raw_amount_out", DataType::UInt64, false),
+    ]);
+    Arc::new(Schema::new(fields))
+}
+
+pub(super) fn swap_batch(rows: &[SwapRow]) -> Result<RecordBatch, ArrowError> {
+    let columns: Vec<ArrayRef> = vec![
+        strings(rows.iter().map(|row| row.signature.as_str())),
+        Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.slot))),
+        Arc::new(TimestampMillisecondArray::from_iter(rows.iter().map(|row| row.block_time.map(|block_time| block_time * 1000))).with_timezone("UTC")),
+        Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.transaction_index))),
+        Arc::new(UInt32Array::from_iter_values(rows.iter().map(|row| row.swap_index))),
+        strings(rows.iter().map(|row| row.wallet.as_str())),
+        strings(rows.iter().map(|row| row.token_in.as_str())),
+        strings(rows.iter().map(|row| row.token_out.as_str())),
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.amount_in))),
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.amount_out))),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|row| row.raw_amount_in))),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|row| row.raw_amount_out))),
+    ];
+    RecordBatch::try_new(swap_schema(), columns)
+}
+
+pub(super) fn transfer_schema() -> SchemaRef {
+    let mut fields = context_fields();
+    fields.extend([
+        Field::new("transfer_index", DataType::UInt32, false),
+        Field::new("caller_program_id", DataType::Utf8, true),
+        Field::new("outer_program_id", DataType::Utf8, true),
+        Field::new("mint", DataType::Utf8, false),
+        Field::new("decimals", DataType::UInt8, false),
+        Field::new("amount", DataType::Float64, false),
+        Field::new("raw_amount", DataType::UInt64, false),
+        Field::new("fee", DataType::Utf8, true),
+        Field::new("from_user_account", DataType::Utf8, false),
+        Field::new("from_token_account", DataType::Utf8, false),
+        Field::new("to_user_account", DataType::Utf8, false),
+        Field::new("to_token_account", DataType::Utf8, false),
+    ]);
+    Arc::new(Schema::new(fields))
+}
+
+pub(super) fn transfer_batch(rows: &[TransferRow]) -> Result<RecordBatch, ArrowError> {
+    let columns: Vec<ArrayRef> = vec![
+        strings(rows.iter().map(|row| row.signature.as_str())),
+        Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.slot))),
+        Arc::new(TimestampMillisecondArray::from_iter(rows.iter().map(|row| row.block_time.map(|block_time| block_time * 1000))).with_timezone("UTC")),
+        Arc::new(UInt64Array::from_iter(rows.iter().map(|row| row.transaction_index))),
+        Arc::new(UInt32Array::from_iter_values(rows.iter().map(|row| row.transfer_index))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|row| row.caller_program_id.as_deref()))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|row| row.outer_program_id.as_deref()))),
+        strings(rows.iter().map(|row| row.mint.as_str())),
+        Arc::new(UInt8Array::from_iter_values(rows.iter().map(|row| row.decimals))),
+        Arc::new(Float64Array::from_iter_values(rows.iter().map(|row| row.amount))),
+        Arc::new(UInt64Array::from_iter_values(rows.iter().map(|row| row.raw_amount))),
+        Arc::new(StringArray::from_iter(rows.iter().map(|row| row.fee.as_deref()))),
+        strings(rows.iter().map(|row| row.from_user_account.as_str())),
+        strings(rows.iter().map(|row| row.from_token_account.as_str())),
+        strings(rows.iter().map(|row| row.to_user_account.as_str())),
+        strings(rows.iter().map(|row| row.to_token_account.as_str())),
+    ];
+    RecordBatch::try_new(transfer_schema(), columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use arrow_array::{Float64Array, StringArray, TimestampMillisecondArray, UInt64Array, UInt8Array};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use super::super::tests::{export_dir, parsed};
+    use super::super::{ExportFormat, Exporter};
+    use super::{swap_schema, transfer_schema};
+
+    #[test]
+    fn parquet_files_read_back_with_the_schema_and_values() {
+        let dir = export_dir();
+        let mut exporter = Exporter::new(&dir, ExportFormat::Parquet, None).unwrap();
+        exporter.write(&parsed(7)).unwrap();
+        // Closes the files, later rows go into a new part.
+        exporter.flush().unwrap();
+        exporter.write(&parsed(8)).unwrap();
+        exporter.finish().unwrap();
+
+        let read = |name: &str| {
+            let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(dir.join(name)).unwrap()).unwrap();
+            let schema = reader.schema().clone();
+            let batches: Vec<_> = reader.build().unwrap().map(Result::unwrap).collect();
+            assert_eq!(batches.len(), 1);
+            (schema, batches.into_iter().next().unwrap())
+        };
+
+        let (schema, swaps) = read("swaps.parquet");
+        assert_eq!(schema.fields(), swap_schema().fields());
+        let column = |name: &str| swaps.column_by_name(name).unwrap().as_any();
+        let signatures = column("signature").downcast_ref::<StringArray>().unwrap();
+        assert_eq!(signatures.value(0), "sig7");
+        let block_times = column("block_time").downcast_ref::<TimestampMillisecondArray>().unwrap();
+        assert_eq!(block_times.value(0), 1_700_000_000_000);
+        assert_eq!(column("amount_in").downcast_ref::<Float64Array>().unwrap().value(0), 1.5);
+        assert_eq!(column("raw_amount_out").downcast_ref::<UInt64Array>().unwrap().value(0), 250_000_000);
+
+        let (schema, transfers) = read("transfers.parquet");
+        assert_eq!(schema.fields(), transfer_schema().fields());
+        let column = |name: &str| transfers.column_by_name(name).unwrap().as_any();
+        assert_eq!(column("decimals").downcast_ref::<UInt8Array>().unwrap().value(0), 6);
+        assert_eq!(
+            column("raw_amount").downcast_ref::<UInt64Array>().unwrap().value(0),
+            9_007_199_254_740_993,
+        );
+
+        let (_, swaps) = read("swaps-1.parquet");
+        let signatures = swaps.column_by_name("signature").unwrap().as_any().downcast_ref::<StringArray>().unwrap();
+        assert_eq!(signatures.value(0), "sig8");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
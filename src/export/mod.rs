@@ -0,0 +1,537 @@
+#[cfg(feature = "parquet")]
+mod columnar;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use chrono::{DateTime, SecondsFormat};
+use serde::{Serialize, Serializer};
+use crate::pipeline::ParsedTransaction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(ExportFormat::Csv),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(ExportFormat::Parquet),
+            #[cfg(not(feature = "parquet"))]
+            "parquet" => Err("built without the parquet feature".to_string()),
+            _ => Err(format!("unknown export format {}, expected csv or parquet", format)),
+        }
+    }
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// A swap flattened with its transaction's context.
+#[derive(Debug, Clone, Serialize)]
+pub struct SwapRow {
+    pub signature: String,
+    pub slot: Option<u64>,
+    #[serde(serialize_with = "serialize_block_time")]
+    pub block_time: Option<i64>,
+    pub transaction_index: Option<u64>,
+    /// Position of the swap within its transaction.
+    pub swap_index: u32,
+    pub wallet: String,
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: f64,
+    pub amount_out: f64,
+    pub raw_amount_in: u64,
+    pub raw_amount_out: u64,
+}
+
+impl SwapRow {
+    pub fn from_transaction(parsed: &ParsedTransaction) -> Vec<SwapRow> {
+        parsed
+            .swaps
+            .iter()
+            .enumerate()
+            .map(|(swap_index, swap)| SwapRow {
+                signature: parsed.signature.clone(),
+                slot: parsed.slot,
+                block_time: parsed.block_time,
+                transaction_index: parsed.transaction_index.map(|index| index as u64),
+                swap_index: swap_index as u32,
+                wallet: swap.wallet.clone(),
+                token_in: swap.token_in.clone(),
+                token_out: swap.token_out.clone(),
+                amount_in: swap.amount_in,
+                amount_out: swap.amount_out,
+                raw_amount_in: swap.raw_amount_in,
+                raw_amount_out: swap.raw_amount_out,
+            })
+            .collect()
+    }
+}
+
+/// A transfer flattened with its transaction's context.
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferRow {
+    pub signature: String,
+    pub slot: Option<u64>,
+    #[serde(serialize_with = "serialize_block_time")]
+    pub block_time: Option<i64>,
+    pub transaction_index: Option<u64>,
+    /// Position of the transfer within its transaction.
+    pub transfer_index: u32,
+    pub caller_program_id: Option<String>,
+    pub outer_program_id: Option<String>,
+    pub mint: String,
+    pub decimals: u8,
+    pub amount: f64,
+    pub raw_amount: u64,
+    /// `protocol` or `coin_creator` for a swap fee leg, already counted in the swap's fees.
+    pub fee: Option<String>,
+    pub from_user_account: String,
+    pub from_token_account: String,
+    pub to_user_account: String,
+    pub to_token_account: String,
+}
+
+impl TransferRow {
+    pub fn from_transaction(parsed: &ParsedTransaction) -> Vec<TransferRow> {
+        parsed
+            .transfers
+            .iter()
+            .enumerate()
+            .map(|(transfer_index, transfer)| {
+                let diff = &transfer.token_balance_diff;
+                TransferRow {
+                    signature: parsed.signature.clone(),
+                    slot: parsed.slot,
+                    block_time: parsed.block_time,
+                    transaction_index: parsed.transaction_index.map(|index| index as u64),
+                    transfer_index: transfer_index as u32,
+                    caller_program_id: transfer.caller_program_id.map(|program| program.to_string()),
+                    outer_program_id: transfer.outer_program_id.map(|program| program.to_string()),
+                    mint: diff.mint.clone(),
+                    decimals: diff.decimals,
+                    amount: diff.token_amount,
+                    raw_amount: diff.raw_amount,
+                    fee: transfer.fee.as_ref().map(|fee| fee.as_str().to_string()),
+                    from_user_account: transfer.from_user_account.to_string(),
+                    from_token_account: transfer.from_token_account.to_string(),
+                    to_user_account: transfer.to_user_account.to_string(),
+                    to_token_account: transfer.to_token_account.to_string(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// CSV has no timestamp type, block times are written as RFC 3339 so DuckDB and pandas detect
+/// them.
+fn serialize_block_time<S: Serializer>(block_time: &Option<i64>, serializer: S) -> Result<S::Ok, S::Error> {
+    let block_time = block_time
+        .and_then(|block_time| DateTime::from_timestamp(block_time, 0))
+        .map(|block_time| block_time.to_rfc3339_opts(SecondsFormat::Secs, true));
+    block_time.serialize(serializer)
+}
+
+trait ExportRow: Serialize + Sized {
+    const NAME: &'static str;
+
+    fn slot(&self) -> Option<u64>;
+
+    #[cfg(feature = "parquet")]
+    fn schema() -> arrow_schema::SchemaRef;
+
+    #[cfg(feature = "parquet")]
+    fn record_batch(rows: &[Self]) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError>;
+}
+
+impl ExportRow for SwapRow {
+    const NAME: &'static str = "swaps";
+
+    fn slot(&self) -> Option<u64> {
+        self.slot
+    }
+
+    #[cfg(feature = "parquet")]
+    fn schema() -> arrow_schema::SchemaRef {
+        columnar::swap_schema()
+    }
+
+    #[cfg(feature = "parquet")]
+    fn record_batch(rows: &[Self]) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError> {
+        columnar::swap_batch(rows)
+    }
+}
+
+impl ExportRow for TransferRow {
+    const NAME: &'static str = "transfers";
+
+    fn slot(&self) -> Option<u64> {
+        self.slot
+    }
+
+    #[cfg(feature = "parquet")]
+    fn schema() -> arrow_schema::SchemaRef {
+        columnar::transfer_schema()
+    }
+
+    #[cfg(feature = "parquet")]
+    fn record_batch(rows: &[Self]) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError> {
+        columnar::transfer_batch(rows)
+    }
+}
+
+enum TableFile {
+    Csv(csv::Writer<File>),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::arrow::ArrowWriter<File>),
+}
+
+/// Buffers rows of one kind and writes them out a batch at a time, into one file per slot range.
+struct Table<R: ExportRow> {
+    rows: Vec<R>,
+    /// First slot of the range the buffered rows and the open file belong to, `None` when not
+    /// rotating or for rows without a slot.
+    range_start: Option<u64>,
+    file: Option<TableFile>,
+}
+
+impl<R: ExportRow> Table<R> {
+    fn new() -> Self {
+        Table {
+            rows: vec![],
+            range_start: None,
+            file: None,
+        }
+    }
+
+    fn push(&mut self, row: R, settings: &ExportSettings) -> io::Result<()> {
+        let range_start = settings
+            .rotate_slots
+            .zip(row.slot())
+            .map(|(rotate_slots, slot)| slot - slot % rotate_slots);
+        if range_start != self.range_start {
+            self.close(settings)?;
+            self.range_start = range_start;
+        }
+
+        self.rows.push(row);
+        if self.rows.len() >= settings.batch_size {
+            self.flush(settings)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self, settings: &ExportSettings) -> io::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => {
+                let path = settings.path(R::NAME, self.range_start);
+                let file = File::create_new(&path)?;
+                self.file.insert(match settings.format {
+                    ExportFormat::Csv => TableFile::Csv(csv::Writer::from_writer(file)),
+                    #[cfg(feature = "parquet")]
+                    ExportFormat::Parquet => TableFile::Parquet(columnar::create_writer(file, R::schema())?),
+                })
+            }
+        };
+
+        match file {
+            TableFile::Csv(writer) => {
+                for row in self.rows.iter() {
+                    writer.serialize(row)?;
+                }
+                writer.flush()?;
+            }
+            #[cfg(feature = "parquet")]
+            TableFile::Parquet(writer) => {
+                let batch = R::record_batch(&self.rows).map_err(io::Error::other)?;
+                writer.write(&batch)?;
+            }
+        }
+        self.rows.clear();
+        Ok(())
+    }
+
+    /// Writes out the buffered rows and closes the file if it is only readable once closed.
+    fn sync(&mut self, settings: &ExportSettings) -> io::Result<()> {
+        self.flush(settings)?;
+        match self.file {
+            #[cfg(feature = "parquet")]
+            Some(TableFile::Parquet(_)) => self.close(settings),
+            _ => Ok(()),
+        }
+    }
+
+    fn close(&mut self, settings: &ExportSettings) -> io::Result<()> {
+        self.flush(settings)?;
+        match self.file.take() {
+            Some(TableFile::Csv(mut writer)) => writer.flush(),
+            #[cfg(feature = "parquet")]
+            Some(TableFile::Parquet(writer)) => writer.close().map(|_| ()).map_err(io::Error::other),
+            None => Ok(()),
+        }
+    }
+}
+
+struct ExportSettings {
+    dir: PathBuf,
+    format: ExportFormat,
+    rotate_slots: Option<u64>,
+    batch_size: usize,
+}
+
+impl ExportSettings {
+    /// `swaps-000312000000-000312009999.csv` when rotating, `swaps.csv` otherwise. Existing files
+    /// are never overwritten, a resumed run writes the next free `-1`, `-2`... part instead.
+    fn path(&self, name: &str, range_start: Option<u64>) -> PathBuf {
+        let stem = match self.rotate_slots.zip(range_start) {
+            Some((rotate_slots, start)) => format!("{}-{:012}-{:012}", name, start, start + rotate_slots - 1),
+            None => name.to_string(),
+        };
+        let extension = self.format.extension();
+        let mut path = self.dir.join(format!("{}.{}", stem, extension));
+        let mut part = 0;
+        while path.exists() {
+            part += 1;
+            path = self.dir.join(format!("{}-{}.{}", stem, part, extension));
+        }
+        path
+    }
+}
+
+/// Writes the swaps and transfers of parsed transactions into `swaps` and `transfers` files,
+/// optionally rotated every `rotate_slots` slots. Rows are buffered `batch_size` at a time, only
+/// `finish` (or dropping the exporter) writes out the rest and completes the files.
+pub struct Exporter {
+    settings: ExportSettings,
+    swaps: Table<SwapRow>,
+    transfers: Table<TransferRow>,
+}
+
+impl Exporter {
+    pub fn new(dir: impl AsRef<Path>, format: ExportFormat, rotate_slots: Option<u64>) -> io::Result<Self> {
+        if rotate_slots == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "rotate_slots must be positive"));
+        }
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Exporter {
+            settings: ExportSettings {
+                dir: dir.as_ref().to_path_buf(),
+                format,
+                rotate_slots,
+                batch_size: 10_000,
+            },
+            swaps: Table::new(),
+            transfers: Table::new(),
+        })
+    }
+
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.settings.batch_size = batch_size.max(1);
+    }
+
+    pub fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+        for row in SwapRow::from_transaction(parsed) {
+            self.swaps.push(row, &self.settings)?;
+        }
+        for row in TransferRow::from_transaction(parsed) {
+            self.transfers.push(row, &self.settings)?;
+        }
+        Ok(())
+    }
+
+    /// Writes out every buffered row. A Parquet file has no footer until it is closed, so it is
+    /// closed here and later rows go into the next `-1`, `-2`... part.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.swaps.sync(&self.settings)?;
+        self.transfers.sync(&self.settings)
+    }
+
+    pub fn finish(&mut self) -> io::Result<()> {
+        self.swaps.close(&self.settings)?;
+        self.transfers.close(&self.settings)
+    }
+}
+
+impl Drop for Exporter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            eprintln!("Error finishing export: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use solana_sdk::pubkey::Pubkey;
+    use crate::pipeline::ParsedTransaction;
+    use crate::swap_parser::Swap;
+    use crate::tx_parser::{TokenBalanceDiff, Transfer};
+    use super::{ExportFormat, Exporter};
+
+    pub(super) fn export_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("solana-parser-export-{}", Pubkey::new_unique()))
+    }
+
+    /// A transaction with one swap and one transfer, its signature named after `slot`.
+    pub(super) fn parsed(slot: u64) -> ParsedTransaction {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        ParsedTransaction {
+            signature: format!("sig{}", slot),
+            slot: Some(slot),
+            block_time: Some(1_700_000_000),
+            transaction_index: Some(3),
+            transfers: vec![Transfer {
+                instruction_index: 1,
+                inner_instruction_index: Some(0),
+                instruction_program_id: None,
+                instruction_input_accounts: None,
+                caller_program_id: Some(key(1)),
+                outer_program_id: Some(key(1)),
+                token_balance_diff: TokenBalanceDiff {
+                    token_amount: 9_007_199_254.740993,
+                    raw_amount: 9_007_199_254_740_993,
+                    mint: "Mint".to_string(),
+                    decimals: 6,
+                },
+                fee: None,
+                to_user_account: key(2),
+                to_token_account: key(3),
+                from_user_account: key(4),
+                from_token_account: key(5),
+            }],
+            swaps: vec![Swap {
+                instruction_index: 1,
+                inner_instruction_index: None,
+                wallet: "wallet".to_string(),
+                pool: None,
+                token_in: "Mint".to_string(),
+                token_out: "SOL".to_string(),
+                amount_in: 1.5,
+                amount_out: 0.25,
+                raw_amount_in: 1_500_000,
+                raw_amount_out: 250_000_000,
+                fees: vec![],
+            }],
+            pool_creations: vec![],
+            pump_events: vec![],
+            account_events: vec![],
+            balance_changes: vec![],
+        }
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn writes_csv_with_a_header_and_a_line_per_row() {
+        let dir = export_dir();
+        let mut exporter = Exporter::new(&dir, ExportFormat::Csv, None).unwrap();
+        exporter.write(&parsed(7)).unwrap();
+        exporter.finish().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("swaps.csv")).unwrap(),
+            "signature,slot,block_time,transaction_index,swap_index,wallet,token_in,token_out,amount_in,\
+             amount_out,raw_amount_in,raw_amount_out\n\
+             sig7,7,2023-11-14T22:13:20Z,3,0,wallet,Mint,SOL,1.5,0.25,1500000,250000000\n",
+        );
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        assert_eq!(
+            fs::read_to_string(dir.join("transfers.csv")).unwrap(),
+            format!(
+                "signature,slot,block_time,transaction_index,transfer_index,caller_program_id,outer_program_id,\
+                 mint,decimals,amount,raw_amount,fee,from_user_account,from_token_account,to_user_account,\
+                 to_token_account\n\
+                 sig7,7,2023-11-14T22:13:20Z,3,0,{},{},Mint,6,9007199254.740993,9007199254740993,,{},{},{},{}\n",
+                key(1),
+                key(1),
+                key(4),
+                key(5),
+                key(2),
+                key(3),
+            ),
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_files_at_slot_range_boundaries() {
+        let dir = export_dir();
+        let mut exporter = Exporter::new(&dir, ExportFormat::Csv, Some(100)).unwrap();
+        for slot in [99, 100, 199, 250] {
+            exporter.write(&parsed(slot)).unwrap();
+        }
+        exporter.finish().unwrap();
+
+        let signatures = |name: &str| -> Vec<String> {
+            let mut reader = csv::Reader::from_path(dir.join(name)).unwrap();
+            reader.records().map(|record| record.unwrap()[0].to_string()).collect()
+        };
+        assert_eq!(
+            file_names(&dir),
+            [
+                "swaps-000000000000-000000000099.csv",
+                "swaps-000000000100-000000000199.csv",
+                "swaps-000000000200-000000000299.csv",
+                "transfers-000000000000-000000000099.csv",
+                "transfers-000000000100-000000000199.csv",
+                "transfers-000000000200-000000000299.csv",
+            ],
+        );
+        assert_eq!(signatures("swaps-000000000000-000000000099.csv"), ["sig99"]);
+        assert_eq!(signatures("swaps-000000000100-000000000199.csv"), ["sig100", "sig199"]);
+        assert_eq!(signatures("transfers-000000000200-000000000299.csv"), ["sig250"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_resumed_export_writes_the_next_part_instead_of_overwriting() {
+        let dir = export_dir();
+        for slot in [1, 2] {
+            let mut exporter = Exporter::new(&dir, ExportFormat::Csv, Some(100)).unwrap();
+            exporter.write(&parsed(slot)).unwrap();
+        }
+
+        assert_eq!(
+            file_names(&dir),
+            [
+                "swaps-000000000000-000000000099-1.csv",
+                "swaps-000000000000-000000000099.csv",
+                "transfers-000000000000-000000000099-1.csv",
+                "transfers-000000000000-000000000099.csv",
+            ],
+        );
+        let contents = fs::read_to_string(dir.join("swaps-000000000000-000000000099-1.csv")).unwrap();
+        assert!(contents.lines().nth(1).unwrap().starts_with("sig2,2,"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use futures::{SinkExt, StreamExt};
+use solana_sdk::commitment_config::{self, CommitmentConfig};
+use solana_sdk::signature::Signature;
+use yellowstone_grpc_client::{ClientTlsConfig, GeyserGrpcClient};
+use yellowstone_grpc_proto::convert_from;
+use yellowstone_grpc_proto::prelude::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::prelude::{
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterTransactions, SubscribeRequestPing,
+    SubscribeUpdateTransaction,
+};
+use crate::encoding;
+use crate::lookup_table::AddressLookupTableResolver;
+use crate::pipeline::{self, ParsedTransaction};
+use crate::stream::SeenSignatures;
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Which transactions the server sends, mirroring Yellowstone's transaction filter. An empty
+/// `account_include` means every transaction.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionFilter {
+    /// Any of these accounts must appear in the transaction.
+    pub account_include: Vec<String>,
+    /// None of these accounts may appear in the transaction.
+    pub account_exclude: Vec<String>,
+    /// All of these accounts must appear in the transaction.
+    pub account_required: Vec<String>,
+    pub include_failed: bool,
+    pub include_votes: bool,
+}
+
+impl TransactionFilter {
+    /// Whether `update` passes the vote and failed flags. The server applies them already, this
+    /// guards against providers that ignore them.
+    pub fn accepts(&self, update: &SubscribeUpdateTransaction) -> bool {
+        let Some(info) = update.transaction.as_ref() else {
+            return false;
+        };
+        let failed = info.meta.as_ref().is_some_and(|meta| meta.err.is_some());
+        (self.include_votes || !info.is_vote) && (self.include_failed || !failed)
+    }
+}
+
+/// Consumes a Yellowstone Geyser gRPC transaction subscription, parsing every update as it
+/// arrives and reconnecting with backoff when the stream ends. A reconnect resubscribes from the
+/// last slot seen, and the transactions of that slot already handed out are dropped.
+pub struct GrpcStream {
+    endpoint: String,
+    x_token: Option<String>,
+    pub filter: TransactionFilter,
+    pub commitment: CommitmentConfig,
+    last_slot: Option<u64>,
+    seen: SeenSignatures,
+}
+
+impl GrpcStream {
+    pub fn new(endpoint: &str, x_token: Option<String>, filter: TransactionFilter) -> Self {
+        GrpcStream {
+            endpoint: endpoint.to_string(),
+            x_token,
+            filter,
+            commitment: CommitmentConfig::confirmed(),
+            last_slot: None,
+            seen: SeenSignatures::default(),
+        }
+    }
+
+    fn request(&self) -> SubscribeRequest {
+        let commitment = match self.commitment.commitment {
+            commitment_config::CommitmentLevel::Processed => CommitmentLevel::Processed,
+            commitment_config::CommitmentLevel::Confirmed => CommitmentLevel::Confirmed,
+            commitment_config::CommitmentLevel::Finalized => CommitmentLevel::Finalized,
+        };
+
+        SubscribeRequest {
+            transactions: HashMap::from([(
+                "solana-parser".to_string(),
+                SubscribeRequestFilterTransactions {
+                    vote: (!self.filter.include_votes).then_some(false),
+                    failed: (!self.filter.include_failed).then_some(false),
+                    signature: None,
+                    account_include: self.filter.account_include.clone(),
+                    account_exclude: self.filter.account_exclude.clone(),
+                    account_required: self.filter.account_required.clone(),
+                },
+            )]),
+            commitment: Some(commitment as i32),
+            // Servers only keep a few recent slots, older ones fail the subscription.
+            from_slot: self.last_slot,
+            ..Default::default()
+        }
+    }
+
+    /// Runs until the process is stopped, handing each parsed transaction to `on_parsed`.
+    pub async fn run(
+        &mut self,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+        mut on_parsed: impl FnMut(&ParsedTransaction),
+    ) {
+        let mut reconnect_delay = Duration::from_secs(1);
+
+        loop {
+            match self.consume(lookup_table_resolver, &mut on_parsed, &mut reconnect_delay).await {
+                Ok(()) => eprintln!("Stream from {} ended, reconnecting", self.endpoint),
+                Err(e) => eprintln!("Error: {}, reconnecting in {:?}", e, reconnect_delay),
+            }
+            tokio::time::sleep(reconnect_delay).await;
+            reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    async fn consume(
+        &mut self,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+        on_parsed: &mut impl FnMut(&ParsedTransaction),
+        reconnect_delay: &mut Duration,
+    ) -> Result<(), String> {
+        let mut builder = GeyserGrpcClient::build_from_shared(self.endpoint.clone())
+            .map_err(|e| e.to_string())?
+            .x_token(self.x_token.clone())
+            .map_err(|e| e.to_string())?
+            .connect_timeout(Duration::from_secs(10));
+        if self.endpoint.starts_with("https") {
+            builder = builder
+                .tls_config(ClientTlsConfig::new().with_native_roots())
+                .map_err(|e| e.to_string())?;
+        }
+        let mut client = builder.connect().await.map_err(|e| e.to_string())?;
+        let (mut requests, mut updates) = client
+            .subscribe_with_request(Some(self.request()))
+            .await
+            .map_err(|e| e.to_string())?;
+        *reconnect_delay = Duration::from_secs(1);
+
+        while let Some(update) = updates.next().await {
+            match update.map_err(|e| e.to_string())?.update_oneof {
+                Some(UpdateOneof::Transaction(transaction)) => {
+                    self.last_slot = Some(self.last_slot.map_or(transaction.slot, |slot| slot.max(transaction.slot)));
+                    if !self.filter.accepts(&transaction) || !self.mark_seen(&transaction) {
+                        continue;
+                    }
+                    match parse_update(transaction, lookup_table_resolver) {
+                        Some(parsed) => on_parsed(&parsed),
+                        None => eprintln!("Error: Transaction update failed to decode"),
+                    }
+                }
+                // Servers behind load balancers ping idle streams and drop them without a reply.
+                Some(UpdateOneof::Ping(_)) => {
+                    requests
+                        .send(SubscribeRequest {
+                            ping: Some(SubscribeRequestPing { id: 1 }),
+                            ..Default::default()
+                        })
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `false` if the transaction was already handed out before a reconnect.
+    fn mark_seen(&mut self, update: &SubscribeUpdateTransaction) -> bool {
+        let signature = update.transaction.as_ref().and_then(|info| Signature::try_from(info.signature.as_slice()).ok());
+        signature.is_none_or(|signature| self.seen.insert(signature))
+    }
+}
+
+/// Converts a `SubscribeUpdateTransaction` from protobuf to the native transaction and meta and
+/// parses it. `None` if the update is missing its transaction or meta or fails to convert.
+pub fn parse_update(
+    update: SubscribeUpdateTransaction,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+) -> Option<ParsedTransaction> {
+    let info = update.transaction?;
+    let transaction = convert_from::create_tx_versioned(info.transaction?).ok()?;
+    let meta = convert_from::create_tx_meta(info.meta?).ok()?;
+
+    let transaction = encoding::decode_native_transaction(transaction, meta);
+    let mut parsed = pipeline::parse_decoded_transaction(&transaction, lookup_table_resolver);
+    parsed.slot = Some(update.slot);
+    parsed.transaction_index = Some(info.index as usize);
+    Some(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashSet, VecDeque};
+    use std::pin::Pin;
+    use std::sync::{Arc, Mutex};
+    use futures::channel::mpsc;
+    use futures::future::{self, Either};
+    use futures::Stream;
+    use solana_sdk::message::Message;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::{SanitizedTransaction, Transaction, TransactionError};
+    use solana_transaction_status_client_types::TransactionStatusMeta;
+    use yellowstone_grpc_proto::convert_to;
+    use yellowstone_grpc_proto::prelude::geyser_server::{Geyser, GeyserServer};
+    use yellowstone_grpc_proto::prelude::{
+        GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+        GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+        GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+        PongResponse, SubscribeReplayInfoRequest, SubscribeReplayInfoResponse, SubscribeUpdate,
+        SubscribeUpdatePing, SubscribeUpdateTransactionInfo,
+    };
+    use yellowstone_grpc_proto::tonic::codegen::async_trait;
+    use yellowstone_grpc_proto::tonic::transport::server::TcpIncoming;
+    use yellowstone_grpc_proto::tonic::transport::Server;
+    use yellowstone_grpc_proto::tonic::{Request, Response, Status, Streaming};
+    use super::*;
+
+    type Updates = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+
+    /// Answers each subscription with the next session's updates and then ends the stream, the
+    /// last session is held open.
+    struct StubGeyser {
+        sessions: Mutex<VecDeque<Vec<SubscribeUpdate>>>,
+        requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+    }
+
+    #[async_trait]
+    impl Geyser for StubGeyser {
+        type SubscribeStream = Updates;
+
+        async fn subscribe(
+            &self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<Updates>, Status> {
+            let subscribe = request.into_inner().message().await?.unwrap();
+            self.requests.lock().unwrap().push(subscribe);
+            let mut sessions = self.sessions.lock().unwrap();
+            let updates = futures::stream::iter(sessions.pop_front().unwrap_or_default().into_iter().map(Ok));
+            let updates: Updates = match sessions.is_empty() {
+                true => Box::pin(updates.chain(futures::stream::pending())),
+                false => Box::pin(updates),
+            };
+            Ok(Response::new(updates))
+        }
+
+        async fn subscribe_replay_info(
+            &self,
+            _: Request<SubscribeReplayInfoRequest>,
+        ) -> Result<Response<SubscribeReplayInfoResponse>, Status> {
+            Err(Status::unimplemented("subscribe_replay_info"))
+        }
+
+        async fn ping(&self, _: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_latest_blockhash(
+            &self,
+            _: Request<GetLatestBlockhashRequest>,
+        ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+            Err(Status::unimplemented("get_latest_blockhash"))
+        }
+
+        async fn get_block_height(
+            &self,
+            _: Request<GetBlockHeightRequest>,
+        ) -> Result<Response<GetBlockHeightResponse>, Status> {
+            Err(Status::unimplemented("get_block_height"))
+        }
+
+        async fn get_slot(&self, _: Request<GetSlotRequest>) -> Result<Response<GetSlotResponse>, Status> {
+            Err(Status::unimplemented("get_slot"))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _: Request<IsBlockhashValidRequest>,
+        ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+            Err(Status::unimplemented("is_blockhash_valid"))
+        }
+
+        async fn get_version(&self, _: Request<GetVersionRequest>) -> Result<Response<GetVersionResponse>, Status> {
+            Err(Status::unimplemented("get_version"))
+        }
+    }
+
+    /// A transfer of 1 SOL from a fresh payer, as a Yellowstone transaction update.
+    fn update(is_vote: bool, err: Option<TransactionError>) -> (SubscribeUpdateTransaction, Pubkey, Pubkey) {
+        let (payer, recipient) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let instruction = system_instruction::transfer(&payer, &recipient, 1_000_000_000);
+        let mut transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&payer)));
+        transaction.signatures[0] = Signature::new_unique();
+        let sanitized = SanitizedTransaction::try_from_legacy_transaction(transaction, &HashSet::new()).unwrap();
+        let meta = TransactionStatusMeta {
+            status: err.map_or(Ok(()), Err),
+            fee: 5000,
+            pre_balances: vec![3_000_000_000, 0, 1],
+            post_balances: vec![1_999_995_000, 1_000_000_000, 1],
+            ..TransactionStatusMeta::default()
+        };
+
+        let update = SubscribeUpdateTransaction {
+            transaction: Some(SubscribeUpdateTransactionInfo {
+                signature: sanitized.signature().as_ref().to_vec(),
+                is_vote,
+                transaction: Some(convert_to::create_transaction(&sanitized)),
+                meta: Some(convert_to::create_transaction_meta(&meta)),
+                index: 42,
+            }),
+            slot: 300,
+        };
+        (update, payer, recipient)
+    }
+
+    #[test]
+    fn parses_an_update() {
+        let (update, payer, recipient) = update(false, None);
+
+        let parsed = parse_update(update, None).unwrap();
+
+        assert_eq!(parsed.slot, Some(300));
+        assert_eq!(parsed.transaction_index, Some(42));
+        let change = |account: &Pubkey| {
+            parsed
+                .balance_changes
+                .iter()
+                .find(|change| change.account == *account && change.mint == "SOL")
+                .map(|change| change.change())
+        };
+        assert_eq!(change(&payer), Some(-1_000_005_000));
+        assert_eq!(change(&recipient), Some(1_000_000_000));
+    }
+
+    #[test]
+    fn update_without_meta_is_none() {
+        let (mut update, _, _) = update(false, None);
+        update.transaction.as_mut().unwrap().meta = None;
+
+        assert!(parse_update(update, None).is_none());
+    }
+
+    #[test]
+    fn filter_drops_votes_and_failed_transactions_unless_included() {
+        let transfer = update(false, None).0;
+        let vote = update(true, None).0;
+        let failed = update(false, Some(TransactionError::AccountNotFound)).0;
+
+        let filter = TransactionFilter::default();
+        assert!(filter.accepts(&transfer));
+        assert!(!filter.accepts(&vote));
+        assert!(!filter.accepts(&failed));
+
+        let filter = TransactionFilter {
+            include_votes: true,
+            include_failed: true,
+            ..TransactionFilter::default()
+        };
+        assert!(filter.accepts(&vote));
+        assert!(filter.accepts(&failed));
+    }
+
+    #[test]
+    fn request_asks_the_server_to_filter() {
+        let stream = GrpcStream::new("http://localhost:10000", None, TransactionFilter {
+            account_include: vec!["Prog".to_string()],
+            include_failed: true,
+            ..TransactionFilter::default()
+        });
+
+        let request = stream.request();
+        let filter = &request.transactions["solana-parser"];
+        assert_eq!(filter.vote, Some(false));
+        assert_eq!(filter.failed, None);
+        assert_eq!(filter.account_include, ["Prog"]);
+        assert_eq!(request.commitment, Some(CommitmentLevel::Confirmed as i32));
+    }
+
+    fn transaction_update(update: SubscribeUpdateTransaction, slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec!["solana-parser".to_string()],
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction { slot, ..update })),
+            created_at: None,
+        }
+    }
+
+    fn signature(update: &SubscribeUpdateTransaction) -> String {
+        Signature::try_from(update.transaction.as_ref().unwrap().signature.as_slice()).unwrap().to_string()
+    }
+
+    #[test]
+    fn resumes_from_the_last_slot_after_the_stream_drops() {
+        let (a, b, c, d) = (update(false, None).0, update(false, None).0, update(false, None).0, update(false, None).0);
+        let (vote, failed) = (update(true, None).0, update(false, Some(TransactionError::AccountNotFound)).0);
+        let expected = [signature(&a), signature(&b), signature(&c), signature(&d)];
+        // The first stream ends after slot 11, the second replays it from `from_slot`.
+        let sessions = VecDeque::from([
+            vec![transaction_update(a, 10), transaction_update(b.clone(), 11)],
+            vec![
+                transaction_update(b, 11),
+                transaction_update(vote, 12),
+                SubscribeUpdate {
+                    filters: vec![],
+                    update_oneof: Some(UpdateOneof::Ping(SubscribeUpdatePing {})),
+                    created_at: None,
+                },
+                transaction_update(c, 12),
+                transaction_update(failed, 13),
+                transaction_update(d, 13),
+            ],
+        ]);
+        let requests = Arc::new(Mutex::new(vec![]));
+        let stub = StubGeyser { sessions: Mutex::new(sessions), requests: requests.clone() };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parsed = runtime.block_on(async {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint = format!("http://{}", listener.local_addr().unwrap());
+            listener.set_nonblocking(true).unwrap();
+            let incoming =
+                TcpIncoming::from_listener(tokio::net::TcpListener::from_std(listener).unwrap(), true, None).unwrap();
+            tokio::spawn(Server::builder().add_service(GeyserServer::new(stub)).serve_with_incoming(incoming));
+
+            let mut stream = GrpcStream::new(&endpoint, None, TransactionFilter::default());
+            let (sender, receiver) = mpsc::unbounded();
+            let run = stream.run(None, move |parsed| sender.unbounded_send((parsed.signature.clone(), parsed.slot)).unwrap());
+            let collected = tokio::time::timeout(Duration::from_secs(30), receiver.take(4).collect::<Vec<_>>());
+            let parsed = match future::select(Box::pin(run), Box::pin(collected)).await {
+                Either::Right((collected, _)) => collected.unwrap(),
+                Either::Left(_) => unreachable!(),
+            };
+            parsed
+        });
+
+        let signatures: Vec<&str> = parsed.iter().map(|(signature, _)| signature.as_str()).collect();
+        assert_eq!(signatures, expected);
+        let slots: Vec<Option<u64>> = parsed.iter().map(|(_, slot)| *slot).collect();
+        assert_eq!(slots, [Some(10), Some(11), Some(12), Some(13)]);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].from_slot, None);
+        assert_eq!(requests[1].from_slot, Some(11));
+        assert!(requests[1].transactions.contains_key("solana-parser"));
+    }
+}
@@ -0,0 +1,398 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde_json::{json, Value};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding,
+};
+use crate::lookup_table::AddressLookupTableResolver;
+use crate::pipeline::{self, ParsedTransaction};
+use crate::rpc::ResilientRpcClient;
+
+// Providers cap JSON-RPC batches, 100 getTransaction calls is within every common limit.
+const TRANSACTIONS_PER_BATCH: usize = 100;
+
+/// How far a backfill of `address` has got, persisted as JSON after every page so an
+/// interrupted run picks up where it stopped. Signatures are walked newest to oldest, `before`
+/// is the oldest one already handled.
+pub struct HistoryCursor {
+    path: PathBuf,
+    pub address: Pubkey,
+    pub before: Option<Signature>,
+    pub until: Option<Signature>,
+    pub processed: u64,
+}
+
+impl HistoryCursor {
+    /// Loads the cursor at `path`, starting from the newest signature if the file does not exist
+    /// yet. A given `until` wins over the saved one, which is only used when none is given so a
+    /// resumed run stops at the same place.
+    pub fn open(
+        path: impl AsRef<Path>,
+        address: Pubkey,
+        until: Option<Signature>,
+    ) -> io::Result<Self> {
+        let mut cursor = HistoryCursor {
+            path: path.as_ref().to_path_buf(),
+            address,
+            before: None,
+            until,
+            processed: 0,
+        };
+
+        let contents = match fs::read_to_string(&cursor.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(cursor),
+            Err(e) => return Err(e),
+        };
+        let saved = serde_json::from_str::<Value>(&contents)?;
+        let signature = |key: &str| -> io::Result<Option<Signature>> {
+            match saved[key].as_str() {
+                Some(signature) => Signature::from_str(signature)
+                    .map(Some)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+                None => Ok(None),
+            }
+        };
+
+        if saved["address"].as_str() != Some(address.to_string().as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is a cursor for {}", cursor.path.display(), saved["address"]),
+            ));
+        }
+        cursor.before = signature("before")?;
+        if cursor.until.is_none() {
+            cursor.until = signature("until")?;
+        }
+        cursor.processed = saved["processed"].as_u64().unwrap_or_default();
+        Ok(cursor)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let saved = json!({
+            "address": self.address.to_string(),
+            "before": self.before.map(|signature| signature.to_string()),
+            "until": self.until.map(|signature| signature.to_string()),
+            "processed": self.processed,
+        });
+        // Written aside and renamed over so a crash never leaves a half written cursor.
+        let partial_path = self.path.with_extension("partial");
+        fs::write(&partial_path, serde_json::to_string_pretty(&saved)?)?;
+        fs::rename(&partial_path, &self.path)
+    }
+}
+
+/// Where backfilled transactions go.
+pub trait HistorySink {
+    fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()>;
+
+    /// Makes everything written so far durable, the cursor moves past it right after.
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Pages through `getSignaturesForAddress` from `cursor` back to `cursor.until` (or the
+/// address's first transaction), fetching each page's successful transactions in batches,
+/// parsing them and writing them to `sink`. A transaction the node cannot return is skipped
+/// with an error. The sink is flushed before the cursor moves past a page, so a crash means the
+/// page is written again on resume, never skipped.
+pub fn backfill(
+    rpc_client: &ResilientRpcClient,
+    cursor: &mut HistoryCursor,
+    page_size: usize,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+    sink: &mut impl HistorySink,
+) -> io::Result<()> {
+    loop {
+        let page = rpc_client
+            .get_signatures_for_address(&cursor.address, cursor.before, cursor.until, page_size)
+            .map_err(|e| io::Error::other(format!("Error fetching signatures: {:?}", e)))?;
+        let signatures = page
+            .iter()
+            .map(|status| Signature::from_str(&status.signature))
+            .collect::<Result<Vec<Signature>, _>>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let Some(last) = signatures.last() else {
+            return Ok(());
+        };
+
+        // Failed transactions are listed too but have nothing worth parsing.
+        let successful: Vec<Signature> = page
+            .iter()
+            .zip(signatures.iter())
+            .filter(|(status, _)| status.err.is_none())
+            .map(|(_, signature)| *signature)
+            .collect();
+        for signatures in successful.chunks(TRANSACTIONS_PER_BATCH) {
+            for (signature, tx) in signatures.iter().zip(fetch_transactions(rpc_client, signatures)?) {
+                let Some(tx) = tx else {
+                    continue;
+                };
+                match pipeline::parse_confirmed_transaction(&tx, lookup_table_resolver) {
+                    Some(parsed) => sink.write(&parsed)?,
+                    None => eprintln!("Error: Transaction {} failed to decode", signature),
+                }
+            }
+        }
+
+        sink.flush()?;
+        cursor.before = Some(*last);
+        cursor.processed += page.len() as u64;
+        cursor.save()?;
+    }
+}
+
+/// Fetches `signatures` in one batch, asking again one by one (with the client's retries and
+/// failover) for those the batch did not return. `None` for any still missing after that.
+fn fetch_transactions(
+    rpc_client: &ResilientRpcClient,
+    signatures: &[Signature],
+) -> io::Result<Vec<Option<EncodedConfirmedTransactionWithStatusMeta>>> {
+    let results = rpc_client
+        .get_transactions(signatures, UiTransactionEncoding::Base64)
+        .map_err(|e| io::Error::other(format!("Error fetching transactions: {:?}", e)))?;
+
+    let transactions = signatures
+        .iter()
+        .zip(results)
+        .map(|(signature, result)| match result {
+            Ok(Some(tx)) => Some(tx),
+            _ => match rpc_client.find_transaction(signature, UiTransactionEncoding::Base64) {
+                Ok(Some(tx)) => Some(tx),
+                Ok(None) => {
+                    eprintln!("Error: Transaction {} not found, skipping it", signature);
+                    None
+                }
+                Err(e) => {
+                    eprintln!("Error fetching {}: {:?}, skipping it", signature, e);
+                    None
+                }
+            },
+        })
+        .collect();
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use serde_json::Value;
+    use crate::rpc::stub::{self, transaction, StubRpc};
+    use crate::rpc::RpcConfig;
+    use super::*;
+
+    /// Records what it is handed and the cursor file's contents at every flush.
+    #[derive(Default)]
+    struct RecordingSink {
+        cursor_path: PathBuf,
+        written: Vec<String>,
+        processed_at_flush: Vec<Option<u64>>,
+    }
+
+    impl HistorySink for RecordingSink {
+        fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+            self.written.push(parsed.signature.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            let saved = fs::read_to_string(&self.cursor_path).ok();
+            let processed = saved.map(|saved| serde_json::from_str::<Value>(&saved).unwrap()["processed"].as_u64().unwrap());
+            self.processed_at_flush.push(processed);
+            Ok(())
+        }
+    }
+
+    fn cursor_path() -> PathBuf {
+        std::env::temp_dir().join(format!("solana-parser-history-{}.json", Pubkey::new_unique()))
+    }
+
+    fn client(rpc: &StubRpc) -> ResilientRpcClient {
+        ResilientRpcClient::new(RpcConfig {
+            endpoints: vec![rpc.url.clone()],
+            max_retries: 0,
+            ..RpcConfig::default()
+        })
+    }
+
+    /// Serves `pages` of (signature, failed) newest first, keyed by the `before` signature of
+    /// the request, and the transactions in `transactions`. Batched lookups of signatures in
+    /// `batch_misses` come back null, single ones find them unless they are in `missing`.
+    fn stub(
+        pages: HashMap<Option<String>, Vec<(String, bool)>>,
+        transactions: HashMap<String, Value>,
+        batch_misses: Vec<String>,
+        missing: Vec<String>,
+    ) -> StubRpc {
+        let transactions = Arc::new(transactions);
+        let lookup = move |request: &Value, batched: bool| {
+            let signature = request["params"][0].as_str().unwrap().to_string();
+            let missed = missing.contains(&signature) || (batched && batch_misses.contains(&signature));
+            match transactions.get(&signature) {
+                Some(tx) if !missed => stub::result(request, tx.clone()),
+                _ => stub::result(request, Value::Null),
+            }
+        };
+        StubRpc::start(move |request| match request {
+            Value::Array(requests) => {
+                (200, Value::Array(requests.iter().map(|request| lookup(request, true).1).collect()))
+            }
+            _ if request["method"] == "getTransaction" => lookup(request, false),
+            _ => {
+                let before = request["params"][1]["before"].as_str().map(str::to_string);
+                let page = pages.get(&before).cloned().unwrap_or_default();
+                let statuses: Vec<Value> = page
+                    .iter()
+                    .map(|(signature, failed)| {
+                        json!({
+                            "signature": signature,
+                            "slot": 1,
+                            "err": if *failed { json!({"InstructionError": [0, {"Custom": 1}]}) } else { Value::Null },
+                            "memo": null,
+                            "blockTime": null,
+                            "confirmationStatus": "finalized",
+                        })
+                    })
+                    .collect();
+                stub::result(request, Value::Array(statuses))
+            }
+        })
+    }
+
+    /// `count` transactions as `getTransaction` returns them, by signature.
+    fn transactions(count: usize) -> (Vec<String>, HashMap<String, Value>) {
+        let mut signatures = vec![];
+        let mut transactions = HashMap::new();
+        for _ in 0..count {
+            let (signature, mut tx) = transaction(&Pubkey::new_unique());
+            tx["slot"] = json!(1);
+            tx["blockTime"] = Value::Null;
+            signatures.push(signature.to_string());
+            transactions.insert(signature.to_string(), tx);
+        }
+        (signatures, transactions)
+    }
+
+    #[test]
+    fn fetches_pages_in_batches_and_skips_transactions_the_node_cannot_return() {
+        let (signatures, transactions) = transactions(5);
+        let page: Vec<(String, bool)> =
+            signatures.iter().enumerate().map(|(index, signature)| (signature.clone(), index == 2)).collect();
+        let rpc = stub(
+            HashMap::from([(None, page)]),
+            transactions,
+            vec![signatures[1].clone(), signatures[3].clone()],
+            vec![signatures[3].clone()],
+        );
+        let path = cursor_path();
+        let mut cursor = HistoryCursor::open(&path, Pubkey::new_unique(), None).unwrap();
+        let mut sink = RecordingSink { cursor_path: path.clone(), ..RecordingSink::default() };
+
+        backfill(&client(&rpc), &mut cursor, 10, None, &mut sink).unwrap();
+
+        // The failed one is never fetched, the one the node lost is skipped.
+        assert_eq!(sink.written, [signatures[0].clone(), signatures[1].clone(), signatures[4].clone()]);
+        let batches: Vec<usize> =
+            rpc.requests().iter().filter_map(|request| request.as_array().map(Vec::len)).collect();
+        assert_eq!(batches, [4]);
+        assert_eq!(
+            rpc.methods(),
+            ["getSignaturesForAddress", "getTransaction", "getTransaction", "getSignaturesForAddress"]
+        );
+        // Flushed before the cursor was first saved.
+        assert_eq!(sink.processed_at_flush, [None]);
+        assert_eq!(cursor.before, Some(Signature::from_str(&signatures[4]).unwrap()));
+        assert_eq!(cursor.processed, 5);
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Fails the flush of the `fail_at` page, counting from 1.
+    struct FailingSink {
+        written: Vec<String>,
+        flushes: usize,
+        fail_at: usize,
+    }
+
+    impl HistorySink for FailingSink {
+        fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+            self.written.push(parsed.signature.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            match self.flushes == self.fail_at {
+                true => Err(io::Error::other("sink unavailable")),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn saves_and_reloads_the_cursor() {
+        let path = cursor_path();
+        let address = Pubkey::new_unique();
+        let (before, until) = (Signature::new_unique(), Signature::new_unique());
+
+        let mut cursor = HistoryCursor::open(&path, address, Some(until)).unwrap();
+        assert_eq!((cursor.before, cursor.until, cursor.processed), (None, Some(until), 0));
+        cursor.before = Some(before);
+        cursor.processed = 7;
+        cursor.save().unwrap();
+        assert!(!path.with_extension("partial").exists());
+
+        let cursor = HistoryCursor::open(&path, address, None).unwrap();
+        assert_eq!((cursor.before, cursor.until, cursor.processed), (Some(before), Some(until), 7));
+
+        let error = HistoryCursor::open(&path, Pubkey::new_unique(), None).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn a_given_until_wins_over_the_saved_one() {
+        let path = cursor_path();
+        let address = Pubkey::new_unique();
+        let (saved, given) = (Signature::new_unique(), Signature::new_unique());
+
+        HistoryCursor::open(&path, address, Some(saved)).unwrap().save().unwrap();
+        assert_eq!(HistoryCursor::open(&path, address, Some(given)).unwrap().until, Some(given));
+        assert_eq!(HistoryCursor::open(&path, address, None).unwrap().until, Some(saved));
+
+        // A cursor saved without one does not clear the given one.
+        let mut cursor = HistoryCursor::open(&path, address, None).unwrap();
+        cursor.until = None;
+        cursor.save().unwrap();
+        assert_eq!(HistoryCursor::open(&path, address, Some(given)).unwrap().until, Some(given));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn resumes_an_interrupted_backfill_without_skipping_a_page() {
+        let (signatures, transactions) = transactions(4);
+        let page = |signatures: &[String]| signatures.iter().map(|signature| (signature.clone(), false)).collect();
+        let pages = HashMap::from([(None, page(&signatures[..2])), (Some(signatures[1].clone()), page(&signatures[2..]))]);
+        let rpc = stub(pages, transactions, vec![], vec![]);
+        let rpc_client = client(&rpc);
+        let (path, address) = (cursor_path(), Pubkey::new_unique());
+
+        // The second page is written but the sink fails to flush it.
+        let mut cursor = HistoryCursor::open(&path, address, None).unwrap();
+        let mut sink = FailingSink { written: vec![], flushes: 0, fail_at: 2 };
+        assert!(backfill(&rpc_client, &mut cursor, 2, None, &mut sink).is_err());
+        assert_eq!(sink.written, signatures);
+
+        let mut cursor = HistoryCursor::open(&path, address, None).unwrap();
+        assert_eq!(cursor.before, Some(Signature::from_str(&signatures[1]).unwrap()));
+        assert_eq!(cursor.processed, 2);
+        let mut sink = FailingSink { written: vec![], flushes: 0, fail_at: 0 };
+        backfill(&rpc_client, &mut cursor, 2, None, &mut sink).unwrap();
+        assert_eq!(sink.written, signatures[2..]);
+        assert_eq!(cursor.processed, 4);
+        fs::remove_file(path).unwrap();
+    }
+}
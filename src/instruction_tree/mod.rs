@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use solana_sdk::pubkey::Pubkey;
+use crate::tx_parser::{self, FlatInstruction, KnownInstruction};
+
+/// Stack height the runtime reports for top level instructions.
+const TOP_LEVEL_STACK_HEIGHT: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct InstructionNode {
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    pub stack_height: u32,
+    pub program_id: Pubkey,
+    pub program_id_index: u8,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+    pub parsed: Option<KnownInstruction<'static>>,
+    pub children: Vec<InstructionNode>,
+}
+
+impl InstructionNode {
+    /// See `FlatInstruction::known`.
+    pub fn known(&self) -> Option<KnownInstruction<'_>> {
+        tx_parser::known_instruction(&self.program_id, &self.data, self.parsed.as_ref())
+    }
+}
+
+/// The transaction's instructions arranged by invocation, one root per top level instruction
+/// with every CPI nested under the instruction that made it.
+#[derive(Debug, Clone, Default)]
+pub struct InstructionTree {
+    pub roots: Vec<InstructionNode>,
+}
+
+impl InstructionTree {
+    /// Builds the tree from `flatten_instructions` output. Inner instructions without a
+    /// `stack_height` (recorded before the runtime reported it) are treated as direct CPIs
+    /// of their top level instruction.
+    pub fn build(instructions: &[FlatInstruction], account_keys: &HashMap<u8, Pubkey>) -> Self {
+        let mut roots: Vec<InstructionNode> = vec![];
+
+        for instruction in instructions {
+            let stack_height = match instruction.inner_instruction_index {
+                None => TOP_LEVEL_STACK_HEIGHT,
+                Some(_) => instruction.stack_height.unwrap_or(TOP_LEVEL_STACK_HEIGHT + 1),
+            };
+            let node = InstructionNode {
+                instruction_index: instruction.instruction_index,
+                inner_instruction_index: instruction.inner_instruction_index,
+                stack_height,
+                program_id: account_keys
+                    .get(&instruction.program_id_index)
+                    .cloned()
+                    .unwrap_or_default(),
+                program_id_index: instruction.program_id_index,
+                accounts: instruction.accounts.clone(),
+                data: instruction.data.clone(),
+                parsed: instruction.parsed.clone(),
+                children: vec![],
+            };
+
+            match roots.last_mut() {
+                Some(root)
+                    if node.inner_instruction_index.is_some()
+                        && root.instruction_index == node.instruction_index =>
+                {
+                    attach(&mut root.children, node, TOP_LEVEL_STACK_HEIGHT)
+                }
+                _ => roots.push(node),
+            }
+        }
+
+        InstructionTree { roots }
+    }
+
+    /// Every node in execution order, each paired with its ancestors from the top level
+    /// instruction down to its direct caller.
+    pub fn walk(&self) -> Vec<(&InstructionNode, Vec<&InstructionNode>)> {
+        let mut nodes = vec![];
+        for root in self.roots.iter() {
+            walk_node(root, &mut vec![], &mut nodes);
+        }
+        nodes
+    }
+}
+
+fn attach(children: &mut Vec<InstructionNode>, node: InstructionNode, parent_height: u32) {
+    match children.last_mut() {
+        Some(last) if node.stack_height > parent_height + 1 => {
+            attach(&mut last.children, node, parent_height + 1)
+        }
+        _ => children.push(node),
+    }
+}
+
+fn walk_node<'a>(
+    node: &'a InstructionNode,
+    ancestors: &mut Vec<&'a InstructionNode>,
+    nodes: &mut Vec<(&'a InstructionNode, Vec<&'a InstructionNode>)>,
+) {
+    nodes.push((node, ancestors.clone()));
+    ancestors.push(node);
+    for child in node.children.iter() {
+        walk_node(child, ancestors, nodes);
+    }
+    ancestors.pop();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::tx_parser::FlatInstruction;
+    use super::{InstructionNode, InstructionTree};
+
+    /// An instruction of program `program_id_index`, also used as its data to tell nodes apart.
+    fn instruction(
+        instruction_index: u8,
+        inner_instruction_index: Option<u8>,
+        stack_height: Option<u32>,
+        program_id_index: u8,
+    ) -> FlatInstruction {
+        FlatInstruction {
+            instruction_index,
+            inner_instruction_index,
+            stack_height,
+            program_id_index,
+            accounts: vec![],
+            data: vec![program_id_index],
+            parsed: None,
+        }
+    }
+
+    fn account_keys() -> HashMap<u8, Pubkey> {
+        (0..10).map(|index| (index, Pubkey::new_unique())).collect()
+    }
+
+    /// Each node's data byte with its children's, e.g. `0(1(2) 3)`.
+    fn shape(nodes: &[InstructionNode]) -> String {
+        let shapes: Vec<String> = nodes
+            .iter()
+            .map(|node| {
+                if node.children.is_empty() {
+                    node.data[0].to_string()
+                } else {
+                    format!("{}({})", node.data[0], shape(&node.children))
+                }
+            })
+            .collect();
+        shapes.join(" ")
+    }
+
+    #[test]
+    fn nests_inner_instructions_by_stack_height() {
+        let account_keys = account_keys();
+        let instructions = [
+            instruction(0, None, None, 0),
+            instruction(0, Some(0), Some(2), 1),
+            instruction(0, Some(1), Some(3), 2),
+            instruction(0, Some(2), Some(2), 3),
+        ];
+
+        let tree = InstructionTree::build(&instructions, &account_keys);
+
+        assert_eq!(shape(&tree.roots), "0(1(2) 3)");
+        let caller = &tree.roots[0].children[0];
+        assert_eq!(caller.program_id, account_keys[&1]);
+        assert_eq!(caller.stack_height, 2);
+        assert_eq!(caller.children[0].stack_height, 3);
+    }
+
+    #[test]
+    fn inner_instructions_without_a_stack_height_are_direct_calls() {
+        let instructions = [
+            instruction(0, None, None, 0),
+            instruction(0, Some(0), None, 1),
+            instruction(0, Some(1), None, 2),
+        ];
+
+        let tree = InstructionTree::build(&instructions, &account_keys());
+
+        assert_eq!(shape(&tree.roots), "0(1 2)");
+        assert!(tree.roots[0].children.iter().all(|child| child.stack_height == 2));
+    }
+
+    #[test]
+    fn keeps_each_top_level_instruction_and_its_calls_apart() {
+        let instructions = [
+            instruction(0, None, None, 0),
+            instruction(0, Some(0), Some(2), 1),
+            instruction(1, None, None, 2),
+            instruction(2, None, None, 3),
+            instruction(2, Some(0), Some(2), 4),
+            instruction(2, Some(1), Some(3), 5),
+        ];
+
+        let tree = InstructionTree::build(&instructions, &account_keys());
+
+        assert_eq!(shape(&tree.roots), "0(1) 2 3(4(5))");
+    }
+
+    #[test]
+    fn walks_in_execution_order_with_ancestors() {
+        let instructions = [
+            instruction(0, None, None, 0),
+            instruction(0, Some(0), Some(2), 1),
+            instruction(0, Some(1), Some(3), 2),
+            instruction(0, Some(2), Some(2), 3),
+            instruction(1, None, None, 4),
+        ];
+        let tree = InstructionTree::build(&instructions, &account_keys());
+
+        let walked: Vec<(u8, Vec<u8>)> = tree
+            .walk()
+            .into_iter()
+            .map(|(node, ancestors)| (node.data[0], ancestors.iter().map(|ancestor| ancestor.data[0]).collect()))
+            .collect();
+
+        assert_eq!(
+            walked,
+            [(0, vec![]), (1, vec![0]), (2, vec![0, 1]), (3, vec![0]), (4, vec![])],
+        );
+    }
+}
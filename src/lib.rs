@@ -0,0 +1,40 @@
+pub mod account_parser;
+#[cfg(feature = "rpc")]
+pub mod clickhouse;
+pub mod encoding;
+pub mod explain;
+pub mod export;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "rpc")]
+pub mod history;
+pub mod instruction_tree;
+pub mod lookup_table;
+pub mod offline;
+pub mod output;
+pub mod pipeline;
+pub mod pool_parser;
+#[cfg(any(feature = "kafka", feature = "nats"))]
+pub mod publish;
+pub mod pump_parser;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+#[cfg(feature = "rpc")]
+pub mod server;
+pub mod storage;
+#[cfg(feature = "rpc")]
+pub mod stream;
+pub mod swap_parser;
+pub mod tx_parser;
+
+pub const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+pub const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+pub const ASSOCIATED_TOKEN_PROGRAM: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
+pub const VOTE_PROGRAM: &str = "Vote111111111111111111111111111111111111111";
+pub const RAYDIUM_V4: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const RAYDIUM_CPMM: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
+pub const RAYDIUM_CLMM: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const METEORA_DLMM: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+pub const METEORA_POOLS: &str = "Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB";
+pub const PUMP_FUN: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+pub const PUMP_SWAP: &str = "pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA";
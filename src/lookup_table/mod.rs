@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::pubkey::Pubkey;
+#[cfg(feature = "rpc")]
+use crate::rpc::ResilientRpcClient;
+
+/// Somewhere to read raw address lookup table account data from.
+pub trait LookupTableSource {
+    fn get_table_data(&self, table: &Pubkey) -> Option<Vec<u8>>;
+}
+
+#[derive(Default)]
+pub struct InMemoryLookupTableSource {
+    tables: HashMap<Pubkey, Vec<u8>>,
+}
+
+impl InMemoryLookupTableSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, table: Pubkey, data: Vec<u8>) {
+        self.tables.insert(table, data);
+    }
+}
+
+impl LookupTableSource for InMemoryLookupTableSource {
+    fn get_table_data(&self, table: &Pubkey) -> Option<Vec<u8>> {
+        self.tables.get(table).cloned()
+    }
+}
+
+#[cfg(feature = "rpc")]
+pub struct RpcLookupTableSource {
+    rpc_client: ResilientRpcClient,
+}
+
+#[cfg(feature = "rpc")]
+impl RpcLookupTableSource {
+    pub fn new(rpc_client: ResilientRpcClient) -> Self {
+        RpcLookupTableSource { rpc_client }
+    }
+}
+
+#[cfg(feature = "rpc")]
+impl LookupTableSource for RpcLookupTableSource {
+    fn get_table_data(&self, table: &Pubkey) -> Option<Vec<u8>> {
+        match self.rpc_client.get_account_data(table) {
+            Ok(data) => Some(data),
+            Err(e) => {
+                eprintln!("Error fetching lookup table {}: {:?}", table, e);
+                None
+            }
+        }
+    }
+}
+
+/// A JSON file mapping table addresses to their base64 account data, e.g.
+/// `{"<table address>": "<base64 data>"}`.
+pub struct FileLookupTableSource {
+    path: PathBuf,
+    tables: HashMap<Pubkey, Vec<u8>>,
+}
+
+impl FileLookupTableSource {
+    /// Loads the cache at `path`, starting empty if the file does not exist yet.
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let mut tables: HashMap<Pubkey, Vec<u8>> = HashMap::new();
+
+        if let Ok(contents) = fs::read_to_string(&path) {
+            match serde_json::from_str::<HashMap<String, String>>(&contents) {
+                Ok(entries) => {
+                    for (table, data) in entries.iter() {
+                        if let (Ok(table), Ok(data)) = (Pubkey::from_str(table), STANDARD.decode(data)) {
+                            tables.insert(table, data);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Error reading lookup table cache {}: {:?}", path.display(), e),
+            }
+        }
+
+        FileLookupTableSource { path, tables }
+    }
+
+    pub fn insert(&mut self, table: Pubkey, data: Vec<u8>) {
+        self.tables.insert(table, data);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let entries: HashMap<String, String> = self
+            .tables
+            .iter()
+            .map(|(table, data)| (table.to_string(), STANDARD.encode(data)))
+            .collect();
+        fs::write(&self.path, serde_json::to_string_pretty(&entries)?)
+    }
+}
+
+impl LookupTableSource for FileLookupTableSource {
+    fn get_table_data(&self, table: &Pubkey) -> Option<Vec<u8>> {
+        self.tables.get(table).cloned()
+    }
+}
+
+/// Resolves a v0 message's address table lookups to the keys they load, for transactions
+/// whose meta has no `loadedAddresses` (built locally, simulated, or read without meta).
+pub struct AddressLookupTableResolver {
+    source: Box<dyn LookupTableSource + Send + Sync>,
+    tables: Mutex<HashMap<Pubkey, Vec<Pubkey>>>,
+}
+
+impl AddressLookupTableResolver {
+    pub fn new(source: impl LookupTableSource + Send + Sync + 'static) -> Self {
+        AddressLookupTableResolver {
+            source: Box::new(source),
+            tables: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The table's addresses, fetched again when the cached copy has fewer than `len`. Tables
+    /// only grow, so a cached table that is too short has been extended since it was fetched.
+    fn table_addresses(&self, table: &Pubkey, len: usize) -> Option<Vec<Pubkey>> {
+        if let Some(addresses) = self.tables.lock().unwrap().get(table) {
+            if addresses.len() >= len {
+                return Some(addresses.clone());
+            }
+        }
+
+        let data = self.source.get_table_data(table)?;
+        let addresses = match AddressLookupTable::deserialize(&data) {
+            Ok(lookup_table) => lookup_table.addresses.to_vec(),
+            Err(e) => {
+                eprintln!("Error decoding lookup table {}: {:?}", table, e);
+                return None;
+            }
+        };
+        self.tables.lock().unwrap().insert(*table, addresses.clone());
+        Some(addresses)
+    }
+
+    /// Loaded keys in runtime order, every table's writable keys followed by every table's
+    /// readonly keys. `None` if any table is missing or an index is out of range.
+    pub fn resolve(&self, lookups: &[MessageAddressTableLookup]) -> Option<LoadedAddresses> {
+        let mut loaded_addresses = LoadedAddresses::default();
+
+        for lookup in lookups.iter() {
+            let len = lookup
+                .writable_indexes
+                .iter()
+                .chain(lookup.readonly_indexes.iter())
+                .map(|index| *index as usize + 1)
+                .max()
+                .unwrap_or(0);
+            let addresses = self.table_addresses(&lookup.account_key, len)?;
+            for index in lookup.writable_indexes.iter() {
+                loaded_addresses.writable.push(*addresses.get(*index as usize)?);
+            }
+            for index in lookup.readonly_indexes.iter() {
+                loaded_addresses.readonly.push(*addresses.get(*index as usize)?);
+            }
+        }
+
+        Some(loaded_addresses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use solana_sdk::address_lookup_table::state::{AddressLookupTable, LookupTableMeta};
+    use solana_sdk::message::v0::MessageAddressTableLookup;
+    use solana_sdk::pubkey::Pubkey;
+    use super::{AddressLookupTableResolver, LookupTableSource};
+
+    /// Tables that can change after the resolver owns the source, counting every fetch.
+    #[derive(Clone, Default)]
+    struct SharedSource {
+        tables: Arc<Mutex<HashMap<Pubkey, Vec<Pubkey>>>>,
+        fetches: Arc<Mutex<usize>>,
+    }
+
+    impl LookupTableSource for SharedSource {
+        fn get_table_data(&self, table: &Pubkey) -> Option<Vec<u8>> {
+            *self.fetches.lock().unwrap() += 1;
+            let addresses = self.tables.lock().unwrap().get(table)?.clone();
+            AddressLookupTable {
+                meta: LookupTableMeta::default(),
+                addresses: Cow::Owned(addresses),
+            }
+            .serialize_for_tests()
+            .ok()
+        }
+    }
+
+    fn lookup(table: Pubkey, readonly_index: u8) -> MessageAddressTableLookup {
+        MessageAddressTableLookup {
+            account_key: table,
+            writable_indexes: vec![0],
+            readonly_indexes: vec![readonly_index],
+        }
+    }
+
+    #[test]
+    fn refetches_a_cached_table_that_has_since_been_extended() {
+        let (table, first, second) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let source = SharedSource::default();
+        source.tables.lock().unwrap().insert(table, vec![first]);
+        let resolver = AddressLookupTableResolver::new(source.clone());
+
+        let loaded = resolver.resolve(&[lookup(table, 0)]).unwrap();
+        assert_eq!((loaded.writable, loaded.readonly), (vec![first], vec![first]));
+        resolver.resolve(&[lookup(table, 0)]).unwrap();
+        assert_eq!(*source.fetches.lock().unwrap(), 1);
+
+        source.tables.lock().unwrap().get_mut(&table).unwrap().push(second);
+        let loaded = resolver.resolve(&[lookup(table, 1)]).unwrap();
+        assert_eq!((loaded.writable, loaded.readonly), (vec![first], vec![second]));
+        assert_eq!(*source.fetches.lock().unwrap(), 2);
+
+        // An index past the end of the fresh table is still a miss.
+        assert!(resolver.resolve(&[lookup(table, 2)]).is_none());
+        assert_eq!(*source.fetches.lock().unwrap(), 3);
+    }
+
+    #[test]
+    fn missing_tables_are_not_cached() {
+        let (table, address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let source = SharedSource::default();
+        let resolver = AddressLookupTableResolver::new(source.clone());
+
+        assert!(resolver.resolve(&[lookup(table, 0)]).is_none());
+        source.tables.lock().unwrap().insert(table, vec![address]);
+        assert!(resolver.resolve(&[lookup(table, 0)]).is_some());
+    }
+}
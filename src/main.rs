@@ -1,79 +1,586 @@
-mod tx_parser;
-mod swap_parser;
-
-use solana_client::rpc_client::RpcClient;
-use solana_client::rpc_config::RpcTransactionConfig;
+use clap::{Args, Parser, Subcommand};
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::signature::Signature;
-use solana_transaction_status::{
-    EncodedConfirmedTransactionWithStatusMeta,
-    UiTransactionEncoding,
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding,
 };
-use std::fmt::Debug;
+use solana_parser::clickhouse::ClickHouseSink;
+use solana_parser::explain::{self, Explanation};
+#[cfg(feature = "grpc")]
+use solana_parser::grpc;
+use solana_parser::export::{ExportFormat, Exporter};
+use solana_parser::history::{self, HistoryCursor, HistorySink};
+use solana_parser::lookup_table::{AddressLookupTableResolver, RpcLookupTableSource};
+use solana_parser::offline;
+use solana_parser::output::{self, OutputFormat};
+use solana_parser::pipeline::{self, ParsedTransaction};
+#[cfg(any(feature = "kafka", feature = "nats"))]
+use solana_parser::publish::{Encoding, KeyBy, Publisher};
+use solana_parser::rpc::{ResilientRpcClient, RpcConfig};
+use solana_parser::server::Server;
+use solana_parser::storage::Store;
+use solana_parser::stream::{LiveStream, StreamMode};
+use solana_parser::{METEORA_DLMM, METEORA_POOLS, PUMP_FUN, PUMP_SWAP, RAYDIUM_CLMM, RAYDIUM_CPMM, RAYDIUM_V4};
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
 use std::str::FromStr;
-use crate::tx_parser::TokenBalanceDiff;
 
-const RAYDIUM_V4:&str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
-const RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+const WS_URL: &str = "wss://api.mainnet-beta.solana.com";
+const DEX_PROGRAMS: [&str; 7] = [
+    RAYDIUM_V4,
+    RAYDIUM_CPMM,
+    RAYDIUM_CLMM,
+    METEORA_DLMM,
+    METEORA_POOLS,
+    PUMP_FUN,
+    PUMP_SWAP,
+];
+
+#[derive(Parser)]
+#[command(about = "Parse swaps, transfers and other events out of Solana transactions")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// JSON-RPC endpoint, repeat to fail over between several. Overrides `SOLANA_RPC_URL` and
+    /// `--rpc-config`.
+    #[arg(long = "rpc-url", global = true)]
+    rpc_urls: Vec<String>,
+    /// JSON file with endpoints, commitment, retry and rate limit settings.
+    #[arg(long, global = true)]
+    rpc_config: Option<PathBuf>,
+    /// processed, confirmed or finalized
+    #[arg(long, global = true)]
+    commitment: Option<String>,
+    /// pretty, json or ndjson. Diagnostics always go to stderr so JSON output can be piped.
+    #[arg(long, global = true, default_value = "pretty")]
+    format: OutputFormat,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch a transaction by signature and parse it.
+    Tx {
+        signature: String,
+        /// base58, base64, json or jsonParsed
+        #[arg(long, default_value = "base64")]
+        encoding: String,
+    },
+    /// Parse saved getTransaction responses without touching the network. Directories are
+    /// searched for `.json` files, `-` reads from stdin.
+    File {
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Print a readable breakdown of a transaction: signers, the instruction tree, transfers and
+    /// net balance changes. Takes a signature, or a saved getTransaction response which is read
+    /// without touching the network.
+    Explain {
+        /// Signature, or a file path, `-` for stdin.
+        target: String,
+    },
+    /// Fetch a block, or read a saved getBlock response, and parse every non-vote transaction
+    /// in order.
+    Block {
+        /// Slot to fetch, or the slot the saved block belongs to when `--file` is given.
+        slot: Option<u64>,
+        #[arg(long)]
+        file: Option<PathBuf>,
+        #[command(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Walk an address's transactions from newest to oldest, parsing each one. Progress is kept
+    /// in a cursor file, so running the same command again resumes where it stopped.
+    History {
+        address: String,
+        /// Stop once this signature is reached, e.g. the newest one of a previous backfill.
+        /// Without it a resumed run stops where the saved cursor says.
+        #[arg(long)]
+        until: Option<String>,
+        /// Appended to as transactions are parsed, stdout if not given.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Defaults to `<address>.cursor.json`.
+        #[arg(long)]
+        cursor: Option<PathBuf>,
+        /// Signatures fetched per `getSignaturesForAddress` call, at most 1000.
+        #[arg(long, default_value_t = 1000)]
+        page_size: usize,
+        #[command(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Follow transactions as they land and parse them, until stopped.
+    Stream {
+        /// Programs to follow, the supported DEXes if none are given.
+        #[arg(long = "program")]
+        programs: Vec<String>,
+        /// Use `blockSubscribe` rather than `logsSubscribe`.
+        #[arg(long)]
+        blocks: bool,
+        #[arg(long, default_value = WS_URL)]
+        ws_url: String,
+        #[command(flatten)]
+        sinks: SinkArgs,
+    },
+    /// Serve the parser over HTTP, see `docs/http-api.md` for the routes.
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        #[arg(long, default_value_t = 4)]
+        threads: usize,
+        /// Parsed transactions kept for `GET /tx/{signature}`, 0 disables the cache.
+        #[arg(long, default_value_t = 10_000)]
+        cache_size: usize,
+    },
+    /// Consume a Yellowstone Geyser gRPC transaction stream and parse each transaction.
+    #[cfg(feature = "grpc")]
+    Grpc {
+        endpoint: String,
+        #[arg(long)]
+        x_token: Option<String>,
+        /// Transactions mentioning any of these, the supported DEXes if none are given.
+        #[arg(long = "include")]
+        account_include: Vec<String>,
+        /// Transactions mentioning any of these are dropped.
+        #[arg(long = "exclude")]
+        account_exclude: Vec<String>,
+        /// Transactions must mention all of these.
+        #[arg(long = "require")]
+        account_required: Vec<String>,
+        /// Also parse failed transactions.
+        #[arg(long)]
+        failed: bool,
+    },
+}
+
+#[derive(Args)]
+struct SinkArgs {
+    /// Write swaps and transfers to files in this directory instead of printing them.
+    #[arg(long, value_name = "DIR")]
+    export: Option<PathBuf>,
+    /// csv, or parquet when built with the `parquet` feature.
+    #[arg(long, default_value = "csv")]
+    export_format: ExportFormat,
+    /// Start new export files every this many slots.
+    #[arg(long)]
+    rotate_slots: Option<u64>,
+    /// Write parsed transactions to a database instead of printing them, a SQLite file or a
+    /// `postgres://` URL.
+    #[arg(long, value_name = "URL")]
+    store: Option<String>,
+    /// Write swaps to ClickHouse over its HTTP interface, e.g. `http://localhost:8123`.
+    #[arg(long, value_name = "URL")]
+    clickhouse: Option<String>,
+    /// Created if it does not exist.
+    #[arg(long, default_value = "swaps")]
+    clickhouse_table: String,
+    /// Publish swaps, transfers and pool creations to `kafka://host:9092` (brokers separated by
+    /// commas) or a `nats://host:4222` JetStream server.
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    #[arg(long, value_name = "URL")]
+    publish: Option<String>,
+    /// Events go to `<topic>.swaps`, `<topic>.transfers` and `<topic>.pool_creations`.
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    #[arg(long, default_value = "solana")]
+    publish_topic: String,
+    /// mint, pool or wallet
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    #[arg(long, default_value = "mint")]
+    publish_key: KeyBy,
+    /// json or protobuf
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    #[arg(long, default_value = "json")]
+    publish_encoding: Encoding,
+}
+
+impl SinkArgs {
+    fn is_empty(&self) -> bool {
+        let is_empty = self.export.is_none() && self.store.is_none() && self.clickhouse.is_none();
+        #[cfg(any(feature = "kafka", feature = "nats"))]
+        let is_empty = is_empty && self.publish.is_none();
+        is_empty
+    }
+}
+
+/// Files, databases and message buses parsed transactions go to in place of stdout.
+struct Sinks {
+    exporter: Option<Exporter>,
+    store: Option<Store>,
+    clickhouse: Option<ClickHouseSink>,
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    publisher: Option<Publisher>,
+}
+
+impl Sinks {
+    /// `None` when none of `--export`, `--store`, `--clickhouse` or `--publish` is given.
+    fn open(args: &SinkArgs) -> Option<Sinks> {
+        if args.is_empty() {
+            return None;
+        }
+        let exporter = args.export.as_ref().map(|dir| {
+            Exporter::new(dir, args.export_format, args.rotate_slots)
+                .unwrap_or_else(|e| panic!("Error exporting to {}: {:?}", dir.display(), e))
+        });
+        let store = args.store.as_ref().map(|url| {
+            Store::open(url).unwrap_or_else(|e| panic!("Error opening {}: {:?}", url, e))
+        });
+        let clickhouse = args.clickhouse.as_ref().map(|url| {
+            ClickHouseSink::new(url, &args.clickhouse_table)
+                .and_then(|clickhouse| clickhouse.create_table().map(|_| clickhouse))
+                .unwrap_or_else(|e| panic!("Error opening {}: {:?}", url, e))
+        });
+        #[cfg(any(feature = "kafka", feature = "nats"))]
+        let publisher = args.publish.as_ref().map(|url| {
+            Publisher::open(url, &args.publish_topic, args.publish_key, args.publish_encoding)
+                .unwrap_or_else(|e| panic!("Error connecting to {}: {:?}", url, e))
+        });
+        Some(Sinks {
+            exporter,
+            store,
+            clickhouse,
+            #[cfg(any(feature = "kafka", feature = "nats"))]
+            publisher,
+        })
+    }
+
+    fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+        if let Some(exporter) = self.exporter.as_mut() {
+            exporter.write(parsed)?;
+        }
+        if let Some(store) = self.store.as_mut() {
+            store.write(parsed)?;
+        }
+        if let Some(clickhouse) = self.clickhouse.as_mut() {
+            clickhouse.write(parsed)?;
+        }
+        #[cfg(any(feature = "kafka", feature = "nats"))]
+        if let Some(publisher) = self.publisher.as_mut() {
+            publisher.write(parsed)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(exporter) = self.exporter.as_mut() {
+            exporter.flush()?;
+        }
+        if let Some(store) = self.store.as_mut() {
+            store.flush()?;
+        }
+        if let Some(clickhouse) = self.clickhouse.as_mut() {
+            clickhouse.flush()?;
+        }
+        #[cfg(any(feature = "kafka", feature = "nats"))]
+        if let Some(publisher) = self.publisher.as_mut() {
+            publisher.flush()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match self.exporter.as_mut() {
+            Some(exporter) => exporter.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The sinks a history backfill writes to, or the output file or stdout when none are given.
+struct HistoryOutput {
+    sinks: Option<Sinks>,
+    out: Box<dyn Write>,
+    format: OutputFormat,
+}
+
+impl HistorySink for HistoryOutput {
+    fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+        match self.sinks.as_mut() {
+            Some(sinks) => sinks.write(parsed),
+            None => {
+                output::write_transaction(&mut self.out, parsed, self.format)?;
+                self.out.flush()
+            }
+        }
+    }
 
+    fn flush(&mut self) -> io::Result<()> {
+        match self.sinks.as_mut() {
+            Some(sinks) => sinks.flush(),
+            None => self.out.flush(),
+        }
+    }
+}
 
+fn rpc_config(cli: &Cli, commitment: Option<CommitmentConfig>) -> RpcConfig {
+    let mut rpc_config = RpcConfig::load(cli.rpc_config.as_deref())
+        .unwrap_or_else(|e| panic!("Error reading RPC config: {:?}", e));
+    rpc_config.set_endpoints(cli.rpc_urls.clone());
+    if let Some(commitment) = commitment {
+        rpc_config.commitment = commitment;
+    }
+    rpc_config
+}
 
-struct Transfer {
-    token_balance_diff: TokenBalanceDiff,
-    to_user_account: Pubkey,
-    to_token_account: Pubkey,
-    from_user_account: Pubkey,
-    from_token_account: Pubkey,
+fn print_parsed(parsed: &ParsedTransaction, format: OutputFormat) {
+    output::write_transaction(&mut io::stdout().lock(), parsed, format)
+        .unwrap_or_else(|e| panic!("Error: {:?}", e));
 }
 
-fn fetch_tx(signature: &Signature) -> EncodedConfirmedTransactionWithStatusMeta {
-    let rpc_client = RpcClient::new(RPC_URL.to_string());
-    let tx = rpc_client.get_transaction_with_config(
-        signature,
-        RpcTransactionConfig {
-            encoding: Some(UiTransactionEncoding::Base64),
-            commitment: None,
-            max_supported_transaction_version: Some(2),
-        },
-    );
-
-    tx.unwrap_or_else(|e| panic!("Error: {:?}", e))
+fn parse_and_print(
+    txs: &[EncodedConfirmedTransactionWithStatusMeta],
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+    format: OutputFormat,
+) {
+    for tx in txs.iter() {
+        match pipeline::parse_confirmed_transaction(tx, lookup_table_resolver) {
+            Some(parsed) => print_parsed(&parsed, format),
+            None => eprintln!("Error: Transaction failed to decode"),
+        }
+    }
 }
 
+fn explain_and_print(explanation: Option<Explanation>) {
+    match explanation {
+        Some(explanation) => explanation
+            .write(&mut io::stdout().lock())
+            .unwrap_or_else(|e| panic!("Error: {:?}", e)),
+        None => eprintln!("Error: Transaction failed to decode"),
+    }
+}
 
 fn main() {
-    let transaction_signarure = Signature::from_str(
-        "2qLvZ13vVxbyBP6JBezdps2uymqSKeFTXE7JbktLD97sVZXrCdaDTReLraNv5PXv9h6q83KZK1tzHKkroDwucvhH",
-    )
-    .unwrap();
-    let tx = fetch_tx(&transaction_signarure);
-    let meta = tx.transaction.meta.clone().unwrap();
-    let transaction = match tx.transaction.transaction.clone().decode() {
-        Some(t) => t,
-        None => {
-            println!("Error: Transaction failed to decode");
-            return;
-        }
-    };
-
-    let balance_map = tx_parser::create_balance_diff_map(&meta);
-    let native_balance_map = tx_parser::create_native_balance_diff_map(&meta);
-    let account_keys = tx_parser::get_all_account_keys(&meta, transaction.message.static_account_keys());
-
-
-
-    let token_account_map =
-        tx_parser::build_token_account_map(transaction.message.instructions(), &meta, &account_keys);
-
-    let transfers = tx_parser::parse_instructions(
-        transaction.message.instructions(),
-        &meta.inner_instructions,
-        &balance_map,
-        &native_balance_map,
-        &account_keys,
-        &token_account_map,
-    );
-    // println!("Transfers: {:?}", transfers);
-    let swaps = swap_parser::parse_swaps(transfers, &account_keys);
-    println!("Swaps: {:?}", swaps);
+    let cli = Cli::parse();
+    let commitment = cli.commitment.as_ref().map(|commitment| CommitmentConfig {
+        commitment: CommitmentLevel::from_str(commitment)
+            .unwrap_or_else(|e| panic!("Unknown commitment: {:?}", e)),
+    });
+    let rpc_client = ResilientRpcClient::new(rpc_config(&cli, commitment));
+    let format = cli.format;
+
+    match cli.command {
+        Command::Tx { signature, encoding } => {
+            let transaction_signature = Signature::from_str(&signature)
+                .unwrap_or_else(|e| panic!("Invalid signature: {:?}", e));
+            let encoding = serde_json::from_value::<UiTransactionEncoding>(serde_json::Value::String(encoding))
+                .unwrap_or_else(|e| panic!("Unknown encoding: {:?}", e));
+            match rpc_client.get_transaction(&transaction_signature, encoding) {
+                Ok(tx) => {
+                    let lookup_table_resolver =
+                        AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client));
+                    parse_and_print(&[tx], Some(&lookup_table_resolver), format);
+                }
+                Err(e) => eprintln!("Error: {:?}", e),
+            }
+        }
+        Command::File { paths } => {
+            for path in paths {
+                if path.as_os_str() == "-" {
+                    match offline::load_reader(io::stdin().lock()) {
+                        Ok(txs) => parse_and_print(&txs, None, format),
+                        Err(e) => eprintln!("Error reading stdin: {:?}", e),
+                    }
+                    continue;
+                }
+
+                let files = match offline::find_files(&path) {
+                    Ok(files) => files,
+                    Err(e) => {
+                        eprintln!("Error reading {}: {:?}", path.display(), e);
+                        continue;
+                    }
+                };
+                for file in files {
+                    match offline::load_file(&file) {
+                        Ok(txs) => parse_and_print(&txs, None, format),
+                        Err(e) => eprintln!("Error reading {}: {:?}", file.display(), e),
+                    }
+                }
+            }
+        }
+        Command::Explain { target } => {
+            if target == "-" || PathBuf::from(&target).exists() {
+                let txs = if target == "-" {
+                    offline::load_reader(io::stdin().lock())
+                } else {
+                    offline::load_file(&PathBuf::from(&target))
+                };
+                let txs = txs.unwrap_or_else(|e| panic!("Error reading {}: {:?}", target, e));
+                for (position, tx) in txs.iter().enumerate() {
+                    if position > 0 {
+                        println!();
+                    }
+                    explain_and_print(Explanation::new(tx, None));
+                }
+            } else {
+                let transaction_signature = Signature::from_str(&target)
+                    .unwrap_or_else(|e| panic!("Invalid signature or missing file {}: {:?}", target, e));
+                let tx = match rpc_client.get_transaction(&transaction_signature, UiTransactionEncoding::Base64) {
+                    Ok(tx) => tx,
+                    Err(e) => {
+                        eprintln!("Error: {:?}", e);
+                        return;
+                    }
+                };
+                let lookup_table_resolver =
+                    AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client.clone()));
+                let mut explanation = Explanation::new(&tx, Some(&lookup_table_resolver));
+                if let Some(explanation) = explanation.as_mut() {
+                    match explain::fetch_symbols(&rpc_client, &explanation.unknown_mints()) {
+                        Ok(symbols) => explanation.symbols.extend(symbols),
+                        Err(e) => eprintln!("Error fetching token symbols: {:?}", e),
+                    }
+                }
+                explain_and_print(explanation);
+            }
+        }
+        Command::Block { slot, file, sinks } => {
+            let parsed_block = match file {
+                Some(file) => {
+                    let contents = fs::read_to_string(&file)
+                        .unwrap_or_else(|e| panic!("Error reading {}: {:?}", file.display(), e));
+                    let block = offline::block_from_json(&contents)
+                        .unwrap_or_else(|e| panic!("Error reading {}: {:?}", file.display(), e));
+                    pipeline::parse_block(&block, slot, None)
+                }
+                None => {
+                    let slot = slot.unwrap_or_else(|| panic!("A slot or --file is required"));
+                    let lookup_table_resolver =
+                        AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client.clone()));
+                    match pipeline::fetch_block(&rpc_client, slot, Some(&lookup_table_resolver)) {
+                        Ok(parsed_block) => parsed_block,
+                        Err(e) => {
+                            eprintln!("Error: {:?}", e);
+                            return;
+                        }
+                    }
+                }
+            };
+            match Sinks::open(&sinks) {
+                Some(mut sinks) => {
+                    let result = parsed_block
+                        .transactions
+                        .iter()
+                        .try_for_each(|parsed| sinks.write(parsed))
+                        .and_then(|_| sinks.finish());
+                    if let Err(e) = result {
+                        eprintln!("Error: {:?}", e);
+                    }
+                }
+                None => output::write_block(&mut io::stdout().lock(), &parsed_block, format)
+                    .unwrap_or_else(|e| panic!("Error: {:?}", e)),
+            }
+        }
+        Command::History { address, until, output, cursor, page_size, sinks } => {
+            let address = Pubkey::from_str(&address)
+                .unwrap_or_else(|e| panic!("Invalid address: {:?}", e));
+            let until = until.map(|until| {
+                Signature::from_str(&until).unwrap_or_else(|e| panic!("Invalid signature: {:?}", e))
+            });
+            let cursor_path = cursor.unwrap_or_else(|| PathBuf::from(format!("{}.cursor.json", address)));
+            let mut cursor = HistoryCursor::open(&cursor_path, address, until)
+                .unwrap_or_else(|e| panic!("Error reading {}: {:?}", cursor_path.display(), e));
+            if let Some(before) = cursor.before {
+                eprintln!("Resuming {} before {} ({} done)", address, before, cursor.processed);
+            }
+
+            let out: Box<dyn Write> = match output {
+                Some(output) => Box::new(
+                    fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&output)
+                        .unwrap_or_else(|e| panic!("Error opening {}: {:?}", output.display(), e)),
+                ),
+                None => Box::new(io::stdout()),
+            };
+            let mut history_output = HistoryOutput { sinks: Sinks::open(&sinks), out, format };
+            let lookup_table_resolver =
+                AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client.clone()));
+            let result = history::backfill(
+                &rpc_client,
+                &mut cursor,
+                page_size,
+                Some(&lookup_table_resolver),
+                &mut history_output,
+            );
+            let result = result.and(history_output.sinks.as_mut().map_or(Ok(()), |sinks| sinks.finish()));
+            match result {
+                Ok(()) => eprintln!("Done, {} transactions", cursor.processed),
+                Err(e) => eprintln!(
+                    "Error: {:?}, run again to resume from {}",
+                    e,
+                    cursor_path.display()
+                ),
+            }
+        }
+        Command::Stream { programs, blocks, ws_url, sinks } => {
+            let programs: Vec<Pubkey> = if programs.is_empty() {
+                DEX_PROGRAMS.iter().map(|program| Pubkey::from_str(program).unwrap()).collect()
+            } else {
+                programs
+                    .iter()
+                    .map(|program| {
+                        Pubkey::from_str(program).unwrap_or_else(|e| panic!("Invalid program: {:?}", e))
+                    })
+                    .collect()
+            };
+            let mode = if blocks { StreamMode::Blocks } else { StreamMode::Logs };
+            let lookup_table_resolver =
+                AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client.clone()));
+            let mut stream = LiveStream::new(&ws_url, rpc_client, programs, mode);
+            if let Some(commitment) = commitment {
+                stream.commitment = commitment;
+            }
+            let mut sinks = Sinks::open(&sinks);
+            // Live events go out with their transaction instead of waiting for a full batch.
+            #[cfg(any(feature = "kafka", feature = "nats"))]
+            if let Some(publisher) = sinks.as_mut().and_then(|sinks| sinks.publisher.as_mut()) {
+                publisher.batch_size = 1;
+            }
+            stream.run(Some(&lookup_table_resolver), |parsed| match sinks.as_mut() {
+                Some(sinks) => {
+                    if let Err(e) = sinks.write(parsed) {
+                        eprintln!("Error writing {}: {:?}", parsed.signature, e);
+                    }
+                }
+                None => print_parsed(parsed, format),
+            });
+        }
+        Command::Serve { listen, threads, cache_size } => {
+            Server::new(rpc_client, cache_size)
+                .run(&listen, threads)
+                .unwrap_or_else(|e| panic!("Error serving on {}: {:?}", listen, e));
+        }
+        #[cfg(feature = "grpc")]
+        Command::Grpc {
+            endpoint,
+            x_token,
+            account_include,
+            account_exclude,
+            account_required,
+            failed,
+        } => {
+            let account_include = if account_include.is_empty() {
+                DEX_PROGRAMS.iter().map(|program| program.to_string()).collect()
+            } else {
+                account_include
+            };
+            let filter = grpc::TransactionFilter {
+                account_include,
+                account_exclude,
+                account_required,
+                include_failed: failed,
+                include_votes: false,
+            };
+            let mut stream = grpc::GrpcStream::new(&endpoint, x_token, filter);
+            stream.commitment = commitment.unwrap_or(CommitmentConfig::confirmed());
+            let lookup_table_resolver =
+                AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client));
+            tokio::runtime::Runtime::new()
+                .unwrap_or_else(|e| panic!("Error: {:?}", e))
+                .block_on(stream.run(Some(&lookup_table_resolver), |parsed| print_parsed(parsed, format)));
+        }
+    }
 }
@@ -0,0 +1,84 @@
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiConfirmedBlock,
+};
+
+/// Reads saved `getTransaction` responses. `contents` may hold a single response, a JSON array
+/// of them, or several concatenated / newline-delimited, each either the full JSON-RPC envelope
+/// or just its `result`.
+pub fn transactions_from_json(
+    contents: &str,
+) -> Result<Vec<EncodedConfirmedTransactionWithStatusMeta>, serde_json::Error> {
+    let mut transactions = vec![];
+    for value in serde_json::Deserializer::from_str(contents).into_iter::<Value>() {
+        collect_transactions(value?, &mut transactions)?;
+    }
+    Ok(transactions)
+}
+
+fn collect_transactions(
+    value: Value,
+    transactions: &mut Vec<EncodedConfirmedTransactionWithStatusMeta>,
+) -> Result<(), serde_json::Error> {
+    match value {
+        Value::Array(values) => {
+            for value in values {
+                collect_transactions(value, transactions)?;
+            }
+        }
+        Value::Object(mut object) if object.contains_key("jsonrpc") => {
+            // A `null` result is what the RPC returns for an unknown signature.
+            match object.remove("result") {
+                Some(Value::Null) | None => {}
+                Some(result) => collect_transactions(result, transactions)?,
+            }
+        }
+        value => transactions.push(serde_json::from_value(value)?),
+    }
+    Ok(())
+}
+
+pub fn load_reader(
+    mut reader: impl Read,
+) -> io::Result<Vec<EncodedConfirmedTransactionWithStatusMeta>> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    Ok(transactions_from_json(&contents)?)
+}
+
+/// Every `.json` file under `path` in name order, or `path` itself if it is a file.
+pub fn find_files(path: &Path) -> io::Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = vec![];
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            files.extend(find_files(&entry)?);
+        } else if entry.extension().is_some_and(|extension| extension == "json") {
+            files.push(entry);
+        }
+    }
+    Ok(files)
+}
+
+pub fn load_file(path: &Path) -> io::Result<Vec<EncodedConfirmedTransactionWithStatusMeta>> {
+    load_reader(fs::File::open(path)?)
+}
+
+/// Reads a saved `getBlock` response, either the full JSON-RPC envelope or just its `result`.
+pub fn block_from_json(contents: &str) -> Result<UiConfirmedBlock, serde_json::Error> {
+    let mut value = serde_json::from_str::<Value>(contents)?;
+    if value.get("jsonrpc").is_some() {
+        value = value["result"].take();
+    }
+    serde_json::from_value(value)
+}
@@ -0,0 +1,158 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+use crate::pipeline::{ParsedBlock, ParsedTransaction};
+
+/// How parsed transactions are written, the JSON layout is described in `docs/json-output.md`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    /// Human readable summary, its layout is not stable.
+    #[default]
+    Pretty,
+    /// Each transaction, or a whole block, as an indented JSON document.
+    Json,
+    /// Each transaction as one line of JSON.
+    Ndjson,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "pretty" => Ok(OutputFormat::Pretty),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            _ => Err(format!("unknown format {}, expected json, ndjson or pretty", format)),
+        }
+    }
+}
+
+pub fn write_transaction(
+    out: &mut impl Write,
+    parsed: &ParsedTransaction,
+    format: OutputFormat,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Pretty => write_pretty(out, parsed),
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, parsed)?;
+            writeln!(out)
+        }
+        OutputFormat::Ndjson => {
+            serde_json::to_writer(&mut *out, parsed)?;
+            writeln!(out)
+        }
+    }
+}
+
+/// Writes a block as one JSON document with `Json`, otherwise transaction by transaction.
+pub fn write_block(out: &mut impl Write, parsed_block: &ParsedBlock, format: OutputFormat) -> io::Result<()> {
+    match format {
+        OutputFormat::Pretty => {
+            parsed_block
+                .transactions
+                .iter()
+                .try_for_each(|parsed| write_pretty(out, parsed))
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut *out, parsed_block)?;
+            writeln!(out)
+        }
+        OutputFormat::Ndjson => parsed_block
+            .transactions
+            .iter()
+            .try_for_each(|parsed| write_transaction(out, parsed, format)),
+    }
+}
+
+fn write_pretty(out: &mut impl Write, parsed: &ParsedTransaction) -> io::Result<()> {
+    match parsed.transaction_index {
+        Some(transaction_index) => writeln!(out, "Transaction #{}: {}", transaction_index, parsed.signature)?,
+        None => writeln!(out, "Transaction: {}", parsed.signature)?,
+    }
+    if !parsed.pool_creations.is_empty() {
+        writeln!(out, "Pool creations: {:?}", parsed.pool_creations)?;
+    }
+    if !parsed.pump_events.is_empty() {
+        writeln!(out, "Pump events: {:?}", parsed.pump_events)?;
+    }
+    if !parsed.account_events.is_empty() {
+        writeln!(out, "Account events: {:?}", parsed.account_events)?;
+    }
+    if !parsed.transfers.is_empty() {
+        writeln!(out, "Transfers: {:?}", parsed.transfers)?;
+    }
+    writeln!(out, "Swaps: {:?}", parsed.swaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+    use crate::pipeline::{ParsedBlock, ParsedTransaction};
+    use crate::tx_parser::{TokenBalanceDiff, Transfer};
+    use super::{write_block, write_transaction, OutputFormat};
+
+    fn parsed(transfers: Vec<Transfer>) -> ParsedTransaction {
+        ParsedTransaction {
+            signature: "sig".to_string(),
+            slot: Some(7),
+            block_time: None,
+            transaction_index: Some(0),
+            transfers,
+            swaps: vec![],
+            pool_creations: vec![],
+            pump_events: vec![],
+            account_events: vec![],
+            balance_changes: vec![],
+        }
+    }
+
+    fn transfer() -> Transfer {
+        Transfer {
+            instruction_index: 0,
+            inner_instruction_index: None,
+            instruction_program_id: None,
+            instruction_input_accounts: None,
+            caller_program_id: None,
+            outer_program_id: None,
+            token_balance_diff: TokenBalanceDiff {
+                token_amount: 1.5,
+                raw_amount: 1_500_000,
+                mint: "TransferMint".to_string(),
+                decimals: 6,
+            },
+            fee: None,
+            to_user_account: Pubkey::new_unique(),
+            to_token_account: Pubkey::new_unique(),
+            from_user_account: Pubkey::new_unique(),
+            from_token_account: Pubkey::new_unique(),
+        }
+    }
+
+    fn pretty_transaction(parsed: &ParsedTransaction) -> String {
+        let mut out = Vec::new();
+        write_transaction(&mut out, parsed, OutputFormat::Pretty).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn pretty_transaction_lists_transfers_only_when_there_are_some() {
+        assert!(pretty_transaction(&parsed(vec![transfer()])).contains("Transfers: [Transfer {"));
+        assert!(!pretty_transaction(&parsed(vec![])).contains("Transfers:"));
+    }
+
+    #[test]
+    fn pretty_block_matches_its_transactions() {
+        let block = ParsedBlock {
+            slot: Some(7),
+            block_time: None,
+            blockhash: "hash".to_string(),
+            transactions: vec![parsed(vec![transfer()]), parsed(vec![])],
+        };
+        let mut out = Vec::new();
+        write_block(&mut out, &block, OutputFormat::Pretty).unwrap();
+        let expected: String = block.transactions.iter().map(pretty_transaction).collect();
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+        assert_eq!(expected.matches("Transfers:").count(), 1);
+    }
+}
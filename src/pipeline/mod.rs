@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "rpc")]
+use solana_client::client_error::Result as ClientResult;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::transaction::SanitizedTransaction;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, EncodedTransactionWithStatusMeta,
+    TransactionStatusMeta, UiConfirmedBlock,
+};
+use crate::account_parser::{self, AccountEvent};
+use crate::encoding::{self, DecodedTransaction};
+use crate::lookup_table::AddressLookupTableResolver;
+use crate::pool_parser::{self, PoolCreation};
+use crate::pump_parser::{self, PumpEvent};
+#[cfg(feature = "rpc")]
+use crate::rpc::ResilientRpcClient;
+use crate::swap_parser::{self, Swap};
+use crate::tx_parser::{self, BalanceChange, Balances, FlatInstruction, Transfer};
+use crate::VOTE_PROGRAM;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParsedTransaction {
+    pub signature: String,
+    pub slot: Option<u64>,
+    pub block_time: Option<i64>,
+    /// Position of the transaction within its block, vote transactions included.
+    pub transaction_index: Option<usize>,
+    pub transfers: Vec<Transfer>,
+    pub swaps: Vec<Swap>,
+    pub pool_creations: Vec<PoolCreation>,
+    pub pump_events: Vec<PumpEvent>,
+    pub account_events: Vec<AccountEvent>,
+    pub balance_changes: Vec<BalanceChange>,
+}
+
+pub fn parse_confirmed_transaction(
+    tx: &EncodedConfirmedTransactionWithStatusMeta,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+) -> Option<ParsedTransaction> {
+    let mut parsed_transaction = parse_transaction(&tx.transaction, lookup_table_resolver)?;
+    parsed_transaction.slot = Some(tx.slot);
+    parsed_transaction.block_time = tx.block_time;
+    Some(parsed_transaction)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParsedBlock {
+    pub slot: Option<u64>,
+    pub block_time: Option<i64>,
+    pub blockhash: String,
+    /// Non-vote transactions in block order.
+    pub transactions: Vec<ParsedTransaction>,
+}
+
+/// Parses every non-vote transaction of a `getBlock` response fetched with full transaction
+/// details. Transactions that fail to decode are skipped but keep their index.
+pub fn parse_block(
+    block: &UiConfirmedBlock,
+    slot: Option<u64>,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+) -> ParsedBlock {
+    let mut transactions: Vec<ParsedTransaction> = vec![];
+
+    for (transaction_index, tx) in block.transactions.iter().flatten().enumerate() {
+        let Some(transaction) = encoding::decode_transaction(tx) else {
+            eprintln!("Error: Transaction {} failed to decode", transaction_index);
+            continue;
+        };
+        if is_vote_transaction(&transaction) {
+            continue;
+        }
+        let mut parsed_transaction = parse_decoded_transaction(&transaction, lookup_table_resolver);
+        parsed_transaction.slot = slot;
+        parsed_transaction.block_time = block.block_time;
+        parsed_transaction.transaction_index = Some(transaction_index);
+        transactions.push(parsed_transaction);
+    }
+
+    ParsedBlock {
+        slot,
+        block_time: block.block_time,
+        blockhash: block.blockhash.clone(),
+        transactions,
+    }
+}
+
+/// Fetches `slot` with full transaction details and parses it like `parse_block`.
+#[cfg(feature = "rpc")]
+#[allow(clippy::result_large_err)]
+pub fn fetch_block(
+    rpc_client: &ResilientRpcClient,
+    slot: u64,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+) -> ClientResult<ParsedBlock> {
+    let block = rpc_client.get_block(slot)?;
+    Ok(parse_block(&block, Some(slot), lookup_table_resolver))
+}
+
+fn is_vote_transaction(transaction: &DecodedTransaction) -> bool {
+    let vote_program = Pubkey::from_str(VOTE_PROGRAM).unwrap();
+    let account_keys = transaction.message.static_account_keys();
+    transaction.message.instructions().iter().any(|instruction| {
+        account_keys.get(instruction.program_id_index as usize) == Some(&vote_program)
+    })
+}
+
+/// Runs every parser over one transaction in any encoding `encoding::decode_transaction`
+/// accepts. `None` if the transaction has no meta or fails to decode.
+pub fn parse_transaction(
+    tx: &EncodedTransactionWithStatusMeta,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+) -> Option<ParsedTransaction> {
+    let transaction = encoding::decode_transaction(tx)?;
+    Some(parse_decoded_transaction(&transaction, lookup_table_resolver))
+}
+
+/// Runs every parser over a transaction as the runtime hands it to Geyser plugins, reading the
+/// native message and meta in place. Loaded addresses come from the sanitized message, so no
+/// lookup table resolver is needed.
+pub fn parse_sanitized_transaction(
+    transaction: &SanitizedTransaction,
+    meta: &TransactionStatusMeta,
+) -> ParsedTransaction {
+    let message = transaction.message();
+    let account_keys: HashMap<u8, Pubkey> = message
+        .account_keys()
+        .iter()
+        .enumerate()
+        .map(|(index, key)| (index as u8, *key))
+        .collect();
+    let instructions = tx_parser::flatten_native_instructions(
+        message.instructions(),
+        meta.inner_instructions.as_deref(),
+    );
+    parse_instructions_and_balances(
+        transaction.signature().to_string(),
+        &instructions,
+        &account_keys,
+        &Balances::from(meta),
+    )
+}
+
+pub fn parse_decoded_transaction(
+    transaction: &DecodedTransaction,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
+) -> ParsedTransaction {
+    let meta = &transaction.meta;
+    let account_keys =
+        tx_parser::get_all_account_keys(Some(meta), &transaction.message, lookup_table_resolver);
+    let instructions = transaction.instructions();
+    parse_instructions_and_balances(
+        transaction
+            .signatures
+            .first()
+            .map(|signature| signature.to_string())
+            .unwrap_or_default(),
+        &instructions,
+        &account_keys,
+        &Balances::from(meta),
+    )
+}
+
+/// The parsers themselves, shared by every form a transaction arrives in.
+fn parse_instructions_and_balances(
+    signature: String,
+    instructions: &[FlatInstruction],
+    account_keys: &HashMap<u8, Pubkey>,
+    balances: &Balances,
+) -> ParsedTransaction {
+    let balance_map = tx_parser::create_balance_diff_map(balances);
+    let native_balance_map = tx_parser::create_native_balance_diff_map(balances);
+    let token_account_map =
+        tx_parser::build_token_account_map(instructions, balances, account_keys);
+
+    let mut transfers = tx_parser::parse_instructions(
+        instructions,
+        &balance_map,
+        &native_balance_map,
+        account_keys,
+        &token_account_map,
+    );
+    let mut swaps = swap_parser::parse_swaps(&transfers, account_keys);
+    swaps.extend(swap_parser::parse_pump_swaps(instructions, &balance_map, account_keys, &mut transfers));
+    let pool_creations = pool_parser::parse_pool_creations(instructions, account_keys);
+    let pump_events = pump_parser::parse_pump_events(instructions, account_keys);
+    let account_events = account_parser::parse_account_events(instructions, account_keys);
+    let balance_changes = tx_parser::create_balance_changes(balances, account_keys);
+
+    ParsedTransaction {
+        signature,
+        slot: None,
+        block_time: None,
+        transaction_index: None,
+        transfers,
+        swaps,
+        pool_creations,
+        pump_events,
+        account_events,
+        balance_changes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use serde_json::json;
+    use solana_sdk::instruction::{AccountMeta, CompiledInstruction, Instruction};
+    use solana_sdk::message::Message;
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::{SanitizedTransaction, Transaction};
+    use solana_transaction_status_client_types::{
+        InnerInstruction, InnerInstructions, TransactionStatusMeta, TransactionTokenBalance,
+    };
+    use crate::encoding;
+    use super::*;
+
+    fn token_balance(account_index: u8, mint: &Pubkey, owner: &Pubkey, amount: u64) -> TransactionTokenBalance {
+        TransactionTokenBalance {
+            account_index,
+            mint: mint.to_string(),
+            ui_token_amount: serde_json::from_value(json!({
+                "amount": amount.to_string(),
+                "decimals": 6,
+                "uiAmount": amount as f64 / 1e6,
+                "uiAmountString": "",
+            }))
+            .unwrap(),
+            owner: owner.to_string(),
+            program_id: crate::TOKEN_PROGRAM.to_string(),
+        }
+    }
+
+    #[test]
+    fn native_and_rpc_meta_parse_the_same() {
+        let (payer, source, destination, recipient, mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let program = Pubkey::new_unique();
+        let token_program = Pubkey::from_str(crate::TOKEN_PROGRAM).unwrap();
+        // A program that moves tokens by CPI, so the transfer is an inner instruction.
+        let instruction = Instruction::new_with_bytes(
+            program,
+            &[],
+            vec![
+                AccountMeta::new(source, false),
+                AccountMeta::new(destination, false),
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(recipient, false),
+                AccountMeta::new_readonly(token_program, false),
+            ],
+        );
+        let transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&payer)));
+        let keys = &transaction.message.account_keys;
+        let index = |key: &Pubkey| keys.iter().position(|k| k == key).unwrap() as u8;
+        let transfer = spl_token::instruction::transfer(&token_program, &source, &destination, &payer, &[], 2_500_000)
+            .unwrap();
+
+        let meta = TransactionStatusMeta {
+            fee: 5000,
+            pre_balances: keys.iter().map(|key| if *key == payer { 1_000_000_000 } else { 1 }).collect(),
+            post_balances: keys.iter().map(|key| if *key == payer { 999_995_000 } else { 1 }).collect(),
+            inner_instructions: Some(vec![InnerInstructions {
+                index: 0,
+                instructions: vec![InnerInstruction {
+                    instruction: CompiledInstruction {
+                        program_id_index: index(&token_program),
+                        accounts: vec![index(&source), index(&destination), index(&payer)],
+                        data: transfer.data,
+                    },
+                    stack_height: Some(2),
+                }],
+            }]),
+            pre_token_balances: Some(vec![
+                token_balance(index(&source), &mint, &payer, 5_000_000),
+                token_balance(index(&destination), &mint, &recipient, 0),
+            ]),
+            post_token_balances: Some(vec![
+                token_balance(index(&source), &mint, &payer, 2_500_000),
+                token_balance(index(&destination), &mint, &recipient, 2_500_000),
+            ]),
+            ..TransactionStatusMeta::default()
+        };
+
+        let sanitized = SanitizedTransaction::try_from_legacy_transaction(transaction.clone(), &HashSet::new()).unwrap();
+        let native = parse_sanitized_transaction(&sanitized, &meta);
+        let decoded = encoding::decode_native_transaction(transaction.into(), meta);
+        let rpc = parse_decoded_transaction(&decoded, None);
+
+        assert_eq!(serde_json::to_value(&native).unwrap(), serde_json::to_value(&rpc).unwrap());
+        assert_eq!(native.transfers.len(), 1);
+        assert_eq!(native.transfers[0].inner_instruction_index, Some(0));
+        assert_eq!(native.transfers[0].caller_program_id, Some(program));
+        assert_eq!(native.transfers[0].to_user_account, recipient);
+        assert_eq!(native.balance_changes.len(), 3);
+    }
+
+    #[cfg(feature = "rpc")]
+    #[test]
+    fn fetches_and_parses_a_block_in_transaction_order() {
+        use serde_json::Value;
+        use crate::rpc::stub::{self, block, transaction, StubRpc};
+        use crate::rpc::RpcConfig;
+
+        let (_, vote_json) = transaction(&Pubkey::from_str(VOTE_PROGRAM).unwrap());
+        let (first, first_json) = transaction(&Pubkey::new_unique());
+        let (failed, mut failed_json) = transaction(&Pubkey::new_unique());
+        failed_json["meta"]["err"] = json!({"InstructionError": [0, {"Custom": 6001}]});
+        failed_json["meta"]["status"] = json!({"Err": {"InstructionError": [0, {"Custom": 6001}]}});
+        let (_, mut undecodable_json) = transaction(&Pubkey::new_unique());
+        undecodable_json["meta"] = Value::Null;
+        let (second, second_json) = transaction(&Pubkey::new_unique());
+
+        let mut block = block(7, vec![vote_json, first_json, failed_json, undecodable_json, second_json]);
+        block["blockTime"] = json!(1_700_000_000);
+        let blockhash = block["blockhash"].clone();
+        let rpc = StubRpc::start(move |request| match request["params"][0].as_u64() {
+            Some(7) => stub::result(request, block.clone()),
+            _ => stub::error(request, -32007, "Slot 8 was skipped"),
+        });
+        let rpc_client = ResilientRpcClient::new(RpcConfig {
+            endpoints: vec![rpc.url.clone()],
+            max_retries: 0,
+            ..RpcConfig::default()
+        });
+
+        let parsed_block = fetch_block(&rpc_client, 7, None).unwrap();
+        assert_eq!(parsed_block.slot, Some(7));
+        assert_eq!(parsed_block.block_time, Some(1_700_000_000));
+        assert_eq!(json!(parsed_block.blockhash), blockhash);
+        // The vote and the transaction without meta are left out but still count for the index.
+        let transactions: Vec<(String, Option<usize>)> = parsed_block
+            .transactions
+            .iter()
+            .map(|parsed| (parsed.signature.clone(), parsed.transaction_index))
+            .collect();
+        assert_eq!(
+            transactions,
+            [(first.to_string(), Some(1)), (failed.to_string(), Some(2)), (second.to_string(), Some(4))]
+        );
+        assert!(parsed_block.transactions.iter().all(|parsed| {
+            parsed.slot == Some(7) && parsed.block_time == Some(1_700_000_000)
+        }));
+
+        assert!(fetch_block(&rpc_client, 8, None).is_err());
+        assert_eq!(rpc.methods(), ["getBlock", "getBlock"]);
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use solana_sdk::pubkey::Pubkey;
+use crate::tx_parser::{read_u64, FlatInstruction};
+use crate::{METEORA_DLMM, METEORA_POOLS, PUMP_SWAP, RAYDIUM_CLMM, RAYDIUM_CPMM, RAYDIUM_V4};
+
+const RAYDIUM_V4_INITIALIZE2: u8 = 1;
+const RAYDIUM_CPMM_INITIALIZE: [u8; 8] = [175, 175, 109, 31, 13, 152, 155, 237];
+// Raydium CLMM and PumpSwap both name their instruction `create_pool`.
+const CREATE_POOL: [u8; 8] = [233, 146, 209, 142, 207, 104, 64, 188];
+const METEORA_DLMM_INITIALIZE_LB_PAIR: [u8; 8] = [45, 154, 237, 210, 221, 15, 166, 92];
+const METEORA_POOLS_INITIALIZE_PERMISSIONLESS_POOL: [u8; 8] = [118, 173, 41, 157, 173, 72, 97, 103];
+
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PoolCreation {
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    #[serde_as(as = "DisplayFromStr")]
+    pub program_id: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub mint_a: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub mint_b: Pubkey,
+    /// Raw initial deposit of `mint_a`, zero for pools that are created empty (CLMM, DLMM).
+    pub amount_a: u64,
+    /// Raw initial deposit of `mint_b`, zero for pools that are created empty (CLMM, DLMM).
+    pub amount_b: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub creator: Pubkey,
+    pub open_time: Option<u64>,
+}
+
+pub fn parse_pool_creations(
+    instructions: &[FlatInstruction],
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Vec<PoolCreation> {
+    let mut pool_creations = Vec::<PoolCreation>::new();
+
+    for instruction in instructions.iter() {
+        if let Some(pool_creation) = process_instruction(instruction, account_keys) {
+            pool_creations.push(pool_creation);
+        }
+    }
+
+    pool_creations
+}
+
+pub fn process_instruction(
+    instruction: &FlatInstruction,
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Option<PoolCreation> {
+    let program_id = *account_keys.get(&instruction.program_id_index)?;
+    let data = instruction.data.as_slice();
+    let account = |position: usize| -> Option<Pubkey> {
+        account_keys.get(instruction.accounts.get(position)?).cloned()
+    };
+
+    let mut pool_creation = PoolCreation {
+        instruction_index: instruction.instruction_index,
+        inner_instruction_index: instruction.inner_instruction_index,
+        program_id,
+        pool: Pubkey::default(),
+        mint_a: Pubkey::default(),
+        mint_b: Pubkey::default(),
+        amount_a: 0,
+        amount_b: 0,
+        creator: Pubkey::default(),
+        open_time: None,
+    };
+
+    if program_id == Pubkey::from_str(RAYDIUM_V4).unwrap() {
+        // initialize2 { nonce: u8, open_time: u64, init_pc_amount: u64, init_coin_amount: u64 }
+        if data.first() != Some(&RAYDIUM_V4_INITIALIZE2) {
+            return None;
+        }
+        pool_creation.pool = account(4)?;
+        pool_creation.mint_a = account(8)?;
+        pool_creation.mint_b = account(9)?;
+        pool_creation.amount_a = read_u64(data, 18)?;
+        pool_creation.amount_b = read_u64(data, 10)?;
+        pool_creation.creator = account(17)?;
+        pool_creation.open_time = Some(read_u64(data, 2)?);
+        return Some(pool_creation);
+    }
+
+    if data.len() < 8 {
+        return None;
+    }
+    let (discriminator, args) = data.split_at(8);
+
+    if program_id == Pubkey::from_str(RAYDIUM_CPMM).unwrap() {
+        // initialize { init_amount_0: u64, init_amount_1: u64, open_time: u64 }
+        if discriminator != RAYDIUM_CPMM_INITIALIZE {
+            return None;
+        }
+        pool_creation.pool = account(3)?;
+        pool_creation.mint_a = account(4)?;
+        pool_creation.mint_b = account(5)?;
+        pool_creation.amount_a = read_u64(args, 0)?;
+        pool_creation.amount_b = read_u64(args, 8)?;
+        pool_creation.creator = account(0)?;
+        pool_creation.open_time = Some(read_u64(args, 16)?);
+        return Some(pool_creation);
+    }
+
+    if program_id == Pubkey::from_str(RAYDIUM_CLMM).unwrap() {
+        // create_pool { sqrt_price_x64: u128, open_time: u64 }
+        if discriminator != CREATE_POOL {
+            return None;
+        }
+        pool_creation.pool = account(2)?;
+        pool_creation.mint_a = account(3)?;
+        pool_creation.mint_b = account(4)?;
+        pool_creation.creator = account(0)?;
+        pool_creation.open_time = Some(read_u64(args, 16)?);
+        return Some(pool_creation);
+    }
+
+    if program_id == Pubkey::from_str(METEORA_DLMM).unwrap() {
+        // initialize_lb_pair { active_id: i32, bin_step: u16 }
+        if discriminator != METEORA_DLMM_INITIALIZE_LB_PAIR {
+            return None;
+        }
+        pool_creation.pool = account(0)?;
+        pool_creation.mint_a = account(2)?;
+        pool_creation.mint_b = account(3)?;
+        pool_creation.creator = account(8)?;
+        return Some(pool_creation);
+    }
+
+    if program_id == Pubkey::from_str(METEORA_POOLS).unwrap() {
+        // initialize_permissionless_pool { curve_type: CurveType, token_a_amount: u64, token_b_amount: u64 }
+        if discriminator != METEORA_POOLS_INITIALIZE_PERMISSIONLESS_POOL {
+            return None;
+        }
+        // CurveType::ConstantProduct is a bare tag, CurveType::Stable carries 50 bytes of parameters.
+        let amounts_offset = match args.first()? {
+            0 => 1,
+            1 => 51,
+            _ => return None,
+        };
+        pool_creation.pool = account(0)?;
+        pool_creation.mint_a = account(2)?;
+        pool_creation.mint_b = account(3)?;
+        pool_creation.amount_a = read_u64(args, amounts_offset)?;
+        pool_creation.amount_b = read_u64(args, amounts_offset + 8)?;
+        pool_creation.creator = account(17)?;
+        return Some(pool_creation);
+    }
+
+    if program_id == Pubkey::from_str(PUMP_SWAP).unwrap() {
+        // create_pool { index: u16, base_amount_in: u64, quote_amount_in: u64 }, this is what
+        // Pump.fun's `migrate` invokes when a bonding curve graduates.
+        if discriminator != CREATE_POOL {
+            return None;
+        }
+        pool_creation.pool = account(0)?;
+        pool_creation.mint_a = account(3)?;
+        pool_creation.mint_b = account(4)?;
+        pool_creation.amount_a = read_u64(args, 2)?;
+        pool_creation.amount_b = read_u64(args, 10)?;
+        pool_creation.creator = account(2)?;
+        return Some(pool_creation);
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::tx_parser::FlatInstruction;
+    use crate::{METEORA_DLMM, METEORA_POOLS, PUMP_SWAP, RAYDIUM_CLMM, RAYDIUM_CPMM, RAYDIUM_V4};
+    use super::{
+        process_instruction, PoolCreation, CREATE_POOL, METEORA_DLMM_INITIALIZE_LB_PAIR,
+        METEORA_POOLS_INITIALIZE_PERMISSIONLESS_POOL, RAYDIUM_CPMM_INITIALIZE, RAYDIUM_V4_INITIALIZE2,
+    };
+
+    const ACCOUNTS: u8 = 21;
+
+    /// Parses `data` sent to `program` with accounts whose position is also their key index.
+    fn parse(program: &str, data: Vec<u8>) -> (Option<PoolCreation>, HashMap<u8, Pubkey>) {
+        let mut account_keys: HashMap<u8, Pubkey> = (0..ACCOUNTS).map(|index| (index, Pubkey::new_unique())).collect();
+        account_keys.insert(ACCOUNTS, Pubkey::from_str(program).unwrap());
+        let instruction = FlatInstruction {
+            instruction_index: 1,
+            inner_instruction_index: Some(2),
+            stack_height: Some(2),
+            program_id_index: ACCOUNTS,
+            accounts: (0..ACCOUNTS).collect(),
+            data,
+            parsed: None,
+        };
+        (process_instruction(&instruction, &account_keys), account_keys)
+    }
+
+    fn bytes(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    #[test]
+    fn raydium_v4_initialize2() {
+        let data = bytes(&[
+            &[RAYDIUM_V4_INITIALIZE2, 254],
+            &1_700_000_000u64.to_le_bytes(),
+            &5_000u64.to_le_bytes(),
+            &7_000u64.to_le_bytes(),
+        ]);
+        let (pool_creation, keys) = parse(RAYDIUM_V4, data);
+        let pool_creation = pool_creation.unwrap();
+
+        assert_eq!((pool_creation.instruction_index, pool_creation.inner_instruction_index), (1, Some(2)));
+        assert_eq!(pool_creation.program_id.to_string(), RAYDIUM_V4);
+        assert_eq!(pool_creation.pool, keys[&4]);
+        assert_eq!((pool_creation.mint_a, pool_creation.mint_b), (keys[&8], keys[&9]));
+        // The coin side is mint a, its deposit comes last.
+        assert_eq!((pool_creation.amount_a, pool_creation.amount_b), (7_000, 5_000));
+        assert_eq!(pool_creation.creator, keys[&17]);
+        assert_eq!(pool_creation.open_time, Some(1_700_000_000));
+
+        assert!(parse(RAYDIUM_V4, bytes(&[&[9], &[0; 25]])).0.is_none());
+    }
+
+    #[test]
+    fn raydium_cpmm_initialize() {
+        let data = bytes(&[
+            &RAYDIUM_CPMM_INITIALIZE,
+            &100u64.to_le_bytes(),
+            &200u64.to_le_bytes(),
+            &1_700_000_000u64.to_le_bytes(),
+        ]);
+        let (pool_creation, keys) = parse(RAYDIUM_CPMM, data);
+        let pool_creation = pool_creation.unwrap();
+
+        assert_eq!(pool_creation.pool, keys[&3]);
+        assert_eq!((pool_creation.mint_a, pool_creation.mint_b), (keys[&4], keys[&5]));
+        assert_eq!((pool_creation.amount_a, pool_creation.amount_b), (100, 200));
+        assert_eq!(pool_creation.creator, keys[&0]);
+        assert_eq!(pool_creation.open_time, Some(1_700_000_000));
+
+        assert!(parse(RAYDIUM_CPMM, bytes(&[&CREATE_POOL, &[0; 24]])).0.is_none());
+    }
+
+    #[test]
+    fn raydium_clmm_create_pool() {
+        let data = bytes(&[&CREATE_POOL, &(1u128 << 64).to_le_bytes(), &1_700_000_000u64.to_le_bytes()]);
+        let (pool_creation, keys) = parse(RAYDIUM_CLMM, data);
+        let pool_creation = pool_creation.unwrap();
+
+        assert_eq!(pool_creation.pool, keys[&2]);
+        assert_eq!((pool_creation.mint_a, pool_creation.mint_b), (keys[&3], keys[&4]));
+        assert_eq!((pool_creation.amount_a, pool_creation.amount_b), (0, 0));
+        assert_eq!(pool_creation.creator, keys[&0]);
+        assert_eq!(pool_creation.open_time, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn meteora_dlmm_initialize_lb_pair() {
+        let data = bytes(&[&METEORA_DLMM_INITIALIZE_LB_PAIR, &(-500i32).to_le_bytes(), &25u16.to_le_bytes()]);
+        let (pool_creation, keys) = parse(METEORA_DLMM, data);
+        let pool_creation = pool_creation.unwrap();
+
+        assert_eq!(pool_creation.pool, keys[&0]);
+        assert_eq!((pool_creation.mint_a, pool_creation.mint_b), (keys[&2], keys[&3]));
+        assert_eq!((pool_creation.amount_a, pool_creation.amount_b), (0, 0));
+        assert_eq!(pool_creation.creator, keys[&8]);
+        assert_eq!(pool_creation.open_time, None);
+    }
+
+    #[test]
+    fn meteora_pools_initialize_permissionless_pool() {
+        let amounts = bytes(&[&300u64.to_le_bytes(), &400u64.to_le_bytes()]);
+        let constant_product = bytes(&[&METEORA_POOLS_INITIALIZE_PERMISSIONLESS_POOL, &[0], &amounts]);
+        let stable = bytes(&[&METEORA_POOLS_INITIALIZE_PERMISSIONLESS_POOL, &[1], &[7; 50], &amounts]);
+
+        for data in [constant_product, stable] {
+            let (pool_creation, keys) = parse(METEORA_POOLS, data);
+            let pool_creation = pool_creation.unwrap();
+            assert_eq!(pool_creation.pool, keys[&0]);
+            assert_eq!((pool_creation.mint_a, pool_creation.mint_b), (keys[&2], keys[&3]));
+            assert_eq!((pool_creation.amount_a, pool_creation.amount_b), (300, 400));
+            assert_eq!(pool_creation.creator, keys[&17]);
+        }
+
+        let unknown_curve = bytes(&[&METEORA_POOLS_INITIALIZE_PERMISSIONLESS_POOL, &[2], &amounts]);
+        assert!(parse(METEORA_POOLS, unknown_curve).0.is_none());
+    }
+
+    #[test]
+    fn pump_swap_create_pool() {
+        let data = bytes(&[
+            &CREATE_POOL,
+            &0u16.to_le_bytes(),
+            &206_900_000_000_000u64.to_le_bytes(),
+            &84_990_359_074u64.to_le_bytes(),
+        ]);
+        let (pool_creation, keys) = parse(PUMP_SWAP, data);
+        let pool_creation = pool_creation.unwrap();
+
+        assert_eq!(pool_creation.pool, keys[&0]);
+        assert_eq!((pool_creation.mint_a, pool_creation.mint_b), (keys[&3], keys[&4]));
+        assert_eq!((pool_creation.amount_a, pool_creation.amount_b), (206_900_000_000_000, 84_990_359_074));
+        assert_eq!(pool_creation.creator, keys[&2]);
+        assert_eq!(pool_creation.open_time, None);
+
+        // Too short for the amounts.
+        assert!(parse(PUMP_SWAP, bytes(&[&CREATE_POOL, &[0; 9]])).0.is_none());
+    }
+}
@@ -0,0 +1,42 @@
+use std::io;
+use std::time::Duration;
+use kafka::producer::{Producer, Record, RequiredAcks};
+use super::{Broker, Message};
+
+pub(super) struct KafkaBroker {
+    producer: Producer,
+}
+
+impl KafkaBroker {
+    /// Waits for every in-sync replica on each send, otherwise a leader failing right after
+    /// acknowledging could lose the batch.
+    pub(super) fn open(hosts: &str) -> io::Result<Self> {
+        let producer = Producer::from_hosts(hosts.split(',').map(|host| host.to_string()).collect())
+            .with_required_acks(RequiredAcks::All)
+            .with_ack_timeout(Duration::from_secs(10))
+            .create()
+            .map_err(io::Error::other)?;
+        Ok(KafkaBroker { producer })
+    }
+}
+
+impl Broker for KafkaBroker {
+    fn publish(&mut self, messages: &[Message]) -> io::Result<()> {
+        // An empty key goes out as no key, leaving the partition to round robin.
+        let records: Vec<Record<&[u8], &[u8]>> = messages
+            .iter()
+            .map(|message| {
+                let key = message.key.as_deref().unwrap_or_default().as_bytes();
+                Record::from_key_value(&message.topic, key, message.payload.as_slice())
+            })
+            .collect();
+        for confirm in self.producer.send_all(&records).map_err(io::Error::other)? {
+            for partition in confirm.partition_confirms {
+                partition.offset.map_err(|code| {
+                    io::Error::other(format!("{} partition {} returned {:?}", confirm.topic, partition.partition, code))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "nats")]
+mod nats;
+mod proto;
+
+use std::io;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+use serde::Serialize;
+use crate::pipeline::ParsedTransaction;
+
+pub use proto::{PoolCreationEvent, SwapEvent, SwapFee, TransferEvent};
+
+const MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Json,
+    Protobuf,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(encoding: &str) -> Result<Self, Self::Err> {
+        match encoding {
+            "json" => Ok(Encoding::Json),
+            "protobuf" => Ok(Encoding::Protobuf),
+            _ => Err(format!("unknown encoding {}, expected json or protobuf", encoding)),
+        }
+    }
+}
+
+/// What message keys are taken from. Events sharing a key land on the same Kafka partition, so
+/// they are consumed in order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum KeyBy {
+    /// The traded token, the side of a swap or pool that is not wrapped SOL.
+    #[default]
+    Mint,
+    /// Transfers have no pool and are sent without a key.
+    Pool,
+    /// The swapper, the sender of a transfer or the pool creator.
+    Wallet,
+}
+
+impl FromStr for KeyBy {
+    type Err = String;
+
+    fn from_str(key: &str) -> Result<Self, Self::Err> {
+        match key {
+            "mint" => Ok(KeyBy::Mint),
+            "pool" => Ok(KeyBy::Pool),
+            "wallet" => Ok(KeyBy::Wallet),
+            _ => Err(format!("unknown key {}, expected mint, pool or wallet", key)),
+        }
+    }
+}
+
+/// An encoded event, `id` is the same every time the event is published.
+struct Message {
+    topic: String,
+    key: Option<String>,
+    #[cfg_attr(not(feature = "nats"), allow(dead_code))]
+    id: String,
+    payload: Vec<u8>,
+}
+
+/// A message bus the publisher sends to, see `kafka.rs` and `nats.rs`.
+trait Broker {
+    /// Returns once every message is acknowledged by the broker.
+    fn publish(&mut self, messages: &[Message]) -> io::Result<()>;
+}
+
+/// JSON events are the object from `--format json` with the transaction's `signature`, `slot`,
+/// `block_time` and `transaction_index` added.
+#[derive(Serialize)]
+struct JsonEvent<'a, T> {
+    signature: &'a str,
+    slot: Option<u64>,
+    block_time: Option<i64>,
+    transaction_index: Option<usize>,
+    #[serde(flatten)]
+    event: &'a T,
+}
+
+/// Publishes swaps, transfers and pool creations as one message each, buffering `batch_size`
+/// messages between sends. A batch is only dropped once the broker acknowledged all of it, a
+/// failed send keeps it for the next flush, so delivery is at least once and consumers should
+/// expect repeats.
+pub struct Publisher {
+    broker: Box<dyn Broker>,
+    topic: String,
+    key_by: KeyBy,
+    encoding: Encoding,
+    messages: Vec<Message>,
+    pub batch_size: usize,
+    /// Doubled before each retry.
+    retry_delay: Duration,
+}
+
+impl Publisher {
+    /// `kafka://host:9092` (brokers separated by commas) or `nats://host:4222` for a JetStream
+    /// server. Events go to the `{topic}.swaps`, `{topic}.transfers` and
+    /// `{topic}.pool_creations` topics, or subjects, which must already exist.
+    pub fn open(url: &str, topic: &str, key_by: KeyBy, encoding: Encoding) -> io::Result<Self> {
+        let broker: Box<dyn Broker> = if let Some(hosts) = url.strip_prefix("kafka://") {
+            open_kafka(hosts)?
+        } else if url.starts_with("nats://") {
+            open_nats(url)?
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown broker URL {}, expected kafka:// or nats://", url),
+            ));
+        };
+        Ok(Publisher {
+            broker,
+            topic: topic.to_string(),
+            key_by,
+            encoding,
+            messages: vec![],
+            batch_size: 1000,
+            retry_delay: Duration::from_secs(1),
+        })
+    }
+
+    pub fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+        for swap in parsed.swaps.iter() {
+            let key = match self.key_by {
+                KeyBy::Mint => Some(traded_mint(&swap.token_in, &swap.token_out)),
+                KeyBy::Pool => swap.pool.clone(),
+                KeyBy::Wallet => Some(swap.wallet.clone()),
+            };
+            let payload = self.encode(parsed, swap, || SwapEvent::new(parsed, swap))?;
+            self.push("swaps", key, parsed, swap.instruction_index, swap.inner_instruction_index, payload);
+        }
+        for transfer in parsed.transfers.iter() {
+            let key = match self.key_by {
+                KeyBy::Mint => Some(transfer.token_balance_diff.mint.clone()),
+                KeyBy::Pool => None,
+                KeyBy::Wallet => Some(transfer.from_user_account.to_string()),
+            };
+            let payload = self.encode(parsed, transfer, || TransferEvent::new(parsed, transfer))?;
+            self.push(
+                "transfers",
+                key,
+                parsed,
+                transfer.instruction_index,
+                transfer.inner_instruction_index,
+                payload,
+            );
+        }
+        for pool_creation in parsed.pool_creations.iter() {
+            let key = match self.key_by {
+                KeyBy::Mint => Some(traded_mint(&pool_creation.mint_a.to_string(), &pool_creation.mint_b.to_string())),
+                KeyBy::Pool => Some(pool_creation.pool.to_string()),
+                KeyBy::Wallet => Some(pool_creation.creator.to_string()),
+            };
+            let payload = self.encode(parsed, pool_creation, || PoolCreationEvent::new(parsed, pool_creation))?;
+            self.push(
+                "pool_creations",
+                key,
+                parsed,
+                pool_creation.instruction_index,
+                pool_creation.inner_instruction_index,
+                payload,
+            );
+        }
+
+        if self.messages.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Sends buffered messages, retrying failed sends. The batch stays buffered if every
+    /// attempt fails.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.messages.is_empty() {
+            return Ok(());
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match self.broker.publish(&self.messages) {
+                Ok(()) => break,
+                Err(e) if attempt >= MAX_ATTEMPTS => return Err(e),
+                Err(e) => {
+                    eprintln!("Error publishing: {}, retrying", e);
+                    thread::sleep(self.retry_delay * (1 << attempt));
+                }
+            }
+        }
+        self.messages.clear();
+        Ok(())
+    }
+
+    fn encode<T: Serialize, P: prost::Message>(
+        &self,
+        parsed: &ParsedTransaction,
+        event: &T,
+        protobuf: impl FnOnce() -> P,
+    ) -> io::Result<Vec<u8>> {
+        match self.encoding {
+            Encoding::Json => serde_json::to_vec(&JsonEvent {
+                signature: &parsed.signature,
+                slot: parsed.slot,
+                block_time: parsed.block_time,
+                transaction_index: parsed.transaction_index,
+                event,
+            })
+            .map_err(io::Error::from),
+            Encoding::Protobuf => Ok(protobuf().encode_to_vec()),
+        }
+    }
+
+    fn push(
+        &mut self,
+        kind: &str,
+        key: Option<String>,
+        parsed: &ParsedTransaction,
+        instruction_index: u8,
+        inner_instruction_index: Option<u8>,
+        payload: Vec<u8>,
+    ) {
+        let instruction_path = match inner_instruction_index {
+            Some(inner_instruction_index) => format!("{}.{}", instruction_index, inner_instruction_index),
+            None => instruction_index.to_string(),
+        };
+        self.messages.push(Message {
+            topic: format!("{}.{}", self.topic, kind),
+            key,
+            id: format!("{}:{}:{}", parsed.signature, kind, instruction_path),
+            payload,
+        });
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!("Error flushing publisher: {:?}", e);
+        }
+    }
+}
+
+fn traded_mint(mint_a: &str, mint_b: &str) -> String {
+    if mint_a == spl_token::native_mint::ID.to_string() {
+        mint_b.to_string()
+    } else {
+        mint_a.to_string()
+    }
+}
+
+#[cfg(feature = "kafka")]
+fn open_kafka(hosts: &str) -> io::Result<Box<dyn Broker>> {
+    Ok(Box::new(kafka::KafkaBroker::open(hosts)?))
+}
+
+#[cfg(not(feature = "kafka"))]
+fn open_kafka(_hosts: &str) -> io::Result<Box<dyn Broker>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "built without the kafka feature"))
+}
+
+#[cfg(feature = "nats")]
+fn open_nats(url: &str) -> io::Result<Box<dyn Broker>> {
+    Ok(Box::new(nats::NatsBroker::open(url)?))
+}
+
+#[cfg(not(feature = "nats"))]
+fn open_nats(_url: &str) -> io::Result<Box<dyn Broker>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "built without the nats feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use prost::Message as _;
+    use serde_json::Value;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::pipeline::ParsedTransaction;
+    use crate::pool_parser::PoolCreation;
+    use crate::swap_parser::Swap;
+    use crate::tx_parser::{TokenBalanceDiff, Transfer};
+    use super::{Broker, Encoding, KeyBy, Message, Publisher, SwapEvent, TransferEvent};
+
+    /// What the mock broker was sent, `(topic, key, id, payload)` per acknowledged message.
+    #[derive(Default)]
+    struct Sent {
+        attempts: usize,
+        messages: Vec<(String, Option<String>, String, Vec<u8>)>,
+    }
+
+    /// Fails its first `failures` sends.
+    struct MockBroker {
+        failures: usize,
+        sent: Arc<Mutex<Sent>>,
+    }
+
+    impl Broker for MockBroker {
+        fn publish(&mut self, messages: &[Message]) -> io::Result<()> {
+            let mut sent = self.sent.lock().unwrap();
+            sent.attempts += 1;
+            if self.failures > 0 {
+                self.failures -= 1;
+                return Err(io::Error::other("broker unavailable"));
+            }
+            sent.messages.extend(messages.iter().map(|message| {
+                (message.topic.clone(), message.key.clone(), message.id.clone(), message.payload.clone())
+            }));
+            Ok(())
+        }
+    }
+
+    fn mock_publisher(key_by: KeyBy, encoding: Encoding, failures: usize) -> (Publisher, Arc<Mutex<Sent>>) {
+        let sent = Arc::new(Mutex::new(Sent::default()));
+        let publisher = Publisher {
+            broker: Box::new(MockBroker { failures, sent: sent.clone() }),
+            topic: "solana".to_string(),
+            key_by,
+            encoding,
+            messages: vec![],
+            batch_size: 1000,
+            retry_delay: Duration::ZERO,
+        };
+        (publisher, sent)
+    }
+
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+
+    /// A swap from SOL, a transfer and a pool creation paired with SOL.
+    fn parsed() -> ParsedTransaction {
+        let key = |byte: u8| Pubkey::new_from_array([byte; 32]);
+        ParsedTransaction {
+            signature: "sig".to_string(),
+            slot: Some(7),
+            block_time: Some(1_700_000_000),
+            transaction_index: Some(3),
+            transfers: vec![Transfer {
+                instruction_index: 2,
+                inner_instruction_index: Some(1),
+                instruction_program_id: None,
+                instruction_input_accounts: None,
+                caller_program_id: None,
+                outer_program_id: None,
+                token_balance_diff: TokenBalanceDiff {
+                    token_amount: 1.5,
+                    raw_amount: 1_500_000,
+                    mint: "TransferMint".to_string(),
+                    decimals: 6,
+                },
+                fee: None,
+                to_user_account: key(1),
+                to_token_account: key(2),
+                from_user_account: key(3),
+                from_token_account: key(4),
+            }],
+            swaps: vec![Swap {
+                instruction_index: 2,
+                inner_instruction_index: None,
+                wallet: "Swapper".to_string(),
+                pool: Some("SwapPool".to_string()),
+                token_in: SOL.to_string(),
+                token_out: "SwapMint".to_string(),
+                amount_in: 0.5,
+                amount_out: 100.0,
+                raw_amount_in: 500_000_000,
+                raw_amount_out: 100_000_000,
+                fees: vec![],
+            }],
+            pool_creations: vec![PoolCreation {
+                instruction_index: 0,
+                inner_instruction_index: Some(4),
+                program_id: key(5),
+                pool: key(6),
+                mint_a: SOL.parse().unwrap(),
+                mint_b: key(7),
+                amount_a: 1,
+                amount_b: 2,
+                creator: key(8),
+                open_time: None,
+            }],
+            pump_events: vec![],
+            account_events: vec![],
+            balance_changes: vec![],
+        }
+    }
+
+    #[test]
+    fn keys_messages_by_mint_pool_or_wallet() {
+        let key = |byte: u8| Some(Pubkey::new_from_array([byte; 32]).to_string());
+        for (key_by, expected) in [
+            (KeyBy::Mint, [Some("SwapMint".to_string()), Some("TransferMint".to_string()), key(7)]),
+            (KeyBy::Pool, [Some("SwapPool".to_string()), None, key(6)]),
+            (KeyBy::Wallet, [Some("Swapper".to_string()), key(3), key(8)]),
+        ] {
+            let (mut publisher, sent) = mock_publisher(key_by, Encoding::Json, 0);
+            publisher.write(&parsed()).unwrap();
+            publisher.flush().unwrap();
+
+            let sent = sent.lock().unwrap();
+            let topics: Vec<&str> = sent.messages.iter().map(|(topic, ..)| topic.as_str()).collect();
+            assert_eq!(topics, ["solana.swaps", "solana.transfers", "solana.pool_creations"]);
+            let keys: Vec<Option<String>> = sent.messages.iter().map(|(_, key, ..)| key.clone()).collect();
+            assert_eq!(keys, expected, "keyed by {:?}", key_by);
+        }
+    }
+
+    #[test]
+    fn encodes_json_and_protobuf_payloads() {
+        let parsed = parsed();
+
+        let (mut publisher, sent) = mock_publisher(KeyBy::Mint, Encoding::Json, 0);
+        publisher.write(&parsed).unwrap();
+        publisher.flush().unwrap();
+        let swap: Value = serde_json::from_slice(&sent.lock().unwrap().messages[0].3).unwrap();
+        assert_eq!(swap["signature"], "sig");
+        assert_eq!(swap["slot"], 7);
+        assert_eq!(swap["transaction_index"], 3);
+        assert_eq!(swap["token_out"], "SwapMint");
+        assert_eq!(swap["raw_amount_in"], 500_000_000);
+
+        let (mut publisher, sent) = mock_publisher(KeyBy::Mint, Encoding::Protobuf, 0);
+        publisher.write(&parsed).unwrap();
+        publisher.flush().unwrap();
+        let sent = sent.lock().unwrap();
+        let swap = SwapEvent::decode(sent.messages[0].3.as_slice()).unwrap();
+        assert_eq!(swap, SwapEvent::new(&parsed, &parsed.swaps[0]));
+        assert_eq!((swap.signature.as_str(), swap.slot, swap.amount_out), ("sig", Some(7), 100.0));
+        let transfer = TransferEvent::decode(sent.messages[1].3.as_slice()).unwrap();
+        assert_eq!(transfer, TransferEvent::new(&parsed, &parsed.transfers[0]));
+    }
+
+    #[test]
+    fn message_ids_are_the_same_every_time_an_event_is_published() {
+        let (mut publisher, sent) = mock_publisher(KeyBy::Mint, Encoding::Protobuf, 0);
+        publisher.write(&parsed()).unwrap();
+        publisher.write(&parsed()).unwrap();
+        publisher.flush().unwrap();
+
+        let sent = sent.lock().unwrap();
+        let ids: Vec<&str> = sent.messages.iter().map(|(_, _, id, _)| id.as_str()).collect();
+        assert_eq!(ids[..3], ["sig:swaps:2", "sig:transfers:2.1", "sig:pool_creations:0.4"]);
+        assert_eq!(ids[..3], ids[3..]);
+    }
+
+    #[test]
+    fn a_failed_flush_keeps_the_batch_for_the_next_one() {
+        let (mut publisher, sent) = mock_publisher(KeyBy::Mint, Encoding::Json, 3);
+        publisher.write(&parsed()).unwrap();
+
+        assert!(publisher.flush().is_err());
+        assert_eq!(sent.lock().unwrap().attempts, 3);
+        assert!(sent.lock().unwrap().messages.is_empty());
+        assert_eq!(publisher.messages.len(), 3);
+
+        publisher.flush().unwrap();
+        assert_eq!(sent.lock().unwrap().attempts, 4);
+        assert_eq!(sent.lock().unwrap().messages.len(), 3);
+        assert!(publisher.messages.is_empty());
+    }
+
+    #[test]
+    fn sends_once_a_batch_is_full() {
+        let (mut publisher, sent) = mock_publisher(KeyBy::Mint, Encoding::Json, 0);
+        publisher.batch_size = 4;
+
+        publisher.write(&parsed()).unwrap();
+        assert_eq!(sent.lock().unwrap().attempts, 0);
+        publisher.write(&parsed()).unwrap();
+        assert_eq!(sent.lock().unwrap().messages.len(), 6);
+    }
+}
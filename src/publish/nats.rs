@@ -0,0 +1,41 @@
+use std::io;
+use async_nats::jetstream::{self, context::Publish};
+use tokio::runtime::Runtime;
+use super::{Broker, Message};
+
+/// Publishes through JetStream, core NATS does not acknowledge messages. The event id is sent
+/// as `Nats-Msg-Id`, so a retried send within the stream's duplicate window is stored once.
+pub(super) struct NatsBroker {
+    runtime: Runtime,
+    jetstream: jetstream::Context,
+}
+
+impl NatsBroker {
+    pub(super) fn open(url: &str) -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let client = runtime.block_on(async_nats::connect(url)).map_err(io::Error::other)?;
+        Ok(NatsBroker { runtime, jetstream: jetstream::new(client) })
+    }
+}
+
+impl Broker for NatsBroker {
+    /// Keys become the last subject token, e.g. `solana.swaps.<mint>`.
+    fn publish(&mut self, messages: &[Message]) -> io::Result<()> {
+        let jetstream = &self.jetstream;
+        self.runtime.block_on(async {
+            let mut acks = Vec::with_capacity(messages.len());
+            for message in messages {
+                let subject = match message.key.as_ref() {
+                    Some(key) => format!("{}.{}", message.topic, key),
+                    None => message.topic.clone(),
+                };
+                let publish = Publish::build().payload(message.payload.clone().into()).message_id(&message.id);
+                acks.push(jetstream.send_publish(subject, publish).await.map_err(io::Error::other)?);
+            }
+            for ack in acks {
+                ack.await.map_err(io::Error::other)?;
+            }
+            Ok(())
+        })
+    }
+}
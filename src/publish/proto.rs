@@ -0,0 +1,189 @@
+use crate::pipeline::ParsedTransaction;
+use crate::pool_parser::PoolCreation;
+use crate::swap_parser::Swap;
+use crate::tx_parser::Transfer;
+
+// Hand written rather than generated so building needs no protoc, keep in step with
+// `docs/events.proto`.
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SwapEvent {
+    #[prost(string, tag = "1")]
+    pub signature: String,
+    #[prost(uint64, optional, tag = "2")]
+    pub slot: Option<u64>,
+    #[prost(int64, optional, tag = "3")]
+    pub block_time: Option<i64>,
+    #[prost(uint64, optional, tag = "4")]
+    pub transaction_index: Option<u64>,
+    #[prost(uint32, tag = "5")]
+    pub instruction_index: u32,
+    #[prost(uint32, optional, tag = "6")]
+    pub inner_instruction_index: Option<u32>,
+    #[prost(string, tag = "7")]
+    pub wallet: String,
+    #[prost(string, optional, tag = "8")]
+    pub pool: Option<String>,
+    #[prost(string, tag = "9")]
+    pub token_in: String,
+    #[prost(string, tag = "10")]
+    pub token_out: String,
+    #[prost(double, tag = "11")]
+    pub amount_in: f64,
+    #[prost(double, tag = "12")]
+    pub amount_out: f64,
+    #[prost(message, repeated, tag = "13")]
+    pub fees: Vec<SwapFee>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct SwapFee {
+    /// `protocol` or `coin_creator`.
+    #[prost(string, tag = "1")]
+    pub kind: String,
+    #[prost(string, tag = "2")]
+    pub recipient: String,
+    #[prost(string, tag = "3")]
+    pub mint: String,
+    #[prost(double, tag = "4")]
+    pub amount: f64,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TransferEvent {
+    #[prost(string, tag = "1")]
+    pub signature: String,
+    #[prost(uint64, optional, tag = "2")]
+    pub slot: Option<u64>,
+    #[prost(int64, optional, tag = "3")]
+    pub block_time: Option<i64>,
+    #[prost(uint64, optional, tag = "4")]
+    pub transaction_index: Option<u64>,
+    #[prost(uint32, tag = "5")]
+    pub instruction_index: u32,
+    #[prost(uint32, optional, tag = "6")]
+    pub inner_instruction_index: Option<u32>,
+    #[prost(string, optional, tag = "7")]
+    pub program_id: Option<String>,
+    #[prost(string, tag = "8")]
+    pub mint: String,
+    #[prost(double, tag = "9")]
+    pub amount: f64,
+    #[prost(uint32, tag = "10")]
+    pub decimals: u32,
+    #[prost(string, tag = "11")]
+    pub from_user_account: String,
+    #[prost(string, tag = "12")]
+    pub from_token_account: String,
+    #[prost(string, tag = "13")]
+    pub to_user_account: String,
+    #[prost(string, tag = "14")]
+    pub to_token_account: String,
+    /// The swap fee leg it pays out, `protocol` or `coin_creator`.
+    #[prost(string, optional, tag = "15")]
+    pub fee: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PoolCreationEvent {
+    #[prost(string, tag = "1")]
+    pub signature: String,
+    #[prost(uint64, optional, tag = "2")]
+    pub slot: Option<u64>,
+    #[prost(int64, optional, tag = "3")]
+    pub block_time: Option<i64>,
+    #[prost(uint64, optional, tag = "4")]
+    pub transaction_index: Option<u64>,
+    #[prost(uint32, tag = "5")]
+    pub instruction_index: u32,
+    #[prost(uint32, optional, tag = "6")]
+    pub inner_instruction_index: Option<u32>,
+    #[prost(string, tag = "7")]
+    pub program_id: String,
+    #[prost(string, tag = "8")]
+    pub pool: String,
+    #[prost(string, tag = "9")]
+    pub mint_a: String,
+    #[prost(string, tag = "10")]
+    pub mint_b: String,
+    #[prost(uint64, tag = "11")]
+    pub amount_a: u64,
+    #[prost(uint64, tag = "12")]
+    pub amount_b: u64,
+    #[prost(string, tag = "13")]
+    pub creator: String,
+    #[prost(uint64, optional, tag = "14")]
+    pub open_time: Option<u64>,
+}
+
+impl SwapEvent {
+    pub fn new(parsed: &ParsedTransaction, swap: &Swap) -> Self {
+        SwapEvent {
+            signature: parsed.signature.clone(),
+            slot: parsed.slot,
+            block_time: parsed.block_time,
+            transaction_index: parsed.transaction_index.map(|index| index as u64),
+            instruction_index: swap.instruction_index as u32,
+            inner_instruction_index: swap.inner_instruction_index.map(u32::from),
+            wallet: swap.wallet.clone(),
+            pool: swap.pool.clone(),
+            token_in: swap.token_in.clone(),
+            token_out: swap.token_out.clone(),
+            amount_in: swap.amount_in,
+            amount_out: swap.amount_out,
+            fees: swap
+                .fees
+                .iter()
+                .map(|fee| SwapFee {
+                    kind: fee.kind.as_str().to_string(),
+                    recipient: fee.recipient.clone(),
+                    mint: fee.mint.clone(),
+                    amount: fee.amount,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl TransferEvent {
+    pub fn new(parsed: &ParsedTransaction, transfer: &Transfer) -> Self {
+        TransferEvent {
+            signature: parsed.signature.clone(),
+            slot: parsed.slot,
+            block_time: parsed.block_time,
+            transaction_index: parsed.transaction_index.map(|index| index as u64),
+            instruction_index: transfer.instruction_index as u32,
+            inner_instruction_index: transfer.inner_instruction_index.map(u32::from),
+            program_id: transfer.instruction_program_id.map(|program_id| program_id.to_string()),
+            mint: transfer.token_balance_diff.mint.clone(),
+            amount: transfer.token_balance_diff.token_amount,
+            decimals: transfer.token_balance_diff.decimals as u32,
+            from_user_account: transfer.from_user_account.to_string(),
+            from_token_account: transfer.from_token_account.to_string(),
+            to_user_account: transfer.to_user_account.to_string(),
+            to_token_account: transfer.to_token_account.to_string(),
+            fee: transfer.fee.as_ref().map(|fee| fee.as_str().to_string()),
+        }
+    }
+}
+
+impl PoolCreationEvent {
+    pub fn new(parsed: &ParsedTransaction, pool_creation: &PoolCreation) -> Self {
+        PoolCreationEvent {
+            signature: parsed.signature.clone(),
+            slot: parsed.slot,
+            block_time: parsed.block_time,
+            transaction_index: parsed.transaction_index.map(|index| index as u64),
+            instruction_index: pool_creation.instruction_index as u32,
+            inner_instruction_index: pool_creation.inner_instruction_index.map(u32::from),
+            program_id: pool_creation.program_id.to_string(),
+            pool: pool_creation.pool.to_string(),
+            mint_a: pool_creation.mint_a.to_string(),
+            mint_b: pool_creation.mint_b.to_string(),
+            amount_a: pool_creation.amount_a,
+            amount_b: pool_creation.amount_b,
+            creator: pool_creation.creator.to_string(),
+            open_time: pool_creation.open_time,
+        }
+    }
+}
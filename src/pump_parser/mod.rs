@@ -0,0 +1,289 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use solana_sdk::pubkey::Pubkey;
+use crate::tx_parser::{read_pubkey, read_u64, FlatInstruction, ANCHOR_EVENT_IX_TAG};
+use crate::PUMP_FUN;
+
+const CREATE: [u8; 8] = [24, 30, 200, 40, 5, 28, 7, 119];
+const MIGRATE: [u8; 8] = [155, 234, 231, 146, 236, 158, 162, 30];
+// Bonding curve withdrawal used by the migration account to graduate to Raydium, before PumpSwap existed.
+const WITHDRAW: [u8; 8] = [183, 18, 70, 156, 148, 109, 161, 34];
+const COMPLETE_EVENT: [u8; 8] = [95, 114, 97, 156, 212, 46, 152, 8];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationDestination {
+    PumpSwap,
+    Raydium,
+}
+
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PumpEventKind {
+    Create {
+        name: String,
+        symbol: String,
+        uri: String,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        bonding_curve: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        creator: Pubkey,
+    },
+    /// The bonding curve sold out, emitted by the buy that completed it.
+    Complete {
+        #[serde_as(as = "DisplayFromStr")]
+        user: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        bonding_curve: Pubkey,
+        timestamp: i64,
+    },
+    Migrate {
+        destination: MigrationDestination,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        bonding_curve: Pubkey,
+        /// The PumpSwap pool, Raydium pools are initialised in a later transaction.
+        #[serde_as(as = "Option<DisplayFromStr>")]
+        pool: Option<Pubkey>,
+        #[serde_as(as = "DisplayFromStr")]
+        user: Pubkey,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PumpEvent {
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    pub kind: PumpEventKind,
+}
+
+pub fn parse_pump_events(
+    instructions: &[FlatInstruction],
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Vec<PumpEvent> {
+    let mut pump_events = Vec::<PumpEvent>::new();
+
+    for instruction in instructions.iter() {
+        if let Some(kind) = process_instruction(instruction, account_keys) {
+            pump_events.push(PumpEvent {
+                instruction_index: instruction.instruction_index,
+                inner_instruction_index: instruction.inner_instruction_index,
+                kind,
+            });
+        }
+    }
+
+    pump_events
+}
+
+fn read_string(data: &[u8], offset: usize) -> Option<(String, usize)> {
+    let len = u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize;
+    let bytes = data.get(offset + 4..offset + 4 + len)?;
+    Some((String::from_utf8_lossy(bytes).to_string(), offset + 4 + len))
+}
+
+pub fn process_instruction(
+    instruction: &FlatInstruction,
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Option<PumpEventKind> {
+    let program_id = account_keys.get(&instruction.program_id_index)?;
+    if *program_id != Pubkey::from_str(PUMP_FUN).unwrap() || instruction.data.len() < 8 {
+        return None;
+    }
+    let (discriminator, args) = instruction.data.split_at(8);
+    let account = |position: usize| -> Option<Pubkey> {
+        account_keys.get(instruction.accounts.get(position)?).cloned()
+    };
+
+    if discriminator == CREATE {
+        // create { name: String, symbol: String, uri: String, creator: Pubkey }, older
+        // deployments have no creator argument and use the signing user instead.
+        let (name, offset) = read_string(args, 0)?;
+        let (symbol, offset) = read_string(args, offset)?;
+        let (uri, offset) = read_string(args, offset)?;
+        let creator = match read_pubkey(args, offset) {
+            Some(creator) => creator,
+            None => account(7)?,
+        };
+        return Some(PumpEventKind::Create {
+            name,
+            symbol,
+            uri,
+            mint: account(0)?,
+            bonding_curve: account(2)?,
+            creator,
+        });
+    }
+
+    if discriminator == MIGRATE {
+        return Some(PumpEventKind::Migrate {
+            destination: MigrationDestination::PumpSwap,
+            mint: account(2)?,
+            bonding_curve: account(3)?,
+            pool: Some(account(9)?),
+            user: account(5)?,
+        });
+    }
+
+    if discriminator == WITHDRAW {
+        return Some(PumpEventKind::Migrate {
+            destination: MigrationDestination::Raydium,
+            mint: account(2)?,
+            bonding_curve: account(3)?,
+            pool: None,
+            user: account(6)?,
+        });
+    }
+
+    if discriminator == ANCHOR_EVENT_IX_TAG {
+        let (event_discriminator, event) = args.split_at(8.min(args.len()));
+        if event_discriminator != COMPLETE_EVENT {
+            return None;
+        }
+        // CompleteEvent { user: Pubkey, mint: Pubkey, bonding_curve: Pubkey, timestamp: i64 }
+        return Some(PumpEventKind::Complete {
+            user: read_pubkey(event, 0)?,
+            mint: read_pubkey(event, 32)?,
+            bonding_curve: read_pubkey(event, 64)?,
+            timestamp: read_u64(event, 96)? as i64,
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::tx_parser::{FlatInstruction, ANCHOR_EVENT_IX_TAG};
+    use crate::PUMP_FUN;
+    use super::{parse_pump_events, MigrationDestination, PumpEventKind, COMPLETE_EVENT, CREATE, MIGRATE, WITHDRAW};
+
+    const ACCOUNTS: u8 = 24;
+
+    /// Account keys where each of the instruction's accounts sits at the index of its position.
+    fn account_keys() -> HashMap<u8, Pubkey> {
+        let mut account_keys: HashMap<u8, Pubkey> = (0..ACCOUNTS).map(|index| (index, Pubkey::new_unique())).collect();
+        account_keys.insert(ACCOUNTS, Pubkey::from_str(PUMP_FUN).unwrap());
+        account_keys
+    }
+
+    fn instruction(inner_instruction_index: Option<u8>, data: Vec<u8>) -> FlatInstruction {
+        FlatInstruction {
+            instruction_index: 3,
+            inner_instruction_index,
+            stack_height: None,
+            program_id_index: ACCOUNTS,
+            accounts: (0..ACCOUNTS).collect(),
+            data,
+            parsed: None,
+        }
+    }
+
+    fn string(value: &str) -> Vec<u8> {
+        [&(value.len() as u32).to_le_bytes(), value.as_bytes()].concat()
+    }
+
+    fn kinds(data: Vec<u8>, account_keys: &HashMap<u8, Pubkey>) -> Vec<PumpEventKind> {
+        parse_pump_events(&[instruction(None, data)], account_keys)
+            .into_iter()
+            .map(|event| event.kind)
+            .collect()
+    }
+
+    #[test]
+    fn create_with_and_without_the_creator_argument() {
+        let keys = account_keys();
+        let creator = Pubkey::new_unique();
+        let args = [string("Coin"), string("COIN"), string("https://example.com/coin.json")].concat();
+
+        for (data, expected_creator) in [
+            ([CREATE.as_slice(), &args, creator.as_ref()].concat(), creator),
+            // Older deployments, the signing user is the creator.
+            ([CREATE.as_slice(), &args].concat(), keys[&7]),
+        ] {
+            let events = parse_pump_events(&[instruction(Some(1), data)], &keys);
+            assert_eq!(events.len(), 1);
+            assert_eq!((events[0].instruction_index, events[0].inner_instruction_index), (3, Some(1)));
+            let PumpEventKind::Create { name, symbol, uri, mint, bonding_curve, creator } = &events[0].kind else {
+                panic!("expected a create, got {:?}", events[0].kind);
+            };
+            assert_eq!((name.as_str(), symbol.as_str()), ("Coin", "COIN"));
+            assert_eq!(uri, "https://example.com/coin.json");
+            assert_eq!((*mint, *bonding_curve, *creator), (keys[&0], keys[&2], expected_creator));
+        }
+
+        // A name running past the end of the data.
+        let truncated = [CREATE.as_slice(), &100u32.to_le_bytes(), b"Coin"].concat();
+        assert!(kinds(truncated, &keys).is_empty());
+    }
+
+    #[test]
+    fn complete_event() {
+        let keys = account_keys();
+        let (user, mint, bonding_curve) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let data = [
+            ANCHOR_EVENT_IX_TAG.as_slice(),
+            &COMPLETE_EVENT,
+            user.as_ref(),
+            mint.as_ref(),
+            bonding_curve.as_ref(),
+            &1_700_000_000i64.to_le_bytes(),
+        ]
+        .concat();
+
+        let complete = kinds(data, &keys);
+        let [PumpEventKind::Complete { user: event_user, mint: event_mint, bonding_curve: event_curve, timestamp }] =
+            complete.as_slice()
+        else {
+            panic!("expected a complete event, got {:?}", complete);
+        };
+        assert_eq!((*event_user, *event_mint, *event_curve), (user, mint, bonding_curve));
+        assert_eq!(*timestamp, 1_700_000_000);
+
+        // Other events under the same self-CPI tag are not pump events.
+        let trade_event = [ANCHOR_EVENT_IX_TAG.as_slice(), &[189, 219, 127, 211, 78, 230, 97, 238], &[0; 96]].concat();
+        assert!(kinds(trade_event, &keys).is_empty());
+    }
+
+    #[test]
+    fn migrate_to_pump_swap_and_withdraw_to_raydium() {
+        let keys = account_keys();
+
+        let migrate = kinds(MIGRATE.to_vec(), &keys);
+        let [PumpEventKind::Migrate { destination, mint, bonding_curve, pool, user }] = migrate.as_slice()
+        else {
+            panic!("expected a migration, got {:?}", migrate);
+        };
+        assert_eq!(*destination, MigrationDestination::PumpSwap);
+        assert_eq!((*mint, *bonding_curve, *pool, *user), (keys[&2], keys[&3], Some(keys[&9]), keys[&5]));
+
+        let withdraw = kinds(WITHDRAW.to_vec(), &keys);
+        let [PumpEventKind::Migrate { destination, mint, bonding_curve, pool, user }] = withdraw.as_slice()
+        else {
+            panic!("expected a migration, got {:?}", withdraw);
+        };
+        assert_eq!(*destination, MigrationDestination::Raydium);
+        assert_eq!((*mint, *bonding_curve, *pool, *user), (keys[&2], keys[&3], None, keys[&6]));
+    }
+
+    #[test]
+    fn ignores_other_programs_and_instructions() {
+        let mut keys = account_keys();
+        assert!(kinds(vec![102, 6, 61, 18, 1, 218, 235, 234], &keys).is_empty());
+        assert!(kinds(vec![1, 2, 3], &keys).is_empty());
+
+        keys.insert(ACCOUNTS, Pubkey::new_unique());
+        assert!(kinds(MIGRATE.to_vec(), &keys).is_empty());
+    }
+}
@@ -0,0 +1,575 @@
+// Every method mirrors an `RpcClient` one and returns its `ClientResult` unchanged.
+#![allow(clippy::result_large_err)]
+
+pub mod nonblocking;
+#[cfg(test)]
+pub(crate) mod stub;
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest::{self, StatusCode};
+use solana_client::client_error::{ClientError, ClientErrorKind, Result as ClientResult};
+use solana_client::rpc_client::{GetConfirmedSignaturesForAddress2Config, RpcClient};
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET,
+    JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+};
+use solana_client::rpc_request::{RpcError, RpcRequest, RpcResponseErrorData};
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::{CommitmentConfig, CommitmentLevel};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, TransactionDetails, UiConfirmedBlock,
+    UiTransactionEncoding,
+};
+
+pub const DEFAULT_RPC_URL: &str = "https://api.mainnet-beta.solana.com";
+/// Comma separated endpoints, overriding the config file.
+pub const RPC_URL_ENV: &str = "SOLANA_RPC_URL";
+
+/// Where and how to reach JSON-RPC. Loaded from a JSON file such as
+/// `{"endpoints": ["https://..."], "commitment": "confirmed", "max_retries": 5,
+/// "initial_backoff_ms": 500, "max_backoff_ms": 10000, "requests_per_second": 10,
+/// "timeout_secs": 30}`, where every key is optional.
+#[derive(Debug, Clone)]
+pub struct RpcConfig {
+    /// Tried in order, moving to the next one when a request keeps failing.
+    pub endpoints: Vec<String>,
+    pub commitment: CommitmentConfig,
+    pub timeout: Duration,
+    /// Retries per request, across all endpoints, before the error is returned.
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Shared by every endpoint, unlimited if `None`.
+    pub requests_per_second: Option<f64>,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            endpoints: vec![DEFAULT_RPC_URL.to_string()],
+            commitment: CommitmentConfig::default(),
+            timeout: Duration::from_secs(30),
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            requests_per_second: None,
+        }
+    }
+}
+
+impl RpcConfig {
+    /// The defaults, overridden by the config file at `path` if given, then by `SOLANA_RPC_URL`.
+    pub fn load(path: Option<&Path>) -> io::Result<Self> {
+        let mut config = RpcConfig::default();
+        if let Some(path) = path {
+            config.apply_json(&fs::read_to_string(path)?)?;
+        }
+        if let Ok(endpoints) = env::var(RPC_URL_ENV) {
+            config.set_endpoints(endpoints.split(',').map(str::to_string).collect());
+        }
+        Ok(config)
+    }
+
+    fn apply_json(&mut self, contents: &str) -> io::Result<()> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let config = serde_json::from_str::<Value>(contents)?;
+
+        if let Some(endpoints) = config["endpoints"].as_array() {
+            self.set_endpoints(
+                endpoints
+                    .iter()
+                    .map(|endpoint| endpoint.as_str().map(str::to_string))
+                    .collect::<Option<Vec<String>>>()
+                    .ok_or_else(|| invalid("endpoints must be strings".to_string()))?,
+            );
+        }
+        if let Some(commitment) = config["commitment"].as_str() {
+            self.commitment = CommitmentConfig {
+                commitment: CommitmentLevel::from_str(commitment)
+                    .map_err(|e| invalid(format!("commitment: {:?}", e)))?,
+            };
+        }
+        if let Some(timeout) = config["timeout_secs"].as_u64() {
+            self.timeout = Duration::from_secs(timeout);
+        }
+        if let Some(max_retries) = config["max_retries"].as_u64() {
+            self.max_retries = max_retries as u32;
+        }
+        if let Some(initial_backoff) = config["initial_backoff_ms"].as_u64() {
+            self.initial_backoff = Duration::from_millis(initial_backoff);
+        }
+        if let Some(max_backoff) = config["max_backoff_ms"].as_u64() {
+            self.max_backoff = Duration::from_millis(max_backoff);
+        }
+        if let Some(requests_per_second) = config["requests_per_second"].as_f64() {
+            self.requests_per_second = Some(requests_per_second).filter(|rate| *rate > 0.0);
+        }
+        Ok(())
+    }
+
+    /// Replaces the endpoints, keeping the current ones if `endpoints` has none.
+    pub fn set_endpoints(&mut self, endpoints: Vec<String>) {
+        let endpoints: Vec<String> = endpoints
+            .into_iter()
+            .map(|endpoint| endpoint.trim().to_string())
+            .filter(|endpoint| !endpoint.is_empty())
+            .collect();
+        if !endpoints.is_empty() {
+            self.endpoints = endpoints;
+        }
+    }
+}
+
+/// Rate limiting, failover and backoff, shared by the blocking and async clients.
+pub(crate) struct Scheduler {
+    config: RpcConfig,
+    endpoint: AtomicUsize,
+    next_request: Mutex<Instant>,
+}
+
+impl Scheduler {
+    pub(crate) fn new(config: RpcConfig) -> Self {
+        Scheduler {
+            config,
+            endpoint: AtomicUsize::new(0),
+            next_request: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub(crate) fn config(&self) -> &RpcConfig {
+        &self.config
+    }
+
+    pub(crate) fn endpoint(&self) -> usize {
+        self.endpoint.load(Ordering::Relaxed)
+    }
+
+    /// How long to wait before sending to stay under `requests_per_second`, reserving the slot.
+    pub(crate) fn pace(&self) -> Duration {
+        let Some(requests_per_second) = self.config.requests_per_second else {
+            return Duration::ZERO;
+        };
+        let mut next_request = self.next_request.lock().unwrap();
+        let now = Instant::now();
+        let send_at = (*next_request).max(now);
+        *next_request = send_at + Duration::from_secs_f64(1.0 / requests_per_second);
+        send_at - now
+    }
+
+    /// Moves past `failed` after the `attempt`th failure of a request, returning how long to
+    /// back off. Another endpoint is tried straight away, the backoff only starts once every
+    /// endpoint has failed in turn and doubles with each round.
+    pub(crate) fn fail_over(&self, failed: usize, attempt: u32) -> Duration {
+        let endpoints = self.config.endpoints.len() as u32;
+        let _ = self.endpoint.compare_exchange(
+            failed,
+            (failed + 1) % endpoints as usize,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+        if !attempt.is_multiple_of(endpoints) {
+            return Duration::ZERO;
+        }
+        let round = (attempt / endpoints).min(16);
+        (self.config.initial_backoff * 2u32.pow(round - 1)).min(self.config.max_backoff)
+    }
+}
+
+/// Rate limiting (429), server errors (5xx), dropped connections and the node-side errors that
+/// clear up on their own or on another node.
+pub fn is_retryable(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) => true,
+        ClientErrorKind::Reqwest(e) => match e.status() {
+            Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            None => e.is_timeout() || e.is_connect() || e.is_request(),
+        },
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => matches!(
+            *code,
+            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+                | JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+                | JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET
+        ),
+        _ => false,
+    }
+}
+
+/// `getTransaction`, `getBlock` and `getBlocks` reject `processed`.
+pub(crate) fn at_least_confirmed(commitment: CommitmentConfig) -> CommitmentConfig {
+    match commitment.is_at_least_confirmed() {
+        true => commitment,
+        false => CommitmentConfig::confirmed(),
+    }
+}
+
+pub(crate) fn transaction_config(
+    encoding: UiTransactionEncoding,
+    commitment: CommitmentConfig,
+) -> RpcTransactionConfig {
+    RpcTransactionConfig {
+        encoding: Some(encoding),
+        commitment: Some(at_least_confirmed(commitment)),
+        max_supported_transaction_version: Some(0),
+    }
+}
+
+pub(crate) fn block_config(commitment: CommitmentConfig) -> RpcBlockConfig {
+    RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        transaction_details: Some(TransactionDetails::Full),
+        rewards: Some(false),
+        commitment: Some(at_least_confirmed(commitment)),
+        max_supported_transaction_version: Some(0),
+    }
+}
+
+pub(crate) fn batch_body(requests: &[(RpcRequest, Value)]) -> Value {
+    Value::Array(
+        requests
+            .iter()
+            .enumerate()
+            .map(|(id, (request, params))| request.build_request_json(id as u64, params.clone()))
+            .collect(),
+    )
+}
+
+/// Splits a batch response back into one result per request, in request order.
+pub(crate) fn batch_results(response: Value, len: usize) -> ClientResult<Vec<ClientResult<Value>>> {
+    let Value::Array(responses) = response else {
+        // A batch rejected as a whole comes back as a single error object.
+        return Err(response_error(&response["error"]).into());
+    };
+
+    let mut results: Vec<ClientResult<Value>> = (0..len)
+        .map(|_| Err(RpcError::ParseError("a response for every request".to_string()).into()))
+        .collect();
+    for mut response in responses {
+        let Some(result) = response["id"].as_u64().and_then(|id| results.get_mut(id as usize)) else {
+            continue;
+        };
+        *result = match response.get("error") {
+            Some(error) if !error.is_null() => Err(response_error(error).into()),
+            _ => Ok(response["result"].take()),
+        };
+    }
+    Ok(results)
+}
+
+fn response_error(error: &Value) -> RpcError {
+    RpcError::RpcResponseError {
+        code: error["code"].as_i64().unwrap_or_default(),
+        message: error["message"].as_str().unwrap_or_default().to_string(),
+        data: RpcResponseErrorData::Empty,
+    }
+}
+
+pub(crate) fn batch_transaction(
+    result: ClientResult<Value>,
+) -> ClientResult<Option<EncodedConfirmedTransactionWithStatusMeta>> {
+    Ok(serde_json::from_value(result?)?)
+}
+
+/// A blocking JSON-RPC client over one or more endpoints that retries with backoff, fails
+/// over between endpoints and paces requests. Clones share the endpoints and rate limit.
+#[derive(Clone)]
+pub struct ResilientRpcClient {
+    clients: Arc<Vec<RpcClient>>,
+    scheduler: Arc<Scheduler>,
+    // Built on first use, creating one inside an async runtime would panic.
+    http: Arc<OnceLock<reqwest::blocking::Client>>,
+}
+
+impl ResilientRpcClient {
+    pub fn new(config: RpcConfig) -> Self {
+        let clients = config
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                RpcClient::new_with_timeout_and_commitment(endpoint, config.timeout, config.commitment)
+            })
+            .collect();
+        ResilientRpcClient {
+            clients: Arc::new(clients),
+            scheduler: Arc::new(Scheduler::new(config)),
+            http: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.scheduler.config().commitment
+    }
+
+    /// Runs `request` against the current endpoint, retrying errors `is_retryable` accepts.
+    pub fn call<T>(&self, request: impl Fn(&RpcClient) -> ClientResult<T>) -> ClientResult<T> {
+        let mut attempt = 0;
+        loop {
+            thread::sleep(self.scheduler.pace());
+            let endpoint = self.scheduler.endpoint();
+            match request(&self.clients[endpoint]) {
+                Err(e) if is_retryable(&e) && attempt < self.scheduler.config().max_retries => {
+                    attempt += 1;
+                    let backoff = self.scheduler.fail_over(endpoint, attempt);
+                    log::warn!(
+                        "{} failed: {}, retrying in {:?}",
+                        self.scheduler.config().endpoints[endpoint],
+                        e,
+                        backoff
+                    );
+                    thread::sleep(backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub fn get_transaction(
+        &self,
+        signature: &Signature,
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<EncodedConfirmedTransactionWithStatusMeta> {
+        let config = transaction_config(encoding, self.commitment());
+        self.call(|rpc_client| rpc_client.get_transaction_with_config(signature, config))
+    }
+
+    /// `get_transaction` that returns `None` for a signature the node does not have, where
+    /// `RpcClient` fails to deserialize the `null` result.
+    pub fn find_transaction(
+        &self,
+        signature: &Signature,
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<Option<EncodedConfirmedTransactionWithStatusMeta>> {
+        let config = transaction_config(encoding, self.commitment());
+        self.call(|rpc_client| rpc_client.send(RpcRequest::GetTransaction, json!([signature.to_string(), config])))
+    }
+
+    pub fn get_block(&self, slot: u64) -> ClientResult<UiConfirmedBlock> {
+        let config = block_config(self.commitment());
+        self.call(|rpc_client| rpc_client.get_block_with_config(slot, config))
+    }
+
+    pub fn get_blocks(&self, start_slot: u64, end_slot: u64) -> ClientResult<Vec<u64>> {
+        let commitment = at_least_confirmed(self.commitment());
+        self.call(|rpc_client| rpc_client.get_blocks_with_commitment(start_slot, Some(end_slot), commitment))
+    }
+
+    pub fn get_slot(&self) -> ClientResult<u64> {
+        self.call(|rpc_client| rpc_client.get_slot_with_commitment(self.commitment()))
+    }
+
+    pub fn get_account_data(&self, address: &Pubkey) -> ClientResult<Vec<u8>> {
+        self.call(|rpc_client| rpc_client.get_account_data(address))
+    }
+
+    /// At most 100 addresses, `None` for accounts that do not exist.
+    pub fn get_multiple_accounts(&self, addresses: &[Pubkey]) -> ClientResult<Vec<Option<Account>>> {
+        self.call(|rpc_client| rpc_client.get_multiple_accounts(addresses))
+    }
+
+    pub fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize,
+    ) -> ClientResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        self.call(|rpc_client| {
+            rpc_client.get_signatures_for_address_with_config(
+                address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until,
+                    limit: Some(limit),
+                    commitment: Some(at_least_confirmed(self.commitment())),
+                },
+            )
+        })
+    }
+
+    /// Sends `requests` as one JSON-RPC batch. The batch as a whole is retried like any other
+    /// request, each entry's own error is returned in its place.
+    pub fn batch(&self, requests: &[(RpcRequest, Value)]) -> ClientResult<Vec<ClientResult<Value>>> {
+        let body = batch_body(requests).to_string();
+        let http = self.http.get_or_init(|| {
+            reqwest::blocking::Client::builder()
+                .timeout(self.scheduler.config().timeout)
+                .build()
+                .unwrap_or_default()
+        });
+        let response = self.call(|rpc_client| {
+            let response = http
+                .post(rpc_client.url())
+                .header("Content-Type", "application/json")
+                .body(body.clone())
+                .send()?
+                .error_for_status()?;
+            Ok(response.json::<Value>()?)
+        })?;
+        batch_results(response, requests.len())
+    }
+
+    /// `getTransaction` for every signature in one batch, `None` for unknown signatures.
+    pub fn get_transactions(
+        &self,
+        signatures: &[Signature],
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<Vec<ClientResult<Option<EncodedConfirmedTransactionWithStatusMeta>>>> {
+        let config = transaction_config(encoding, self.commitment());
+        let requests: Vec<(RpcRequest, Value)> = signatures
+            .iter()
+            .map(|signature| (RpcRequest::GetTransaction, json!([signature.to_string(), config])))
+            .collect();
+        Ok(self.batch(&requests)?.into_iter().map(batch_transaction).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU32;
+    use super::stub::{self, StubRpc};
+    use super::*;
+
+    fn client(endpoints: &[&StubRpc], initial_backoff: Duration) -> ResilientRpcClient {
+        ResilientRpcClient::new(RpcConfig {
+            endpoints: endpoints.iter().map(|stub| stub.url.clone()).collect(),
+            max_retries: 3,
+            initial_backoff,
+            max_backoff: Duration::from_secs(10),
+            ..RpcConfig::default()
+        })
+    }
+
+    fn batch_reply(request: &Value, result: impl Fn(&Value) -> Value) -> (u16, Value) {
+        let replies = request.as_array().unwrap().iter().map(|request| stub::result(request, result(request)).1);
+        (200, Value::Array(replies.collect()))
+    }
+
+    #[test]
+    fn retries_rate_limits_and_server_errors_with_backoff() {
+        let calls = AtomicU32::new(0);
+        let rpc = StubRpc::start(move |request| match calls.fetch_add(1, Ordering::Relaxed) {
+            0 => (429, json!("Too many requests")),
+            1 => (503, json!("Service unavailable")),
+            _ => batch_reply(request, |_| json!(42)),
+        });
+        let rpc_client = client(&[&rpc], Duration::from_millis(100));
+
+        let started = Instant::now();
+        let results = rpc_client.batch(&[(RpcRequest::GetSlot, json!([]))]).unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap(), &json!(42));
+        assert_eq!(rpc.requests().len(), 3);
+        // 100ms after the 429, doubled to 200ms after the 503.
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let rpc = StubRpc::start(|_| (500, json!("Internal error")));
+        let rpc_client = client(&[&rpc], Duration::from_millis(1));
+
+        let error = rpc_client.get_slot().unwrap_err();
+
+        assert!(is_retryable(&error));
+        assert_eq!(rpc.requests().len(), 4);
+    }
+
+    #[test]
+    fn does_not_retry_other_errors() {
+        let rpc = StubRpc::start(|request| stub::error(request, -32602, "Invalid params"));
+        let rpc_client = client(&[&rpc], Duration::from_millis(1));
+
+        assert!(rpc_client.get_slot().is_err());
+        assert_eq!(rpc.requests().len(), 1);
+    }
+
+    #[test]
+    fn fails_over_to_the_next_endpoint() {
+        let down = StubRpc::start(|_| (503, json!("Service unavailable")));
+        let up = StubRpc::start(|request| stub::result(request, json!(42)));
+        // Long enough to fail the test if the first failure backed off.
+        let rpc_client = client(&[&down, &up], Duration::from_secs(10));
+
+        let started = Instant::now();
+        assert_eq!(rpc_client.get_slot().unwrap(), 42);
+        assert_eq!(rpc_client.get_slot().unwrap(), 42);
+
+        assert!(started.elapsed() < Duration::from_secs(5));
+        // The second call stays on the endpoint that worked.
+        assert_eq!(down.methods(), ["getSlot"]);
+        assert_eq!(up.methods(), ["getSlot", "getSlot"]);
+    }
+
+    #[test]
+    fn batch_results_keep_request_order_and_per_item_errors() {
+        // Replies out of order, with an error in the middle.
+        let rpc = StubRpc::start(|request| {
+            let mut replies: Vec<Value> = request
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|request| match request["params"][0].as_str() {
+                    Some("fail") => stub::error(request, -32009, "Slot skipped").1,
+                    address => stub::result(request, json!(address)).1,
+                })
+                .collect();
+            replies.reverse();
+            (200, Value::Array(replies))
+        });
+        let rpc_client = client(&[&rpc], Duration::from_millis(1));
+
+        let requests: Vec<(RpcRequest, Value)> = ["first", "fail", "last"]
+            .iter()
+            .map(|address| (RpcRequest::GetBalance, json!([address])))
+            .collect();
+        let results = rpc_client.batch(&requests).unwrap();
+
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap(), "first");
+        match results[1].as_ref().unwrap_err().kind() {
+            ClientErrorKind::RpcError(RpcError::RpcResponseError { code, message, .. }) => {
+                assert_eq!(*code, -32009);
+                assert_eq!(message, "Slot skipped");
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(results[2].as_ref().unwrap(), "last");
+    }
+
+    #[test]
+    fn batch_results_report_missing_entries_and_rejected_batches() {
+        let results = batch_results(json!([{"jsonrpc": "2.0", "id": 1, "result": null}]), 2).unwrap();
+        assert!(results[0].is_err());
+        assert_eq!(results[1].as_ref().unwrap(), &Value::Null);
+
+        let rejected = json!({"jsonrpc": "2.0", "id": null, "error": {"code": -32600, "message": "Invalid request"}});
+        assert!(batch_results(rejected, 2).is_err());
+    }
+
+    #[test]
+    fn unknown_signatures_in_a_batch_are_none() {
+        let rpc = StubRpc::start(|request| batch_reply(request, |_| Value::Null));
+        let rpc_client = client(&[&rpc], Duration::from_millis(1));
+
+        let signatures = [Signature::new_unique(), Signature::new_unique()];
+        let transactions = rpc_client.get_transactions(&signatures, UiTransactionEncoding::Base64).unwrap();
+
+        assert_eq!(transactions.len(), 2);
+        assert!(transactions.iter().all(|transaction| matches!(transaction, Ok(None))));
+        let batch = &rpc.requests()[0];
+        assert_eq!(batch[1]["method"], "getTransaction");
+        assert_eq!(batch[1]["params"][0], signatures[1].to_string());
+    }
+}
@@ -0,0 +1,183 @@
+use std::future::Future;
+use std::sync::Arc;
+use serde_json::{json, Value};
+use solana_client::client_error::reqwest;
+use solana_client::client_error::Result as ClientResult;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_request::RpcRequest;
+use solana_client::rpc_response::RpcConfirmedTransactionStatusWithSignature;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{
+    EncodedConfirmedTransactionWithStatusMeta, UiConfirmedBlock, UiTransactionEncoding,
+};
+use super::{
+    at_least_confirmed, batch_body, batch_results, batch_transaction, block_config, is_retryable,
+    transaction_config, RpcConfig, Scheduler,
+};
+
+/// The async counterpart of `rpc::ResilientRpcClient`, with the same retry, failover and rate
+/// limiting behaviour.
+#[derive(Clone)]
+pub struct ResilientRpcClient {
+    clients: Arc<Vec<Arc<RpcClient>>>,
+    scheduler: Arc<Scheduler>,
+    http: reqwest::Client,
+}
+
+impl ResilientRpcClient {
+    pub fn new(config: RpcConfig) -> Self {
+        let clients = config
+            .endpoints
+            .iter()
+            .map(|endpoint| {
+                Arc::new(RpcClient::new_with_timeout_and_commitment(
+                    endpoint.clone(),
+                    config.timeout,
+                    config.commitment,
+                ))
+            })
+            .collect();
+        let http = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .unwrap_or_default();
+        ResilientRpcClient {
+            clients: Arc::new(clients),
+            scheduler: Arc::new(Scheduler::new(config)),
+            http,
+        }
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        self.scheduler.config().commitment
+    }
+
+    /// Runs `request` against the current endpoint, retrying errors `rpc::is_retryable` accepts.
+    pub async fn call<T, F>(&self, request: impl Fn(Arc<RpcClient>) -> F) -> ClientResult<T>
+    where
+        F: Future<Output = ClientResult<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            tokio::time::sleep(self.scheduler.pace()).await;
+            let endpoint = self.scheduler.endpoint();
+            match request(self.clients[endpoint].clone()).await {
+                Err(e) if is_retryable(&e) && attempt < self.scheduler.config().max_retries => {
+                    attempt += 1;
+                    let backoff = self.scheduler.fail_over(endpoint, attempt);
+                    log::warn!(
+                        "{} failed: {}, retrying in {:?}",
+                        self.scheduler.config().endpoints[endpoint],
+                        e,
+                        backoff
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    pub async fn get_transaction(
+        &self,
+        signature: &Signature,
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<EncodedConfirmedTransactionWithStatusMeta> {
+        let config = transaction_config(encoding, self.commitment());
+        self.call(|rpc_client| async move {
+            rpc_client.get_transaction_with_config(signature, config).await
+        })
+        .await
+    }
+
+    pub async fn get_block(&self, slot: u64) -> ClientResult<UiConfirmedBlock> {
+        let config = block_config(self.commitment());
+        self.call(|rpc_client| async move { rpc_client.get_block_with_config(slot, config).await })
+            .await
+    }
+
+    pub async fn get_blocks(&self, start_slot: u64, end_slot: u64) -> ClientResult<Vec<u64>> {
+        let commitment = at_least_confirmed(self.commitment());
+        self.call(|rpc_client| async move {
+            rpc_client
+                .get_blocks_with_commitment(start_slot, Some(end_slot), commitment)
+                .await
+        })
+        .await
+    }
+
+    pub async fn get_slot(&self) -> ClientResult<u64> {
+        let commitment = self.commitment();
+        self.call(|rpc_client| async move { rpc_client.get_slot_with_commitment(commitment).await })
+            .await
+    }
+
+    pub async fn get_account_data(&self, address: &Pubkey) -> ClientResult<Vec<u8>> {
+        self.call(|rpc_client| async move { rpc_client.get_account_data(address).await })
+            .await
+    }
+
+    pub async fn get_signatures_for_address(
+        &self,
+        address: &Pubkey,
+        before: Option<Signature>,
+        until: Option<Signature>,
+        limit: usize,
+    ) -> ClientResult<Vec<RpcConfirmedTransactionStatusWithSignature>> {
+        let commitment = at_least_confirmed(self.commitment());
+        self.call(|rpc_client| async move {
+            rpc_client
+                .get_signatures_for_address_with_config(
+                    address,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: Some(limit),
+                        commitment: Some(commitment),
+                    },
+                )
+                .await
+        })
+        .await
+    }
+
+    /// Sends `requests` as one JSON-RPC batch. The batch as a whole is retried like any other
+    /// request, each entry's own error is returned in its place.
+    pub async fn batch(
+        &self,
+        requests: &[(RpcRequest, Value)],
+    ) -> ClientResult<Vec<ClientResult<Value>>> {
+        let body = batch_body(requests).to_string();
+        let response = self
+            .call(|rpc_client| {
+                let request = self
+                    .http
+                    .post(rpc_client.url())
+                    .header("Content-Type", "application/json")
+                    .body(body.clone());
+                async move {
+                    let response = request.send().await?.error_for_status()?;
+                    Ok(response.json::<Value>().await?)
+                }
+            })
+            .await?;
+        batch_results(response, requests.len())
+    }
+
+    /// `getTransaction` for every signature in one batch, `None` for unknown signatures.
+    pub async fn get_transactions(
+        &self,
+        signatures: &[Signature],
+        encoding: UiTransactionEncoding,
+    ) -> ClientResult<Vec<ClientResult<Option<EncodedConfirmedTransactionWithStatusMeta>>>> {
+        let config = transaction_config(encoding, self.commitment());
+        let requests: Vec<(RpcRequest, Value)> = signatures
+            .iter()
+            .map(|signature| (RpcRequest::GetTransaction, json!([signature.to_string(), config])))
+            .collect();
+        Ok(self.batch(&requests).await?.into_iter().map(batch_transaction).collect())
+    }
+}
@@ -0,0 +1,119 @@
+//! A local JSON-RPC server for tests, answering each request body with a handler.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use base64::Engine;
+use serde_json::{json, Value};
+use solana_sdk::hash::Hash;
+use solana_sdk::instruction::Instruction;
+use solana_sdk::message::Message;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_sdk::transaction::Transaction;
+use tiny_http::{Header, Response, Server};
+
+type Handler = dyn Fn(&Value) -> (u16, Value) + Send + Sync;
+
+pub(crate) struct StubRpc {
+    pub url: String,
+    server: Arc<Server>,
+    requests: Arc<Mutex<Vec<Value>>>,
+}
+
+impl StubRpc {
+    /// `handler` gets each request body, a single call or a batch, and returns the status and
+    /// body to reply with.
+    pub fn start(handler: impl Fn(&Value) -> (u16, Value) + Send + Sync + 'static) -> Self {
+        let server = Arc::new(Server::http("127.0.0.1:0").unwrap());
+        let url = format!("http://{}", server.server_addr());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let (server_clone, requests_clone) = (server.clone(), requests.clone());
+        thread::spawn(move || {
+            for mut request in server_clone.incoming_requests() {
+                let body: Value = match serde_json::from_reader(request.as_reader()) {
+                    Ok(body) => body,
+                    Err(_) => Value::Null,
+                };
+                let (status, reply) = handler(&body);
+                requests_clone.lock().unwrap().push(body);
+                let content_type = Header::from_bytes("Content-Type", "application/json").unwrap();
+                let response = Response::from_string(reply.to_string())
+                    .with_status_code(status)
+                    .with_header(content_type);
+                let _ = request.respond(response);
+            }
+        });
+
+        StubRpc { url, server, requests }
+    }
+
+    /// Every request body received so far, in order.
+    pub fn requests(&self) -> Vec<Value> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The method of every single call received so far, batches are skipped.
+    pub fn methods(&self) -> Vec<String> {
+        self.requests()
+            .iter()
+            .filter_map(|request| request["method"].as_str().map(str::to_string))
+            .collect()
+    }
+}
+
+impl Drop for StubRpc {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+/// The successful reply to a single `request`.
+pub(crate) fn result(request: &Value, result: Value) -> (u16, Value) {
+    (200, json!({"jsonrpc": "2.0", "id": request["id"], "result": result}))
+}
+
+/// The JSON-RPC error reply to a single `request`.
+pub(crate) fn error(request: &Value, code: i64, message: &str) -> (u16, Value) {
+    (200, json!({"jsonrpc": "2.0", "id": request["id"], "error": {"code": code, "message": message}}))
+}
+
+/// A transaction calling `program`, as `getBlock` lists it.
+pub(crate) fn transaction(program: &Pubkey) -> (Signature, Value) {
+    let payer = Pubkey::new_unique();
+    let instruction = Instruction::new_with_bytes(*program, &[], vec![]);
+    let mut transaction = Transaction::new_unsigned(Message::new(&[instruction], Some(&payer)));
+    transaction.signatures[0] = Signature::new_unique();
+    let accounts = transaction.message.account_keys.len();
+    let encoded = base64::engine::general_purpose::STANDARD.encode(bincode::serialize(&transaction).unwrap());
+
+    let json = json!({
+        "transaction": [encoded, "base64"],
+        "meta": {
+            "err": null,
+            "status": {"Ok": null},
+            "fee": 5000,
+            "preBalances": vec![1_000_000; accounts],
+            "postBalances": vec![1_000_000; accounts],
+            "innerInstructions": [],
+            "logMessages": [],
+            "preTokenBalances": [],
+            "postTokenBalances": [],
+            "rewards": [],
+        },
+    });
+    (transaction.signatures[0], json)
+}
+
+/// A `getBlock` result listing `transactions`.
+pub(crate) fn block(slot: u64, transactions: Vec<Value>) -> Value {
+    json!({
+        "blockhash": Hash::new_unique().to_string(),
+        "previousBlockhash": Hash::new_unique().to_string(),
+        "parentSlot": slot - 1,
+        "transactions": transactions,
+        "blockTime": null,
+        "blockHeight": null,
+    })
+}
@@ -0,0 +1,459 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read};
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+use serde::Serialize;
+use serde_json::json;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE, JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED,
+    JSON_RPC_SERVER_ERROR_SLOT_SKIPPED,
+};
+use solana_client::rpc_request::RpcError;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::UiTransactionEncoding;
+use tiny_http::{Header, Method, Request, Response};
+use crate::lookup_table::{AddressLookupTableResolver, RpcLookupTableSource};
+use crate::offline;
+use crate::pipeline;
+use crate::rpc::ResilientRpcClient;
+
+/// The default `max_body_size`, a getTransaction response is rarely over 100 KB.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+struct Reply {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(value: &impl Serialize) -> Reply {
+        match serde_json::to_vec(value) {
+            Ok(body) => Reply { status: 200, content_type: "application/json", body },
+            Err(e) => Reply::error(500, e),
+        }
+    }
+
+    fn error(status: u16, message: impl ToString) -> Reply {
+        Reply {
+            status,
+            content_type: "application/json",
+            body: json!({ "error": message.to_string() }).to_string().into_bytes(),
+        }
+    }
+}
+
+/// Parsed transactions by signature, the oldest entry is evicted once `capacity` is reached.
+struct Cache {
+    capacity: usize,
+    entries: HashMap<String, Vec<u8>>,
+    order: VecDeque<String>,
+}
+
+impl Cache {
+    fn get(&self, signature: &str) -> Option<Vec<u8>> {
+        self.entries.get(signature).cloned()
+    }
+
+    fn insert(&mut self, signature: String, body: Vec<u8>) {
+        if self.capacity == 0 || self.entries.contains_key(&signature) {
+            return;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.order.push_back(signature.clone());
+        self.entries.insert(signature, body);
+    }
+}
+
+#[derive(Default)]
+struct Metrics {
+    /// Requests by route and status.
+    requests: HashMap<(&'static str, u16), u64>,
+    /// Summed request time by route.
+    seconds: HashMap<&'static str, f64>,
+    cache_hits: u64,
+    cache_misses: u64,
+}
+
+/// Serves the pipeline over HTTP:
+///
+/// - `POST /parse` parses a getTransaction response in the body, the same shapes the `file`
+///   command reads. One transaction gives one object, several an array with `null` for any that
+///   failed to parse.
+/// - `GET /tx/{signature}` fetches and parses a transaction, keeping `cache_size` results.
+/// - `GET /block/{slot}` fetches and parses a block.
+/// - `GET /health` and `GET /metrics`, the latter in the Prometheus text format.
+///
+/// Parsed output is the JSON described in `docs/json-output.md`, errors are
+/// `{"error": "<message>"}`.
+pub struct Server {
+    /// Larger `POST /parse` bodies are rejected with 413.
+    pub max_body_size: usize,
+    rpc_client: ResilientRpcClient,
+    lookup_table_resolver: AddressLookupTableResolver,
+    cache: Mutex<Cache>,
+    metrics: Mutex<Metrics>,
+}
+
+impl Server {
+    pub fn new(rpc_client: ResilientRpcClient, cache_size: usize) -> Self {
+        let lookup_table_resolver = AddressLookupTableResolver::new(RpcLookupTableSource::new(rpc_client.clone()));
+        Server {
+            max_body_size: MAX_BODY_SIZE,
+            rpc_client,
+            lookup_table_resolver,
+            cache: Mutex::new(Cache { capacity: cache_size, entries: HashMap::new(), order: VecDeque::new() }),
+            metrics: Mutex::new(Metrics::default()),
+        }
+    }
+
+    /// Listens on `address`, e.g. `127.0.0.1:8080`, handling requests on `threads` threads
+    /// until the process is stopped.
+    pub fn run(self, address: &str, threads: usize) -> io::Result<()> {
+        let http = Arc::new(tiny_http::Server::http(address).map_err(io::Error::other)?);
+        let server = Arc::new(self);
+        eprintln!("Listening on {}", address);
+
+        let workers: Vec<_> = (0..threads.max(1))
+            .map(|_| {
+                let http = http.clone();
+                let server = server.clone();
+                thread::spawn(move || loop {
+                    match http.recv() {
+                        Ok(request) => server.handle(request),
+                        Err(e) => eprintln!("Error receiving request: {:?}", e),
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            let _ = worker.join();
+        }
+        Ok(())
+    }
+
+    fn handle(&self, mut request: Request) {
+        let start = Instant::now();
+        let path = request.url().split('?').next().unwrap_or_default().to_string();
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+        let method = request.method().clone();
+        let (route, reply) = match (&method, segments.as_slice()) {
+            (Method::Post, ["parse"]) => ("parse", guarded(|| self.parse(&mut request))),
+            (Method::Get, ["tx", signature]) => ("tx", guarded(|| self.transaction(signature))),
+            (Method::Get, ["block", slot]) => ("block", guarded(|| self.block(slot))),
+            (Method::Get, ["health"]) => ("health", Reply::json(&json!({ "status": "ok" }))),
+            (Method::Get, ["metrics"]) => ("metrics", self.render_metrics()),
+            (_, ["parse"] | ["tx", _] | ["block", _] | ["health"] | ["metrics"]) => {
+                ("other", Reply::error(405, "method not allowed"))
+            }
+            _ => ("other", Reply::error(404, format!("no route for {}", path))),
+        };
+
+        if let Ok(mut metrics) = self.metrics.lock() {
+            *metrics.requests.entry((route, reply.status)).or_default() += 1;
+            *metrics.seconds.entry(route).or_default() += start.elapsed().as_secs_f64();
+        }
+        let content_type = Header::from_bytes("Content-Type", reply.content_type).unwrap();
+        let response = Response::from_data(reply.body).with_status_code(reply.status).with_header(content_type);
+        if let Err(e) = request.respond(response) {
+            eprintln!("Error responding to {}: {:?}", path, e);
+        }
+    }
+
+    fn parse(&self, request: &mut Request) -> Reply {
+        if request.body_length().is_some_and(|length| length > self.max_body_size) {
+            return Reply::error(413, "body too large");
+        }
+        let mut body = String::new();
+        if let Err(e) = request.as_reader().take(self.max_body_size as u64 + 1).read_to_string(&mut body) {
+            return Reply::error(400, e);
+        }
+        if body.len() > self.max_body_size {
+            return Reply::error(413, "body too large");
+        }
+
+        let txs = match offline::transactions_from_json(&body) {
+            Ok(txs) => txs,
+            Err(e) => return Reply::error(400, e),
+        };
+        let parsed: Vec<_> = txs
+            .iter()
+            .map(|tx| pipeline::parse_confirmed_transaction(tx, Some(&self.lookup_table_resolver)))
+            .collect();
+        match parsed.as_slice() {
+            [] => Reply::error(400, "no transaction in body"),
+            [None] => Reply::error(422, "transaction could not be parsed"),
+            [Some(parsed)] => Reply::json(parsed),
+            _ => Reply::json(&parsed),
+        }
+    }
+
+    fn transaction(&self, signature: &str) -> Reply {
+        let cached = self.cache.lock().ok().and_then(|cache| cache.get(signature));
+        if let Ok(mut metrics) = self.metrics.lock() {
+            match cached {
+                Some(_) => metrics.cache_hits += 1,
+                None => metrics.cache_misses += 1,
+            }
+        }
+        if let Some(body) = cached {
+            return Reply { status: 200, content_type: "application/json", body };
+        }
+
+        let transaction_signature = match Signature::from_str(signature) {
+            Ok(transaction_signature) => transaction_signature,
+            Err(e) => return Reply::error(400, format!("invalid signature: {}", e)),
+        };
+        let tx = match self.rpc_client.find_transaction(&transaction_signature, UiTransactionEncoding::Base64) {
+            Ok(Some(tx)) => tx,
+            Ok(None) => return Reply::error(404, format!("transaction {} not found", signature)),
+            Err(e) => return Reply::error(502, e),
+        };
+
+        let Some(parsed) = pipeline::parse_confirmed_transaction(&tx, Some(&self.lookup_table_resolver)) else {
+            return Reply::error(422, "transaction could not be parsed");
+        };
+        let reply = Reply::json(&parsed);
+        // Only confirmed transactions are fetched, so the result does not change.
+        if reply.status == 200 {
+            if let Ok(mut cache) = self.cache.lock() {
+                cache.insert(signature.to_string(), reply.body.clone());
+            }
+        }
+        reply
+    }
+
+    fn block(&self, slot: &str) -> Reply {
+        let slot = match u64::from_str(slot) {
+            Ok(slot) => slot,
+            Err(e) => return Reply::error(400, format!("invalid slot: {}", e)),
+        };
+        match pipeline::fetch_block(&self.rpc_client, slot, Some(&self.lookup_table_resolver)) {
+            Ok(parsed_block) => Reply::json(&parsed_block),
+            Err(e) if is_missing_block(&e) => Reply::error(404, e),
+            Err(e) => Reply::error(502, e),
+        }
+    }
+
+    fn render_metrics(&self) -> Reply {
+        let mut body = String::new();
+        if let Ok(metrics) = self.metrics.lock() {
+            let mut requests: Vec<_> = metrics.requests.iter().collect();
+            requests.sort();
+            body.push_str("# TYPE solana_parser_requests_total counter\n");
+            for ((route, status), count) in requests {
+                body.push_str(&format!(
+                    "solana_parser_requests_total{{route=\"{}\",status=\"{}\"}} {}\n",
+                    route, status, count
+                ));
+            }
+            let mut seconds: Vec<_> = metrics.seconds.iter().collect();
+            seconds.sort_by(|a, b| a.0.cmp(b.0));
+            body.push_str("# TYPE solana_parser_request_seconds_total counter\n");
+            for (route, seconds) in seconds {
+                body.push_str(&format!("solana_parser_request_seconds_total{{route=\"{}\"}} {}\n", route, seconds));
+            }
+            body.push_str("# TYPE solana_parser_cache_hits_total counter\n");
+            body.push_str(&format!("solana_parser_cache_hits_total {}\n", metrics.cache_hits));
+            body.push_str("# TYPE solana_parser_cache_misses_total counter\n");
+            body.push_str(&format!("solana_parser_cache_misses_total {}\n", metrics.cache_misses));
+        }
+        if let Ok(cache) = self.cache.lock() {
+            body.push_str("# TYPE solana_parser_cache_entries gauge\n");
+            body.push_str(&format!("solana_parser_cache_entries {}\n", cache.entries.len()));
+        }
+        Reply { status: 200, content_type: "text/plain; version=0.0.4", body: body.into_bytes() }
+    }
+}
+
+/// Runs a handler, turning a panic into a 500 so the worker thread survives it.
+fn guarded(handler: impl FnOnce() -> Reply) -> Reply {
+    panic::catch_unwind(AssertUnwindSafe(handler)).unwrap_or_else(|_| Reply::error(500, "internal error"))
+}
+
+/// Skipped slots and blocks the node no longer has.
+fn is_missing_block(error: &ClientError) -> bool {
+    matches!(
+        error.kind(),
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. })
+            if matches!(
+                *code,
+                JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+                    | JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED
+                    | JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+            )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+    use solana_client::client_error::reqwest::blocking::Client;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::rpc::stub::{self, block, transaction, StubRpc};
+    use crate::rpc::RpcConfig;
+    use super::*;
+
+    /// Serves `server` on a free local port, returning its base URL.
+    fn start(server: Server) -> String {
+        let http = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", http.server_addr());
+        thread::spawn(move || {
+            for request in http.incoming_requests() {
+                server.handle(request);
+            }
+        });
+        url
+    }
+
+    fn server(rpc: &StubRpc) -> Server {
+        let rpc_client = ResilientRpcClient::new(RpcConfig {
+            endpoints: vec![rpc.url.clone()],
+            max_retries: 0,
+            ..RpcConfig::default()
+        });
+        Server::new(rpc_client, 10)
+    }
+
+    /// A transaction as `getTransaction` returns it.
+    fn confirmed_transaction(slot: u64) -> (Signature, Value) {
+        let (signature, mut tx) = transaction(&Pubkey::new_unique());
+        tx["slot"] = json!(slot);
+        tx["blockTime"] = Value::Null;
+        (signature, tx)
+    }
+
+    fn get(url: &str) -> (u16, String) {
+        let response = Client::new().get(url).send().unwrap();
+        (response.status().as_u16(), response.text().unwrap())
+    }
+
+    fn post(url: &str, body: String) -> (u16, Value) {
+        let response = Client::new().post(url).body(body).send().unwrap();
+        (response.status().as_u16(), response.json().unwrap())
+    }
+
+    #[test]
+    fn a_panicking_handler_is_answered_with_500() {
+        let reply = guarded(|| panic!("handler failed"));
+        assert_eq!(reply.status, 500);
+        assert_eq!(reply.body, br#"{"error":"internal error"}"#);
+
+        assert_eq!(guarded(|| Reply::error(404, "missing")).status, 404);
+    }
+
+    #[test]
+    fn parses_posted_transactions() {
+        let rpc = StubRpc::start(|request| stub::error(request, -32601, "Method not found"));
+        let mut server = server(&rpc);
+        server.max_body_size = 4096;
+        let url = format!("{}/parse", start(server));
+        let (first, first_json) = confirmed_transaction(5);
+        let (second, second_json) = confirmed_transaction(6);
+
+        let (status, parsed) = post(&url, first_json.to_string());
+        assert_eq!(status, 200);
+        assert_eq!(parsed["signature"], first.to_string());
+        assert_eq!(parsed["slot"], 5);
+
+        let mut without_meta = confirmed_transaction(7).1;
+        without_meta["meta"] = Value::Null;
+        let (status, parsed) = post(&url, json!([first_json, without_meta, second_json]).to_string());
+        assert_eq!(status, 200);
+        let signatures: Vec<&Value> = parsed.as_array().unwrap().iter().map(|parsed| &parsed["signature"]).collect();
+        assert_eq!(signatures, [&json!(first.to_string()), &Value::Null, &json!(second.to_string())]);
+
+        assert_eq!(post(&url, without_meta.to_string()).0, 422);
+        assert_eq!(post(&url, "{not json".to_string()).0, 400);
+        assert_eq!(post(&url, "[]".to_string()).0, 400);
+        assert_eq!(post(&url, " ".repeat(4097)).0, 413);
+        assert!(rpc.requests().is_empty());
+    }
+
+    #[test]
+    fn fetches_transactions_once_and_serves_repeats_from_the_cache() {
+        let (signature, tx) = confirmed_transaction(5);
+        let rpc = StubRpc::start(move |request| {
+            if request["params"][0] == signature.to_string() {
+                stub::result(request, tx.clone())
+            } else {
+                stub::result(request, Value::Null)
+            }
+        });
+        let url = start(server(&rpc));
+
+        for _ in 0..2 {
+            let (status, body) = get(&format!("{}/tx/{}", url, signature));
+            assert_eq!(status, 200);
+            assert_eq!(serde_json::from_str::<Value>(&body).unwrap()["signature"], signature.to_string());
+        }
+        assert_eq!(rpc.methods(), ["getTransaction"]);
+
+        assert_eq!(get(&format!("{}/tx/{}", url, Signature::new_unique())).0, 404);
+        assert_eq!(get(&format!("{}/tx/not-a-signature", url)).0, 400);
+        assert_eq!(rpc.methods(), ["getTransaction", "getTransaction"]);
+
+        let (_, metrics) = get(&format!("{}/metrics", url));
+        assert!(metrics.contains("solana_parser_cache_hits_total 1\n"));
+        assert!(metrics.contains("solana_parser_cache_misses_total 3\n"));
+        assert!(metrics.contains("solana_parser_cache_entries 1\n"));
+    }
+
+    #[test]
+    fn missing_blocks_are_404_and_other_rpc_errors_502() {
+        let (signature, tx) = transaction(&Pubkey::new_unique());
+        let rpc = StubRpc::start(move |request| match request["params"][0].as_u64() {
+            Some(10) => stub::result(request, block(10, vec![tx.clone()])),
+            Some(11) => stub::error(request, JSON_RPC_SERVER_ERROR_SLOT_SKIPPED, "Slot 11 was skipped"),
+            _ => stub::error(request, -32603, "Internal error"),
+        });
+        let url = start(server(&rpc));
+
+        let (status, body) = get(&format!("{}/block/10", url));
+        assert_eq!(status, 200);
+        let parsed: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(parsed["slot"], 10);
+        assert_eq!(parsed["transactions"][0]["signature"], signature.to_string());
+
+        assert_eq!(get(&format!("{}/block/11", url)).0, 404);
+        assert_eq!(get(&format!("{}/block/12", url)).0, 502);
+        assert_eq!(get(&format!("{}/block/latest", url)).0, 400);
+    }
+
+    #[test]
+    fn answers_wrong_methods_unknown_routes_and_counts_requests() {
+        let rpc = StubRpc::start(|request| stub::error(request, -32601, "Method not found"));
+        let url = start(server(&rpc));
+
+        assert_eq!(get(&format!("{}/parse", url)).0, 405);
+        assert_eq!(Client::new().post(format!("{}/health", url)).send().unwrap().status().as_u16(), 405);
+        assert_eq!(get(&format!("{}/nowhere", url)).0, 404);
+        let (status, body) = get(&format!("{}/health", url));
+        assert_eq!((status, body.as_str()), (200, r#"{"status":"ok"}"#));
+
+        let response = Client::new().get(format!("{}/metrics", url)).send().unwrap();
+        assert_eq!(response.headers()["content-type"], "text/plain; version=0.0.4");
+        let metrics = response.text().unwrap();
+        let counters: Vec<&str> = metrics.lines().filter(|line| line.starts_with("solana_parser_requests_total")).collect();
+        assert_eq!(
+            counters,
+            [
+                r#"solana_parser_requests_total{route="health",status="200"} 1"#,
+                r#"solana_parser_requests_total{route="other",status="404"} 1"#,
+                r#"solana_parser_requests_total{route="other",status="405"} 2"#,
+            ]
+        );
+        assert!(metrics.contains("# TYPE solana_parser_request_seconds_total counter\n"));
+        assert!(metrics.contains(r#"solana_parser_request_seconds_total{route="health"} "#));
+        assert!(metrics.contains("solana_parser_cache_entries 0\n"));
+    }
+}
@@ -0,0 +1,83 @@
+-- Block times are unix seconds, raw amounts are NUMERIC as they can exceed BIGINT.
+
+CREATE TABLE transactions (
+    signature TEXT PRIMARY KEY,
+    slot BIGINT,
+    block_time BIGINT,
+    transaction_index BIGINT
+);
+CREATE INDEX transactions_slot ON transactions (slot);
+
+CREATE TABLE transfers (
+    signature TEXT NOT NULL,
+    instruction_path TEXT NOT NULL,
+    caller_program_id TEXT,
+    outer_program_id TEXT,
+    mint TEXT NOT NULL,
+    decimals SMALLINT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    raw_amount NUMERIC(20, 0) NOT NULL,
+    from_user_account TEXT NOT NULL,
+    from_token_account TEXT NOT NULL,
+    to_user_account TEXT NOT NULL,
+    to_token_account TEXT NOT NULL,
+    PRIMARY KEY (signature, instruction_path)
+);
+CREATE INDEX transfers_mint ON transfers (mint);
+
+CREATE TABLE swaps (
+    signature TEXT NOT NULL,
+    instruction_path TEXT NOT NULL,
+    wallet TEXT NOT NULL,
+    token_in TEXT NOT NULL,
+    token_out TEXT NOT NULL,
+    amount_in DOUBLE PRECISION NOT NULL,
+    amount_out DOUBLE PRECISION NOT NULL,
+    raw_amount_in NUMERIC(20, 0),
+    raw_amount_out NUMERIC(20, 0),
+    PRIMARY KEY (signature, instruction_path)
+);
+CREATE INDEX swaps_wallet ON swaps (wallet);
+CREATE INDEX swaps_token_in ON swaps (token_in);
+CREATE INDEX swaps_token_out ON swaps (token_out);
+
+CREATE TABLE swap_fees (
+    signature TEXT NOT NULL,
+    instruction_path TEXT NOT NULL,
+    fee_index BIGINT NOT NULL,
+    kind TEXT NOT NULL,
+    recipient TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (signature, instruction_path, fee_index)
+);
+
+CREATE TABLE liquidity_events (
+    signature TEXT NOT NULL,
+    instruction_path TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    program_id TEXT NOT NULL,
+    pool TEXT,
+    mint_a TEXT NOT NULL,
+    mint_b TEXT,
+    raw_amount_a NUMERIC(20, 0),
+    raw_amount_b NUMERIC(20, 0),
+    creator TEXT NOT NULL,
+    open_time BIGINT,
+    PRIMARY KEY (signature, instruction_path)
+);
+CREATE INDEX liquidity_events_mint_a ON liquidity_events (mint_a);
+CREATE INDEX liquidity_events_mint_b ON liquidity_events (mint_b);
+
+CREATE TABLE balance_changes (
+    signature TEXT NOT NULL,
+    account TEXT NOT NULL,
+    mint TEXT NOT NULL,
+    owner TEXT,
+    decimals SMALLINT NOT NULL,
+    pre_amount NUMERIC(20, 0) NOT NULL,
+    post_amount NUMERIC(20, 0) NOT NULL,
+    PRIMARY KEY (signature, account, mint)
+);
+CREATE INDEX balance_changes_account ON balance_changes (account);
+CREATE INDEX balance_changes_owner ON balance_changes (owner);
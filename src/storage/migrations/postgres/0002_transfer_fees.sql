@@ -0,0 +1,3 @@
+-- Marks the transfers that pay out a swap's fee legs, `protocol` or `coin_creator`.
+
+ALTER TABLE transfers ADD COLUMN fee TEXT;
@@ -0,0 +1,493 @@
+#[cfg(feature = "postgres")]
+mod postgres;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use solana_sdk::pubkey::Pubkey;
+use crate::pipeline::ParsedTransaction;
+use crate::pump_parser::{MigrationDestination, PumpEventKind};
+use crate::{PUMP_SWAP, RAYDIUM_V4};
+
+/// A column value, each variant has its own null so backends can bind typed nulls.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Text(Option<String>),
+    Integer(Option<i64>),
+    Real(Option<f64>),
+    /// Raw token amounts, which can exceed `i64`.
+    Amount(Option<u64>),
+}
+
+impl Value {
+    fn text(text: impl ToString) -> Value {
+        Value::Text(Some(text.to_string()))
+    }
+
+    fn integer(integer: impl Into<i64>) -> Value {
+        Value::Integer(Some(integer.into()))
+    }
+}
+
+/// Rows waiting to be upserted into one table, keyed on the first `key_len` columns.
+#[cfg_attr(not(any(feature = "sqlite", feature = "postgres")), allow(dead_code))]
+pub(crate) struct Table {
+    pub name: &'static str,
+    pub columns: &'static [&'static str],
+    pub key_len: usize,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl Table {
+    fn new(name: &'static str, columns: &'static [&'static str], key_len: usize) -> Self {
+        Table {
+            name,
+            columns,
+            key_len,
+            rows: vec![],
+        }
+    }
+
+    /// Rows sharing a key within one batch would make a single statement update the same row
+    /// twice, which PostgreSQL rejects. The last one wins, as it would across batches.
+    fn dedup(&mut self) {
+        let mut positions = HashMap::<String, usize>::new();
+        let mut rows = Vec::<Vec<Value>>::with_capacity(self.rows.len());
+        for row in self.rows.drain(..) {
+            let key = format!("{:?}", &row[..self.key_len]);
+            match positions.get(&key) {
+                Some(&position) => rows[position] = row,
+                None => {
+                    positions.insert(key, rows.len());
+                    rows.push(row);
+                }
+            }
+        }
+        self.rows = rows;
+    }
+
+    /// Removes the rows of `signature`, for when the transaction is added again.
+    fn remove(&mut self, signature: &str) {
+        self.rows.retain(|row| row[0] != Value::text(signature));
+    }
+
+    /// `DELETE` of the rows whose signature is `signatures`, a single parameter.
+    #[cfg_attr(not(any(feature = "sqlite", feature = "postgres")), allow(dead_code))]
+    pub(crate) fn delete_sql(&self, signatures: &str) -> String {
+        format!("DELETE FROM {} WHERE signature = {}", self.name, signatures)
+    }
+
+    /// `INSERT ... ON CONFLICT DO UPDATE` for `rows` rows, `placeholder` renders the parameter
+    /// for a row and column.
+    #[cfg_attr(not(any(feature = "sqlite", feature = "postgres")), allow(dead_code))]
+    pub(crate) fn upsert_sql(&self, rows: usize, placeholder: impl Fn(usize, usize) -> String) -> String {
+        let values: Vec<String> = (0..rows)
+            .map(|row| {
+                let row: Vec<String> = (0..self.columns.len()).map(|column| placeholder(row, column)).collect();
+                format!("({})", row.join(", "))
+            })
+            .collect();
+        let updates: Vec<String> = self.columns[self.key_len..]
+            .iter()
+            .map(|column| format!("{} = excluded.{}", column, column))
+            .collect();
+        format!(
+            "INSERT INTO {} ({}) VALUES {} ON CONFLICT ({}) DO UPDATE SET {}",
+            self.name,
+            self.columns.join(", "),
+            values.join(", "),
+            self.columns[..self.key_len].join(", "),
+            updates.join(", ")
+        )
+    }
+}
+
+/// `3` for the top level instruction 3, `3.1` for the second instruction it invoked.
+fn instruction_path(instruction_index: u8, inner_instruction_index: Option<u8>) -> Value {
+    match inner_instruction_index {
+        Some(inner_instruction_index) => Value::text(format!("{}.{}", instruction_index, inner_instruction_index)),
+        None => Value::text(instruction_index),
+    }
+}
+
+fn optional_pubkey(pubkey: Option<Pubkey>) -> Value {
+    Value::Text(pubkey.map(|pubkey| pubkey.to_string()))
+}
+
+struct Batch {
+    /// Transactions added since the last write.
+    signatures: HashSet<String>,
+    transactions: Table,
+    transfers: Table,
+    swaps: Table,
+    swap_fees: Table,
+    liquidity_events: Table,
+    balance_changes: Table,
+}
+
+impl Batch {
+    fn new() -> Self {
+        Batch {
+            signatures: HashSet::new(),
+            transactions: Table::new(
+                "transactions",
+                &["signature", "slot", "block_time", "transaction_index"],
+                1,
+            ),
+            transfers: Table::new(
+                "transfers",
+                &[
+                    "signature",
+                    "instruction_path",
+                    "caller_program_id",
+                    "outer_program_id",
+                    "mint",
+                    "decimals",
+                    "amount",
+                    "raw_amount",
+                    "fee",
+                    "from_user_account",
+                    "from_token_account",
+                    "to_user_account",
+                    "to_token_account",
+                ],
+                2,
+            ),
+            swaps: Table::new(
+                "swaps",
+                &[
+                    "signature",
+                    "instruction_path",
+                    "wallet",
+                    "token_in",
+                    "token_out",
+                    "amount_in",
+                    "amount_out",
+                    "raw_amount_in",
+                    "raw_amount_out",
+                ],
+                2,
+            ),
+            swap_fees: Table::new(
+                "swap_fees",
+                &["signature", "instruction_path", "fee_index", "kind", "recipient", "mint", "amount"],
+                3,
+            ),
+            liquidity_events: Table::new(
+                "liquidity_events",
+                &[
+                    "signature",
+                    "instruction_path",
+                    "kind",
+                    "program_id",
+                    "pool",
+                    "mint_a",
+                    "mint_b",
+                    "raw_amount_a",
+                    "raw_amount_b",
+                    "creator",
+                    "open_time",
+                ],
+                2,
+            ),
+            balance_changes: Table::new(
+                "balance_changes",
+                &["signature", "account", "mint", "owner", "decimals", "pre_amount", "post_amount"],
+                3,
+            ),
+        }
+    }
+
+    fn tables(&mut self) -> [&mut Table; 6] {
+        [
+            &mut self.transactions,
+            &mut self.transfers,
+            &mut self.swaps,
+            &mut self.swap_fees,
+            &mut self.liquidity_events,
+            &mut self.balance_changes,
+        ]
+    }
+
+    fn add(&mut self, parsed: &ParsedTransaction) {
+        if !self.signatures.insert(parsed.signature.clone()) {
+            for table in self.tables() {
+                table.remove(&parsed.signature);
+            }
+        }
+        let signature = || Value::text(&parsed.signature);
+
+        self.transactions.rows.push(vec![
+            signature(),
+            Value::Integer(parsed.slot.map(|slot| slot as i64)),
+            Value::Integer(parsed.block_time),
+            Value::Integer(parsed.transaction_index.map(|index| index as i64)),
+        ]);
+
+        for transfer in parsed.transfers.iter() {
+            let diff = &transfer.token_balance_diff;
+            self.transfers.rows.push(vec![
+                signature(),
+                instruction_path(transfer.instruction_index, transfer.inner_instruction_index),
+                optional_pubkey(transfer.caller_program_id),
+                optional_pubkey(transfer.outer_program_id),
+                Value::text(&diff.mint),
+                Value::integer(diff.decimals),
+                Value::Real(Some(diff.token_amount)),
+                Value::Amount(Some(diff.raw_amount)),
+                Value::Text(transfer.fee.as_ref().map(|fee| fee.as_str().to_string())),
+                Value::text(transfer.from_user_account),
+                Value::text(transfer.from_token_account),
+                Value::text(transfer.to_user_account),
+                Value::text(transfer.to_token_account),
+            ]);
+        }
+
+        for swap in parsed.swaps.iter() {
+            let path = || instruction_path(swap.instruction_index, swap.inner_instruction_index);
+            self.swaps.rows.push(vec![
+                signature(),
+                path(),
+                Value::text(&swap.wallet),
+                Value::text(&swap.token_in),
+                Value::text(&swap.token_out),
+                Value::Real(Some(swap.amount_in)),
+                Value::Real(Some(swap.amount_out)),
+                Value::Amount(Some(swap.raw_amount_in)),
+                Value::Amount(Some(swap.raw_amount_out)),
+            ]);
+            for (fee_index, fee) in swap.fees.iter().enumerate() {
+                self.swap_fees.rows.push(vec![
+                    signature(),
+                    path(),
+                    Value::integer(fee_index as i64),
+                    Value::text(fee.kind.as_str()),
+                    Value::text(&fee.recipient),
+                    Value::text(&fee.mint),
+                    Value::Real(Some(fee.amount)),
+                ]);
+            }
+        }
+
+        for pool_creation in parsed.pool_creations.iter() {
+            self.liquidity_events.rows.push(vec![
+                signature(),
+                instruction_path(pool_creation.instruction_index, pool_creation.inner_instruction_index),
+                Value::text("pool_creation"),
+                Value::text(pool_creation.program_id),
+                Value::text(pool_creation.pool),
+                Value::text(pool_creation.mint_a),
+                Value::text(pool_creation.mint_b),
+                Value::Amount(Some(pool_creation.amount_a)),
+                Value::Amount(Some(pool_creation.amount_b)),
+                Value::text(pool_creation.creator),
+                Value::Integer(pool_creation.open_time.map(|open_time| open_time as i64)),
+            ]);
+        }
+
+        // A bonding curve graduating moves its liquidity into a pool, the program is where it
+        // moved to.
+        for pump_event in parsed.pump_events.iter() {
+            let PumpEventKind::Migrate { destination, mint, pool, user, .. } = &pump_event.kind else {
+                continue;
+            };
+            let program_id = match destination {
+                MigrationDestination::PumpSwap => PUMP_SWAP,
+                MigrationDestination::Raydium => RAYDIUM_V4,
+            };
+            self.liquidity_events.rows.push(vec![
+                signature(),
+                instruction_path(pump_event.instruction_index, pump_event.inner_instruction_index),
+                Value::text("migration"),
+                Value::text(program_id),
+                optional_pubkey(*pool),
+                Value::text(mint),
+                Value::Text(None),
+                Value::Amount(None),
+                Value::Amount(None),
+                Value::text(user),
+                Value::Integer(None),
+            ]);
+        }
+
+        for balance_change in parsed.balance_changes.iter() {
+            self.balance_changes.rows.push(vec![
+                signature(),
+                Value::text(balance_change.account),
+                Value::text(&balance_change.mint),
+                Value::Text(balance_change.owner.clone()),
+                Value::integer(balance_change.decimals),
+                Value::Amount(Some(balance_change.pre_amount)),
+                Value::Amount(Some(balance_change.post_amount)),
+            ]);
+        }
+    }
+}
+
+/// A database the store writes to, see `sqlite.rs` and `postgres.rs`.
+trait Backend {
+    /// Deletes the rows of `signatures` from `tables` and upserts every row of `tables`, in one
+    /// database transaction.
+    fn write(&mut self, signatures: &[String], tables: &[&mut Table]) -> io::Result<()>;
+}
+
+/// Writes parsed transactions, their transfers, swaps, liquidity events and balance changes
+/// into SQLite or PostgreSQL tables, `batch_size` transactions per database transaction.
+/// Storing a transaction again replaces all of its rows, including those a re-parse no longer
+/// produces, instead of duplicating them.
+pub struct Store {
+    backend: Box<dyn Backend>,
+    batch: Batch,
+    pending: usize,
+    pub batch_size: usize,
+}
+
+impl Store {
+    /// `postgres://` or `postgresql://` URLs open PostgreSQL, anything else is a SQLite file
+    /// path with an optional `sqlite://` prefix. Migrations not yet applied are run first.
+    pub fn open(url: &str) -> io::Result<Self> {
+        let backend: Box<dyn Backend> = if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            open_postgres(url)?
+        } else {
+            open_sqlite(url.strip_prefix("sqlite://").unwrap_or(url))?
+        };
+        Ok(Store {
+            backend,
+            batch: Batch::new(),
+            pending: 0,
+            batch_size: 500,
+        })
+    }
+
+    pub fn write(&mut self, parsed: &ParsedTransaction) -> io::Result<()> {
+        self.batch.add(parsed);
+        self.pending += 1;
+        if self.pending >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        if self.pending == 0 {
+            return Ok(());
+        }
+        let signatures: Vec<String> = self.batch.signatures.iter().cloned().collect();
+        let mut tables = self.batch.tables();
+        for table in tables.iter_mut() {
+            table.dedup();
+        }
+        self.backend.write(&signatures, &tables)?;
+        for table in tables.iter_mut() {
+            table.rows.clear();
+        }
+        self.batch.signatures.clear();
+        self.pending = 0;
+        Ok(())
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            eprintln!("Error flushing store: {:?}", e);
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn open_sqlite(path: &str) -> io::Result<Box<dyn Backend>> {
+    Ok(Box::new(sqlite::SqliteBackend::open(path)?))
+}
+
+#[cfg(not(feature = "sqlite"))]
+fn open_sqlite(_path: &str) -> io::Result<Box<dyn Backend>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "built without the sqlite feature"))
+}
+
+#[cfg(feature = "postgres")]
+fn open_postgres(url: &str) -> io::Result<Box<dyn Backend>> {
+    Ok(Box::new(postgres::PostgresBackend::open(url)?))
+}
+
+#[cfg(not(feature = "postgres"))]
+fn open_postgres(_url: &str) -> io::Result<Box<dyn Backend>> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "built without the postgres feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+    use crate::pipeline::ParsedTransaction;
+    use crate::tx_parser::{BalanceChange, TokenBalanceDiff, Transfer};
+    use super::{Batch, Table, Value};
+
+    /// A transaction with `transfers` transfers and a balance change of `raw_amount`.
+    pub(super) fn parsed(signature: &str, transfers: u8, raw_amount: u64) -> ParsedTransaction {
+        let transfer = |inner_instruction_index: u8| Transfer {
+            instruction_index: 2,
+            inner_instruction_index: Some(inner_instruction_index),
+            instruction_program_id: None,
+            instruction_input_accounts: None,
+            caller_program_id: None,
+            outer_program_id: None,
+            token_balance_diff: TokenBalanceDiff {
+                token_amount: raw_amount as f64,
+                raw_amount,
+                mint: "Mint".to_string(),
+                decimals: 0,
+            },
+            fee: None,
+            to_user_account: Pubkey::new_unique(),
+            to_token_account: Pubkey::new_unique(),
+            from_user_account: Pubkey::new_unique(),
+            from_token_account: Pubkey::new_unique(),
+        };
+        ParsedTransaction {
+            signature: signature.to_string(),
+            slot: Some(7),
+            block_time: None,
+            transaction_index: None,
+            transfers: (0..transfers).map(transfer).collect(),
+            swaps: vec![],
+            pool_creations: vec![],
+            pump_events: vec![],
+            account_events: vec![],
+            balance_changes: vec![BalanceChange {
+                account: Pubkey::new_unique(),
+                mint: "Mint".to_string(),
+                owner: None,
+                decimals: 0,
+                pre_amount: 0,
+                post_amount: raw_amount,
+            }],
+        }
+    }
+
+    #[test]
+    fn dedup_keeps_the_last_row_of_each_key_in_first_seen_order() {
+        let mut table = Table::new("swap_fees", &["signature", "instruction_path", "amount"], 2);
+        let row = |signature: &str, path: &str, amount: f64| {
+            vec![Value::text(signature), Value::text(path), Value::Real(Some(amount))]
+        };
+        table.rows = vec![row("a", "1", 1.0), row("b", "1", 2.0), row("a", "1", 3.0), row("a", "2", 4.0)];
+
+        table.dedup();
+
+        assert_eq!(table.rows, [row("a", "1", 3.0), row("b", "1", 2.0), row("a", "2", 4.0)]);
+    }
+
+    #[test]
+    fn adding_a_transaction_again_replaces_its_rows_in_the_batch() {
+        let mut batch = Batch::new();
+        batch.add(&parsed("a", 3, 1));
+        batch.add(&parsed("b", 1, 1));
+        batch.add(&parsed("a", 1, 1));
+
+        let signatures: Vec<&Value> = batch.transfers.rows.iter().map(|row| &row[0]).collect();
+        assert_eq!(signatures, [&Value::text("b"), &Value::text("a")]);
+        assert_eq!(batch.transactions.rows.len(), 2);
+        assert_eq!(batch.balance_changes.rows.len(), 2);
+    }
+}
@@ -0,0 +1,89 @@
+use std::io;
+use postgres::types::ToSql;
+use postgres::{Client, NoTls};
+use super::{Backend, Table, Value};
+
+const MIGRATIONS: [(i64, &str); 2] = [
+    (1, include_str!("migrations/postgres/0001_create_tables.sql")),
+    (2, include_str!("migrations/postgres/0002_transfer_fees.sql")),
+];
+
+/// PostgreSQL's limit on bind parameters per statement.
+const MAX_PARAMETERS: usize = 65_535;
+const MAX_ROWS_PER_STATEMENT: usize = 1_000;
+
+pub(super) struct PostgresBackend {
+    client: Client,
+}
+
+impl PostgresBackend {
+    /// Connects without TLS, meant for a local or otherwise trusted instance.
+    pub(super) fn open(url: &str) -> io::Result<Self> {
+        let mut client = Client::connect(url, NoTls).map_err(io::Error::other)?;
+        migrate(&mut client).map_err(io::Error::other)?;
+        Ok(PostgresBackend { client })
+    }
+}
+
+fn migrate(client: &mut Client) -> Result<(), postgres::Error> {
+    client.batch_execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (version BIGINT PRIMARY KEY, applied_at TIMESTAMPTZ NOT NULL DEFAULT now())",
+    )?;
+    let mut transaction = client.transaction()?;
+    // Serialises processes starting at the same time, the second sees the first's migrations.
+    transaction.batch_execute("LOCK TABLE schema_migrations IN EXCLUSIVE MODE")?;
+    let version: i64 = transaction
+        .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[])?
+        .get(0);
+
+    for (migration_version, sql) in MIGRATIONS.iter().filter(|(migration_version, _)| *migration_version > version) {
+        transaction.batch_execute(sql)?;
+        transaction.execute("INSERT INTO schema_migrations (version) VALUES ($1)", &[migration_version])?;
+    }
+    transaction.commit()
+}
+
+/// Parameters are cast explicitly so each value binds as its own type whatever the column's,
+/// amounts go over the wire as text.
+fn placeholder(value: &Value, parameter: usize) -> String {
+    match value {
+        Value::Text(_) => format!("${}::text", parameter),
+        Value::Integer(_) => format!("${}::bigint", parameter),
+        Value::Real(_) => format!("${}::float8", parameter),
+        Value::Amount(_) => format!("${}::text::numeric", parameter),
+    }
+}
+
+fn parameter(value: &Value) -> Box<dyn ToSql + Sync> {
+    match value {
+        Value::Text(text) => Box::new(text.clone()),
+        Value::Integer(integer) => Box::new(*integer),
+        Value::Real(real) => Box::new(*real),
+        Value::Amount(amount) => Box::new(amount.map(|amount| amount.to_string())),
+    }
+}
+
+impl Backend for PostgresBackend {
+    fn write(&mut self, signatures: &[String], tables: &[&mut Table]) -> io::Result<()> {
+        let write = |client: &mut Client| -> Result<(), postgres::Error> {
+            let mut transaction = client.transaction()?;
+            for table in tables.iter() {
+                transaction.execute(&table.delete_sql("ANY($1::text[])"), &[&signatures])?;
+            }
+            for table in tables.iter().filter(|table| !table.rows.is_empty()) {
+                let rows_per_statement = (MAX_PARAMETERS / table.columns.len()).min(MAX_ROWS_PER_STATEMENT);
+                for rows in table.rows.chunks(rows_per_statement) {
+                    let sql = table.upsert_sql(rows.len(), |row, column| {
+                        placeholder(&rows[row][column], row * table.columns.len() + column + 1)
+                    });
+                    let parameters: Vec<Box<dyn ToSql + Sync>> = rows.iter().flatten().map(parameter).collect();
+                    let parameters: Vec<&(dyn ToSql + Sync)> =
+                        parameters.iter().map(|parameter| parameter.as_ref()).collect();
+                    transaction.execute(&sql, &parameters)?;
+                }
+            }
+            transaction.commit()
+        };
+        write(&mut self.client).map_err(io::Error::other)
+    }
+}
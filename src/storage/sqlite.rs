@@ -0,0 +1,174 @@
+use std::io;
+use rusqlite::types::ToSqlOutput;
+use rusqlite::{params_from_iter, Connection, ToSql};
+use super::{Backend, Table, Value};
+
+const MIGRATIONS: [(i64, &str); 2] = [
+    (1, include_str!("migrations/sqlite/0001_create_tables.sql")),
+    (2, include_str!("migrations/sqlite/0002_transfer_fees.sql")),
+];
+
+pub(super) struct SqliteBackend {
+    connection: Connection,
+}
+
+impl SqliteBackend {
+    pub(super) fn open(path: &str) -> io::Result<Self> {
+        let mut connection = Connection::open(path).map_err(io::Error::other)?;
+        // WAL lets readers query the database while a backfill is writing to it.
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .map_err(io::Error::other)?;
+        migrate(&mut connection).map_err(io::Error::other)?;
+        Ok(SqliteBackend { connection })
+    }
+}
+
+fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    connection.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (version INTEGER PRIMARY KEY, applied_at INTEGER NOT NULL)",
+        [],
+    )?;
+    let version: i64 = connection.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )?;
+
+    for (migration_version, sql) in MIGRATIONS.iter().filter(|(migration_version, _)| *migration_version > version) {
+        let transaction = connection.transaction()?;
+        transaction.execute_batch(sql)?;
+        transaction.execute(
+            "INSERT INTO schema_migrations (version, applied_at) VALUES (?1, unixepoch())",
+            [migration_version],
+        )?;
+        transaction.commit()?;
+    }
+    Ok(())
+}
+
+impl ToSql for Value {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(match self {
+            Value::Text(text) => text.to_sql()?,
+            Value::Integer(integer) => integer.to_sql()?,
+            Value::Real(real) => real.to_sql()?,
+            Value::Amount(None) => ToSqlOutput::from(rusqlite::types::Null),
+            // Text would be converted to REAL by the INTEGER column, a blob is stored as it is.
+            Value::Amount(Some(amount)) => match i64::try_from(*amount) {
+                Ok(amount) => ToSqlOutput::from(amount),
+                Err(_) => ToSqlOutput::from(amount.to_string().into_bytes()),
+            },
+        })
+    }
+}
+
+impl Backend for SqliteBackend {
+    fn write(&mut self, signatures: &[String], tables: &[&mut Table]) -> io::Result<()> {
+        let write = |connection: &mut Connection| -> rusqlite::Result<()> {
+            let transaction = connection.transaction()?;
+            for table in tables.iter() {
+                let mut statement = transaction.prepare_cached(&table.delete_sql("?1"))?;
+                for signature in signatures.iter() {
+                    statement.execute([signature])?;
+                }
+            }
+            for table in tables.iter().filter(|table| !table.rows.is_empty()) {
+                let sql = table.upsert_sql(1, |_, column| format!("?{}", column + 1));
+                let mut statement = transaction.prepare_cached(&sql)?;
+                for row in table.rows.iter() {
+                    statement.execute(params_from_iter(row.iter()))?;
+                }
+            }
+            transaction.commit()
+        };
+        write(&mut self.connection).map_err(io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use rusqlite::Connection;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::storage::tests::parsed;
+    use crate::storage::Store;
+    use super::SqliteBackend;
+
+    fn database_path() -> PathBuf {
+        std::env::temp_dir().join(format!("solana-parser-store-{}.sqlite", Pubkey::new_unique()))
+    }
+
+    fn remove(path: PathBuf) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn count(connection: &Connection, table: &str) -> i64 {
+        connection
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let path = database_path();
+        SqliteBackend::open(path.to_str().unwrap()).unwrap();
+        SqliteBackend::open(path.to_str().unwrap()).unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(count(&connection, "schema_migrations"), 2);
+        remove(path);
+    }
+
+    #[test]
+    fn storing_a_transaction_again_replaces_its_rows() {
+        let path = database_path();
+        let mut store = Store::open(path.to_str().unwrap()).unwrap();
+        store.write(&parsed("a", 3, 1)).unwrap();
+        store.write(&parsed("b", 1, 1)).unwrap();
+        store.flush().unwrap();
+        store.write(&parsed("a", 3, 1)).unwrap();
+        store.flush().unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(count(&connection, "transactions"), 2);
+        assert_eq!(count(&connection, "transfers"), 4);
+        // Each parse names a new account, the first one's row must not be left behind.
+        assert_eq!(count(&connection, "balance_changes"), 2);
+
+        // A re-parse that finds fewer transfers drops the ones it no longer finds.
+        store.write(&parsed("a", 1, 1)).unwrap();
+        store.flush().unwrap();
+        let transfers: i64 = connection
+            .query_row("SELECT COUNT(*) FROM transfers WHERE signature = 'a'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(transfers, 1);
+        assert_eq!(count(&connection, "transfers"), 2);
+        drop(store);
+        remove(path);
+    }
+
+    #[test]
+    fn amounts_above_i64_are_stored_exactly() {
+        let path = database_path();
+        let mut store = Store::open(path.to_str().unwrap()).unwrap();
+        store.write(&parsed("a", 1, u64::MAX)).unwrap();
+        store.write(&parsed("b", 1, i64::MAX as u64)).unwrap();
+        store.flush().unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let amounts: Vec<String> = connection
+            .prepare("SELECT CAST(raw_amount AS TEXT) FROM transfers ORDER BY signature")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(amounts, [u64::MAX.to_string(), i64::MAX.to_string()]);
+        drop(store);
+        remove(path);
+    }
+}
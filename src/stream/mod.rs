@@ -0,0 +1,471 @@
+use std::any::Any;
+use std::collections::{HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::Duration;
+use solana_client::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{
+    RpcBlockSubscribeConfig, RpcBlockSubscribeFilter, RpcTransactionLogsConfig,
+    RpcTransactionLogsFilter,
+};
+use solana_client::rpc_response::{Response, RpcBlockUpdate, RpcLogsResponse};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status_client_types::{
+    TransactionDetails, UiConfirmedBlock, UiTransactionEncoding,
+};
+use crate::encoding;
+use crate::lookup_table::AddressLookupTableResolver;
+use crate::pipeline::{self, ParsedTransaction};
+use crate::rpc::ResilientRpcClient;
+use crate::tx_parser;
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+const FETCH_ATTEMPTS: u32 = 5;
+const FETCH_RETRY_DELAY: Duration = Duration::from_millis(500);
+// Enough to cover the overlap between a gap backfill and the live feed it catches up to.
+const SEEN_SIGNATURES: usize = 50_000;
+
+pub enum StreamMode {
+    /// `logsSubscribe` per program, fetching each mentioned transaction with `getTransaction`.
+    Logs,
+    /// `blockSubscribe` per program, which carries the transactions themselves. Needs a node
+    /// started with `--rpc-pubsub-enable-block-subscription`.
+    Blocks,
+}
+
+// Held only to keep the subscriptions open, dropping one unsubscribes and closes its socket.
+type Subscriptions = Vec<Box<dyn Any>>;
+
+enum Update {
+    Logs(Response<RpcLogsResponse>),
+    Block(Response<RpcBlockUpdate>),
+    Disconnected,
+}
+
+/// Follows every transaction that mentions one of `programs` as it lands. Dropped connections
+/// are retried with backoff, and the slots missed while disconnected are backfilled with
+/// `getBlock` before the live feed resumes.
+pub struct LiveStream {
+    ws_url: String,
+    rpc_client: ResilientRpcClient,
+    programs: Vec<Pubkey>,
+    mode: StreamMode,
+    pub commitment: CommitmentConfig,
+    /// Gaps longer than this are only backfilled for their most recent slots.
+    pub max_backfill_slots: u64,
+    last_slot: Option<u64>,
+    seen: SeenSignatures,
+}
+
+impl LiveStream {
+    /// Transactions and gap blocks are fetched through `rpc_client` at its own commitment.
+    pub fn new(
+        ws_url: &str,
+        rpc_client: ResilientRpcClient,
+        programs: Vec<Pubkey>,
+        mode: StreamMode,
+    ) -> Self {
+        LiveStream {
+            ws_url: ws_url.to_string(),
+            rpc_client,
+            programs,
+            mode,
+            commitment: CommitmentConfig::confirmed(),
+            max_backfill_slots: 1_000,
+            last_slot: None,
+            seen: SeenSignatures::default(),
+        }
+    }
+
+    /// Runs until the process is stopped, handing each parsed transaction to `on_parsed`.
+    pub fn run(
+        &mut self,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+        mut on_parsed: impl FnMut(&ParsedTransaction),
+    ) {
+        let mut reconnect_delay = Duration::from_secs(1);
+
+        loop {
+            let (subscriptions, updates) = match self.subscribe() {
+                Ok(subscribed) => subscribed,
+                Err(e) => {
+                    eprintln!("Error subscribing: {}, retrying in {:?}", e, reconnect_delay);
+                    thread::sleep(reconnect_delay);
+                    reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            };
+            reconnect_delay = Duration::from_secs(1);
+
+            // Subscribed first so nothing lands between the backfill and the live feed, the
+            // overlap is dropped as already seen.
+            if let Some(last_slot) = self.last_slot {
+                self.backfill(last_slot, lookup_table_resolver, &mut on_parsed);
+            }
+
+            for update in updates.iter() {
+                match update {
+                    Update::Logs(response) => {
+                        self.handle_logs(response, lookup_table_resolver, &mut on_parsed)
+                    }
+                    Update::Block(response) => {
+                        self.set_last_slot(response.value.slot);
+                        if let Some(block) = response.value.block.as_ref() {
+                            self.handle_block(block, response.value.slot, false, lookup_table_resolver, &mut on_parsed);
+                        }
+                    }
+                    Update::Disconnected => break,
+                }
+            }
+
+            eprintln!("Disconnected from {}, reconnecting", self.ws_url);
+            drop(subscriptions);
+        }
+    }
+
+    /// One subscription per program, since both `mentions` and `mentionsAccountOrProgram` only
+    /// accept a single address, all feeding the same channel.
+    fn subscribe(&self) -> Result<(Subscriptions, mpsc::Receiver<Update>), String> {
+        let (sender, updates) = mpsc::channel::<Update>();
+        let mut subscriptions: Subscriptions = vec![];
+
+        for program in self.programs.iter() {
+            match self.mode {
+                StreamMode::Logs => {
+                    let (subscription, receiver) = PubsubClient::logs_subscribe(
+                        &self.ws_url,
+                        RpcTransactionLogsFilter::Mentions(vec![program.to_string()]),
+                        RpcTransactionLogsConfig {
+                            commitment: Some(self.commitment),
+                        },
+                    )
+                    .map_err(|e| e.to_string())?;
+                    forward(receiver, sender.clone(), Update::Logs);
+                    subscriptions.push(Box::new(subscription));
+                }
+                StreamMode::Blocks => {
+                    let (subscription, receiver) = PubsubClient::block_subscribe(
+                        &self.ws_url,
+                        RpcBlockSubscribeFilter::MentionsAccountOrProgram(program.to_string()),
+                        Some(RpcBlockSubscribeConfig {
+                            commitment: Some(self.commitment),
+                            encoding: Some(UiTransactionEncoding::Base64),
+                            transaction_details: Some(TransactionDetails::Full),
+                            show_rewards: Some(false),
+                            max_supported_transaction_version: Some(0),
+                        }),
+                    )
+                    .map_err(|e| e.to_string())?;
+                    forward(receiver, sender.clone(), Update::Block);
+                    subscriptions.push(Box::new(subscription));
+                }
+            }
+        }
+
+        Ok((subscriptions, updates))
+    }
+
+    fn handle_logs(
+        &mut self,
+        response: Response<RpcLogsResponse>,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+        on_parsed: &mut impl FnMut(&ParsedTransaction),
+    ) {
+        self.set_last_slot(response.context.slot);
+        if response.value.err.is_some() {
+            return;
+        }
+        let Ok(signature) = response.value.signature.parse::<Signature>() else {
+            return;
+        };
+        if !self.seen.insert(signature) {
+            return;
+        }
+
+        // `getTransaction` may lag the notification slightly.
+        for attempt in 1..=FETCH_ATTEMPTS {
+            match self.rpc_client.get_transaction(&signature, UiTransactionEncoding::Base64) {
+                Ok(tx) => {
+                    let parsed = isolated(&signature, || {
+                        pipeline::parse_confirmed_transaction(&tx, lookup_table_resolver)
+                    });
+                    match parsed {
+                        Some(Some(parsed)) => on_parsed(&parsed),
+                        Some(None) => eprintln!("Error: Transaction {} failed to decode", signature),
+                        None => {}
+                    }
+                    return;
+                }
+                Err(e) if attempt == FETCH_ATTEMPTS => {
+                    eprintln!("Error fetching {}: {:?}", signature, e);
+                }
+                Err(_) => thread::sleep(FETCH_RETRY_DELAY),
+            }
+        }
+    }
+
+    /// Parses the transactions of `block` that mention one of the programs. `full_block` is set
+    /// when the block came from `getBlock`, so the transaction index is its real position.
+    fn handle_block(
+        &mut self,
+        block: &UiConfirmedBlock,
+        slot: u64,
+        full_block: bool,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+        on_parsed: &mut impl FnMut(&ParsedTransaction),
+    ) {
+        for (transaction_index, tx) in block.transactions.iter().flatten().enumerate() {
+            let Some(transaction) = encoding::decode_transaction(tx) else {
+                continue;
+            };
+            if transaction.meta.err.is_some() {
+                continue;
+            }
+            let account_keys = tx_parser::get_all_account_keys(
+                Some(&transaction.meta),
+                &transaction.message,
+                lookup_table_resolver,
+            );
+            if !account_keys.values().any(|key| self.programs.contains(key)) {
+                continue;
+            }
+            let signature = match transaction.signatures.first() {
+                Some(signature) if self.seen.insert(*signature) => *signature,
+                _ => continue,
+            };
+
+            let Some(mut parsed) = isolated(&signature, || {
+                pipeline::parse_decoded_transaction(&transaction, lookup_table_resolver)
+            }) else {
+                continue;
+            };
+            parsed.slot = Some(slot);
+            parsed.block_time = block.block_time;
+            parsed.transaction_index = full_block.then_some(transaction_index);
+            on_parsed(&parsed);
+        }
+    }
+
+    /// Replays every block from `last_slot`, which may only have been partly streamed, up to
+    /// the current slot.
+    fn backfill(
+        &mut self,
+        last_slot: u64,
+        lookup_table_resolver: Option<&AddressLookupTableResolver>,
+        on_parsed: &mut impl FnMut(&ParsedTransaction),
+    ) {
+        let current_slot = match self.rpc_client.get_slot() {
+            Ok(current_slot) => current_slot,
+            Err(e) => {
+                eprintln!("Error fetching current slot, not backfilling: {:?}", e);
+                return;
+            }
+        };
+        let start_slot = last_slot.max(current_slot.saturating_sub(self.max_backfill_slots));
+        if start_slot > last_slot {
+            eprintln!("Gap too long, skipping slots {} to {}", last_slot, start_slot - 1);
+        }
+
+        let slots = match self.rpc_client.get_blocks(start_slot, current_slot) {
+            Ok(slots) => slots,
+            Err(e) => {
+                eprintln!("Error listing blocks {} to {}: {:?}", start_slot, current_slot, e);
+                return;
+            }
+        };
+        eprintln!("Backfilling {} blocks from slot {}", slots.len(), start_slot);
+
+        for slot in slots {
+            match self.rpc_client.get_block(slot) {
+                Ok(block) => self.handle_block(&block, slot, true, lookup_table_resolver, on_parsed),
+                Err(e) => eprintln!("Error fetching block {}: {:?}", slot, e),
+            }
+            self.set_last_slot(slot);
+        }
+    }
+
+    fn set_last_slot(&mut self, slot: u64) {
+        self.last_slot = Some(self.last_slot.map_or(slot, |last_slot| last_slot.max(slot)));
+    }
+}
+
+/// The most recent signatures handed out, to drop the ones a reconnect delivers again.
+#[derive(Default)]
+pub(crate) struct SeenSignatures {
+    signatures: HashSet<Signature>,
+    order: VecDeque<Signature>,
+}
+
+impl SeenSignatures {
+    /// `false` if `signature` was already handed out.
+    pub(crate) fn insert(&mut self, signature: Signature) -> bool {
+        if !self.signatures.insert(signature) {
+            return false;
+        }
+        self.order.push_back(signature);
+        if self.order.len() > SEEN_SIGNATURES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.signatures.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Runs `parse` so that a parser panic skips the transaction instead of ending the stream.
+fn isolated<T>(signature: &Signature, parse: impl FnOnce() -> T) -> Option<T> {
+    match panic::catch_unwind(AssertUnwindSafe(parse)) {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            eprintln!("Error: Parser panicked on transaction {}", signature);
+            None
+        }
+    }
+}
+
+/// Moves a subscription's updates onto the shared channel, then reports the socket closing.
+fn forward<T: Send + 'static>(
+    receiver: impl IntoIterator<Item = T> + Send + 'static,
+    sender: Sender<Update>,
+    update: fn(T) -> Update,
+) {
+    thread::spawn(move || {
+        for value in receiver {
+            if sender.send(update(value)).is_err() {
+                return;
+            }
+        }
+        let _ = sender.send(Update::Disconnected);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use serde_json::{json, Value};
+    use tungstenite::Message as WsMessage;
+    use crate::rpc::stub::{self, block, transaction, StubRpc};
+    use crate::rpc::RpcConfig;
+    use super::*;
+
+    /// Serves one connection per session: acknowledges the subscription and sends a
+    /// `logsNotification` per (signature, slot), then drops the socket. The last connection is
+    /// held open.
+    fn start_websocket(sessions: Vec<Vec<(Signature, u64)>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let last = sessions.len() - 1;
+            for (index, notifications) in sessions.into_iter().enumerate() {
+                let (stream, _) = listener.accept().unwrap();
+                let mut socket = tungstenite::accept(stream).unwrap();
+                let subscribe: Value =
+                    serde_json::from_str(&socket.read().unwrap().into_text().unwrap()).unwrap();
+                assert_eq!(subscribe["method"], "logsSubscribe");
+                let reply = json!({"jsonrpc": "2.0", "result": index, "id": subscribe["id"]});
+                socket.send(WsMessage::Text(reply.to_string())).unwrap();
+
+                for (signature, slot) in notifications {
+                    let notification = json!({
+                        "jsonrpc": "2.0",
+                        "method": "logsNotification",
+                        "params": {
+                            "result": {
+                                "context": {"slot": slot},
+                                "value": {"signature": signature.to_string(), "err": null, "logs": []},
+                            },
+                            "subscription": index,
+                        },
+                    });
+                    socket.send(WsMessage::Text(notification.to_string())).unwrap();
+                }
+                if index == last {
+                    while socket.read().is_ok() {}
+                }
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn reconnects_backfills_the_gap_and_drops_repeated_signatures() {
+        let program = Pubkey::new_unique();
+        let (a, a_json) = transaction(&program);
+        let (b, b_json) = transaction(&program);
+        let (c, c_json) = transaction(&program);
+        let (d, d_json) = transaction(&program);
+        let slots = HashMap::from([(a, 10), (b, 11), (d, 12), (c, 13)]);
+        let transactions = HashMap::from([
+            (a.to_string(), a_json),
+            (b.to_string(), b_json.clone()),
+            (c.to_string(), c_json),
+            (d.to_string(), d_json.clone()),
+        ]);
+        // The socket drops after slot 11, and 12 is only seen again through the backfill.
+        let blocks = HashMap::from([(11, block(11, vec![b_json])), (12, block(12, vec![d_json]))]);
+
+        let rpc = StubRpc::start(move |request| match request["method"].as_str() {
+            Some("getTransaction") => {
+                let signature = request["params"][0].as_str().unwrap();
+                let transaction = &transactions[signature];
+                let slot = slots[&signature.parse::<Signature>().unwrap()];
+                stub::result(request, json!({
+                    "slot": slot,
+                    "blockTime": null,
+                    "transaction": transaction["transaction"],
+                    "meta": transaction["meta"],
+                }))
+            }
+            Some("getSlot") => stub::result(request, json!(13)),
+            Some("getBlocks") => stub::result(request, json!([11, 12])),
+            Some("getBlock") => {
+                let slot = request["params"][0].as_u64().unwrap();
+                stub::result(request, blocks[&slot].clone())
+            }
+            _ => stub::error(request, -32601, "Method not found"),
+        });
+        let ws_url = start_websocket(vec![vec![(a, 10), (b, 11)], vec![(b, 11), (d, 12), (c, 13)]]);
+
+        let rpc_client = ResilientRpcClient::new(RpcConfig {
+            endpoints: vec![rpc.url.clone()],
+            max_retries: 0,
+            ..RpcConfig::default()
+        });
+        let (sender, parsed) = mpsc::channel();
+        thread::spawn(move || {
+            let mut stream = LiveStream::new(&ws_url, rpc_client, vec![program], StreamMode::Logs);
+            stream.run(None, |transaction| {
+                let _ = sender.send((transaction.signature.clone(), transaction.slot));
+            });
+        });
+
+        let received: Vec<(String, Option<u64>)> = (0..4)
+            .map(|_| parsed.recv_timeout(Duration::from_secs(30)).unwrap())
+            .collect();
+        assert_eq!(
+            received,
+            vec![
+                (a.to_string(), Some(10)),
+                (b.to_string(), Some(11)),
+                (d.to_string(), Some(12)),
+                (c.to_string(), Some(13)),
+            ]
+        );
+        assert!(parsed.recv_timeout(Duration::from_secs(1)).is_err());
+
+        // The gap runs from the last slot seen before the drop to the current one.
+        let requests = rpc.requests();
+        let get_blocks: Vec<&Value> = requests.iter().filter(|request| request["method"] == "getBlocks").collect();
+        assert_eq!(get_blocks.len(), 1);
+        assert_eq!(get_blocks[0]["params"][0], 11);
+        assert_eq!(get_blocks[0]["params"][1], 13);
+        assert_eq!(rpc.methods().iter().filter(|method| *method == "getBlock").count(), 2);
+        assert_eq!(rpc.methods().iter().filter(|method| *method == "getTransaction").count(), 3);
+    }
+}
@@ -1,27 +1,70 @@
 use std::collections::HashMap;
 use std::str::FromStr;
-use std::thread::current;
+use serde::{Deserialize, Serialize};
 use solana_sdk::pubkey::Pubkey;
-use crate::RAYDIUM_V4;
-use crate::tx_parser::Transfer;
+use crate::{PUMP_SWAP, RAYDIUM_V4};
+use crate::tx_parser::{
+    read_pubkey, read_u64, FlatInstruction, TokenBalanceDiff, Transfer,
+    ANCHOR_EVENT_IX_TAG,
+};
 
-#[derive(Debug)]
+const PUMP_SWAP_BUY: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+const PUMP_SWAP_SELL: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+const PUMP_SWAP_BUY_EVENT: [u8; 8] = [103, 244, 82, 31, 44, 245, 119, 119];
+const PUMP_SWAP_SELL_EVENT: [u8; 8] = [62, 47, 55, 10, 165, 3, 220, 42];
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Swap {
-    wallet: String,
-    token_in: String,
-    token_out: String,
-    amount_in: f64,
-    amount_out: f64,
+    /// The swap instruction for PumpSwap, the first transfer of the pair otherwise.
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    pub wallet: String,
+    /// The AMM account the swap went through, when the instruction names it.
+    pub pool: Option<String>,
+    pub token_in: String,
+    pub token_out: String,
+    pub amount_in: f64,
+    pub amount_out: f64,
+    /// The amounts in the mints' base units, lamports for SOL.
+    pub raw_amount_in: u64,
+    pub raw_amount_out: u64,
+    pub fees: Vec<SwapFee>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SwapFeeKind {
+    Protocol,
+    CoinCreator,
+}
+
+impl SwapFeeKind {
+    /// The name it is serialized under.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapFeeKind::Protocol => "protocol",
+            SwapFeeKind::CoinCreator => "coin_creator",
+        }
+    }
+}
+
+/// A fee leg paid out of the swap to someone other than the pool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwapFee {
+    pub kind: SwapFeeKind,
+    pub recipient: String,
+    pub mint: String,
+    pub amount: f64,
 }
 
 pub fn parse_swaps(
-    transfers: Vec<Transfer>,
+    transfers: &[Transfer],
     account_keys: &HashMap<u8, Pubkey>,
 ) -> Vec<Swap> {
     let mut swaps = Vec::<Swap>::new();
 
     for (index,transfer) in transfers.iter().enumerate() {
-        if let Some(swap) = process_transfer(index, &transfers, account_keys) {
+        if let Some(swap) = process_transfer(index, transfers, account_keys) {
             if transfer.instruction_program_id.is_none() {
                 continue;
             }
@@ -34,7 +77,7 @@ pub fn parse_swaps(
 
 pub fn process_transfer(
     index: usize,
-    transfers: &Vec<Transfer>,
+    transfers: &[Transfer],
     account_keys: &HashMap<u8, Pubkey>,
 ) -> Option<Swap> {
     if index + 1 >= transfers.len() {
@@ -48,8 +91,7 @@ pub fn process_transfer(
     }
 
     let mut wallet = account_keys
-        .get(transfer.instruction_input_accounts.as_ref()?.get(16)?)
-        .unwrap()
+        .get(transfer.instruction_input_accounts.as_ref()?.get(16)?)?
         .to_string();
 
     if let Some(input_accounts) = transfer.instruction_input_accounts.as_ref() {
@@ -62,14 +104,274 @@ pub fn process_transfer(
         }
     }
 
+    let pool = transfer
+        .instruction_input_accounts
+        .as_ref()
+        .and_then(|input_accounts| account_keys.get(input_accounts.get(1)?))
+        .map(|pool| pool.to_string());
+
     let token_in = next_transfer.token_balance_diff.mint.clone();
     let token_out = transfer.token_balance_diff.mint.clone();
 
     Some(Swap{
+        instruction_index: transfer.instruction_index,
+        inner_instruction_index: transfer.inner_instruction_index,
         wallet,
+        pool,
         token_in: token_in.to_string(),
         token_out: token_out.to_string(),
         amount_in: next_transfer.token_balance_diff.token_amount,
         amount_out: transfer.token_balance_diff.token_amount,
+        raw_amount_in: next_transfer.token_balance_diff.raw_amount,
+        raw_amount_out: transfer.token_balance_diff.raw_amount,
+        fees: Vec::new(),
     })
-}
\ No newline at end of file
+}
+
+fn mint_decimals(balance_map: &HashMap<u8, TokenBalanceDiff>, mint: &Pubkey) -> u8 {
+    let mint = mint.to_string();
+    balance_map
+        .values()
+        .find(|balance| balance.mint == mint)
+        .map(|balance| balance.decimals)
+        .unwrap_or(0)
+}
+
+fn ui_amount(amount: u64, decimals: u8) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// PumpSwap reports exact amounts and fee legs in the Buy/Sell event it emits after each
+/// trade, so its swaps are built from those events rather than from the transfers. The
+/// transfers paying out the fee legs are marked with `Transfer::fee`.
+pub fn parse_pump_swaps(
+    instructions: &[FlatInstruction],
+    balance_map: &HashMap<u8, TokenBalanceDiff>,
+    account_keys: &HashMap<u8, Pubkey>,
+    transfers: &mut [Transfer],
+) -> Vec<Swap> {
+    let mut swaps = Vec::<Swap>::new();
+    let pump_swap = Pubkey::from_str(PUMP_SWAP).unwrap();
+    // The last buy/sell and its (base mint, quote mint), the event follows it as a self-CPI.
+    let mut swap_instruction: Option<(FlatInstruction, Pubkey, Pubkey)> = None;
+
+    for instruction in instructions.iter() {
+        if account_keys.get(&instruction.program_id_index) != Some(&pump_swap)
+            || instruction.data.len() < 16
+        {
+            continue;
+        }
+        let (discriminator, args) = instruction.data.split_at(8);
+
+        if discriminator == PUMP_SWAP_BUY || discriminator == PUMP_SWAP_SELL {
+            let account = |position: usize| -> Option<Pubkey> {
+                account_keys.get(instruction.accounts.get(position)?).cloned()
+            };
+            swap_instruction = account(3)
+                .zip(account(4))
+                .map(|(base_mint, quote_mint)| (instruction.clone(), base_mint, quote_mint));
+            continue;
+        }
+
+        if discriminator != ANCHOR_EVENT_IX_TAG {
+            continue;
+        }
+        let (event_discriminator, event) = args.split_at(8);
+        let is_buy = event_discriminator == PUMP_SWAP_BUY_EVENT;
+        if !is_buy && event_discriminator != PUMP_SWAP_SELL_EVENT {
+            continue;
+        }
+        let Some((swap_instruction, base_mint, quote_mint)) = swap_instruction.take() else {
+            continue;
+        };
+        if let Some(swap) =
+            process_pump_swap_event(&swap_instruction, is_buy, event, &base_mint, &quote_mint, balance_map)
+        {
+            // The protocol fee recipient's token account is in the event, the coin creator's
+            // vault is the buy/sell's 18th account.
+            let protocol_fee_account = read_pubkey(event, 272);
+            let coin_creator_fee_account =
+                swap_instruction.accounts.get(17).and_then(|index| account_keys.get(index)).cloned();
+            for fee in swap.fees.iter() {
+                let fee_account = match fee.kind {
+                    SwapFeeKind::Protocol => protocol_fee_account,
+                    SwapFeeKind::CoinCreator => coin_creator_fee_account,
+                };
+                if let Some(fee_account) = fee_account {
+                    mark_fee_transfer(transfers, &swap_instruction, &pump_swap, &fee_account, &fee.kind);
+                }
+            }
+            swaps.push(swap);
+        }
+    }
+
+    swaps
+}
+
+/// Marks the first unmarked transfer PumpSwap made into `fee_account` after `swap_instruction`.
+fn mark_fee_transfer(
+    transfers: &mut [Transfer],
+    swap_instruction: &FlatInstruction,
+    pump_swap: &Pubkey,
+    fee_account: &Pubkey,
+    kind: &SwapFeeKind,
+) {
+    let fee_transfer = transfers.iter_mut().find(|transfer| {
+        transfer.fee.is_none()
+            && transfer.instruction_index == swap_instruction.instruction_index
+            && transfer.inner_instruction_index > swap_instruction.inner_instruction_index
+            && transfer.caller_program_id.as_ref() == Some(pump_swap)
+            && &transfer.to_token_account == fee_account
+    });
+    if let Some(fee_transfer) = fee_transfer {
+        fee_transfer.fee = Some(kind.clone());
+    }
+}
+
+fn process_pump_swap_event(
+    swap_instruction: &FlatInstruction,
+    is_buy: bool,
+    event: &[u8],
+    base_mint: &Pubkey,
+    quote_mint: &Pubkey,
+    balance_map: &HashMap<u8, TokenBalanceDiff>,
+) -> Option<Swap> {
+    // BuyEvent and SellEvent share a layout: timestamp, base amount, quote limit, four reserve
+    // fields, quote amount, lp fee bps, lp fee, protocol fee bps, protocol fee, quote amount
+    // net of lp fee, user quote amount, pool, user, user base/quote token accounts, protocol fee
+    // recipient and its token account, then coin creator, its fee bps and fee (newer pools only).
+    let base_amount = read_u64(event, 8)?;
+    let protocol_fee = read_u64(event, 88)?;
+    let user_quote_amount = read_u64(event, 104)?;
+    let pool = read_pubkey(event, 112)?;
+    let user = read_pubkey(event, 144)?;
+    let protocol_fee_recipient = read_pubkey(event, 240)?;
+    let coin_creator = read_pubkey(event, 304);
+    let coin_creator_fee = read_u64(event, 344).unwrap_or(0);
+
+    let base_decimals = mint_decimals(balance_map, base_mint);
+    let quote_decimals = mint_decimals(balance_map, quote_mint);
+
+    let mut fees = vec![SwapFee {
+        kind: SwapFeeKind::Protocol,
+        recipient: protocol_fee_recipient.to_string(),
+        mint: quote_mint.to_string(),
+        amount: ui_amount(protocol_fee, quote_decimals),
+    }];
+    if let Some(coin_creator) = coin_creator.filter(|_| coin_creator_fee > 0) {
+        fees.push(SwapFee {
+            kind: SwapFeeKind::CoinCreator,
+            recipient: coin_creator.to_string(),
+            mint: quote_mint.to_string(),
+            amount: ui_amount(coin_creator_fee, quote_decimals),
+        });
+    }
+
+    let base = (base_mint, base_amount, base_decimals);
+    let quote = (quote_mint, user_quote_amount, quote_decimals);
+    let ((token_in, raw_amount_in, decimals_in), (token_out, raw_amount_out, decimals_out)) =
+        if is_buy { (base, quote) } else { (quote, base) };
+
+    Some(Swap {
+        instruction_index: swap_instruction.instruction_index,
+        inner_instruction_index: swap_instruction.inner_instruction_index,
+        wallet: user.to_string(),
+        pool: Some(pool.to_string()),
+        token_in: token_in.to_string(),
+        token_out: token_out.to_string(),
+        amount_in: ui_amount(raw_amount_in, decimals_in),
+        amount_out: ui_amount(raw_amount_out, decimals_out),
+        raw_amount_in,
+        raw_amount_out,
+        fees,
+    })
+}
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+    use solana_sdk::pubkey::Pubkey;
+    use crate::tx_parser::{FlatInstruction, TokenBalanceDiff, Transfer, ANCHOR_EVENT_IX_TAG};
+    use crate::PUMP_SWAP;
+    use super::{parse_pump_swaps, SwapFeeKind, PUMP_SWAP_BUY, PUMP_SWAP_BUY_EVENT};
+
+    const PROGRAM_INDEX: u8 = 19;
+
+    fn instruction(inner_instruction_index: Option<u8>, accounts: Vec<u8>, data: Vec<u8>) -> FlatInstruction {
+        FlatInstruction {
+            instruction_index: 0,
+            inner_instruction_index,
+            stack_height: None,
+            program_id_index: PROGRAM_INDEX,
+            accounts,
+            data,
+            parsed: None,
+        }
+    }
+
+    fn transfer(instruction_index: u8, inner_instruction_index: u8, to_token_account: Pubkey) -> Transfer {
+        Transfer {
+            instruction_index,
+            inner_instruction_index: Some(inner_instruction_index),
+            instruction_program_id: None,
+            instruction_input_accounts: None,
+            caller_program_id: Some(Pubkey::from_str(PUMP_SWAP).unwrap()),
+            outer_program_id: Some(Pubkey::from_str(PUMP_SWAP).unwrap()),
+            token_balance_diff: TokenBalanceDiff {
+                token_amount: 0.0,
+                raw_amount: 0,
+                mint: String::new(),
+                decimals: 0,
+            },
+            fee: None,
+            to_user_account: Pubkey::new_unique(),
+            to_token_account,
+            from_user_account: Pubkey::new_unique(),
+            from_token_account: Pubkey::new_unique(),
+        }
+    }
+
+    #[test]
+    fn marks_the_transfers_paying_out_pump_swap_fees() {
+        // Buy accounts by position: pool, user, global config, base mint, quote mint, user base
+        // and quote token accounts, pool base and quote token accounts, protocol fee recipient
+        // and its token account, ... coin creator vault token account at 17.
+        let mut account_keys: HashMap<u8, Pubkey> = (0..19).map(|index| (index, Pubkey::new_unique())).collect();
+        account_keys.insert(PROGRAM_INDEX, Pubkey::from_str(PUMP_SWAP).unwrap());
+        let key = |index: u8| account_keys[&index];
+
+        let mut event = vec![0; 352];
+        event[8..16].copy_from_slice(&1_000u64.to_le_bytes());
+        event[88..96].copy_from_slice(&5u64.to_le_bytes());
+        event[104..112].copy_from_slice(&2_000u64.to_le_bytes());
+        event[112..144].copy_from_slice(key(0).as_ref());
+        event[144..176].copy_from_slice(key(1).as_ref());
+        event[240..272].copy_from_slice(key(9).as_ref());
+        event[272..304].copy_from_slice(key(10).as_ref());
+        event[304..336].copy_from_slice(key(18).as_ref());
+        event[344..352].copy_from_slice(&3u64.to_le_bytes());
+        let instructions = vec![
+            instruction(None, (0..19).collect(), [PUMP_SWAP_BUY.as_slice(), &[0; 16]].concat()),
+            instruction(Some(4), vec![15], [ANCHOR_EVENT_IX_TAG.as_slice(), &PUMP_SWAP_BUY_EVENT, &event].concat()),
+        ];
+        let mut transfers = vec![
+            transfer(0, 0, key(8)),
+            transfer(0, 1, key(5)),
+            transfer(0, 2, key(10)),
+            transfer(0, 3, key(17)),
+            // A later instruction paying the same fee account is not part of this swap.
+            transfer(1, 0, key(10)),
+        ];
+
+        let swaps = parse_pump_swaps(&instructions, &HashMap::new(), &account_keys, &mut transfers);
+
+        assert_eq!(swaps.len(), 1);
+        let fees: Vec<(SwapFeeKind, f64)> = swaps[0].fees.iter().map(|fee| (fee.kind.clone(), fee.amount)).collect();
+        assert_eq!(fees, [(SwapFeeKind::Protocol, 5.0), (SwapFeeKind::CoinCreator, 3.0)]);
+        let marked: Vec<Option<SwapFeeKind>> = transfers.iter().map(|transfer| transfer.fee.clone()).collect();
+        assert_eq!(
+            marked,
+            [None, None, Some(SwapFeeKind::Protocol), Some(SwapFeeKind::CoinCreator), None],
+        );
+    }
+}
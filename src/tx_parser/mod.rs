@@ -1,118 +1,492 @@
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
 use solana_sdk::bs58;
 use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::message::v0::LoadedAddresses;
+use solana_sdk::message::VersionedMessage;
 use solana_sdk::pubkey::Pubkey;
-use solana_sdk::system_instruction::SystemInstruction;
-use solana_transaction_status::option_serializer::OptionSerializer;
-use solana_transaction_status::{
-    UiInnerInstructions, UiInstruction, UiTransactionStatusMeta,
+use solana_transaction_status_client_types::option_serializer::OptionSerializer;
+use solana_transaction_status_client_types::{
+    InnerInstructions, TransactionStatusMeta, TransactionTokenBalance, UiInnerInstructions, UiInstruction,
+    UiTransactionStatusMeta, UiTransactionTokenBalance,
 };
+use solana_sdk::system_instruction::SystemInstruction;
 use spl_token::instruction::TokenInstruction;
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::string::ToString;
-use crate::RAYDIUM_V4;
+use crate::account_parser::{self, AccountEventKind};
+use crate::instruction_tree::{InstructionNode, InstructionTree};
+use crate::lookup_table::AddressLookupTableResolver;
+use crate::swap_parser::SwapFeeKind;
+use crate::{ASSOCIATED_TOKEN_PROGRAM, PUMP_SWAP, RAYDIUM_V4, SYSTEM_PROGRAM, TOKEN_PROGRAM};
+
+/// Programs whose accounts are attached to the transfers they make, for the swap parser.
+const SWAP_PROGRAMS: [&str; 2] = [RAYDIUM_V4, PUMP_SWAP];
+
+const ATA_CREATE: u8 = 0;
+const ATA_CREATE_IDEMPOTENT: u8 = 1;
 
-#[derive(Clone)]
+/// Prefix of the self-CPI instruction Anchor's `emit_cpi!` uses to log events.
+pub(crate) const ANCHOR_EVENT_IX_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenBalanceDiff {
     pub token_amount: f64,
+    /// The same change in the mint's base units, lamports for SOL. Clamped at zero, like the
+    /// UI amount of a SOL diff.
+    pub raw_amount: u64,
     pub mint: String,
     pub decimals: u8,
 }
 
+#[serde_as]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Transfer {
+    /// The token program instruction that made the transfer.
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
     pub instruction_program_id: Option<Pubkey>,
     pub instruction_input_accounts: Option<Vec<u8>>,
+    /// Program that invoked the transfer, `None` for a top level transfer.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub caller_program_id: Option<Pubkey>,
+    /// Top level program the transfer was made under, `None` for a top level transfer.
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub outer_program_id: Option<Pubkey>,
     pub token_balance_diff: TokenBalanceDiff,
+    /// Set when the transfer pays out one of a swap's `fees`, which already counts it.
+    pub fee: Option<SwapFeeKind>,
+    #[serde_as(as = "DisplayFromStr")]
     pub to_user_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
     pub to_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
     pub from_user_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
     pub from_token_account: Pubkey,
 }
-pub fn create_balance_diff_map(meta: &UiTransactionStatusMeta) -> HashMap<u8, TokenBalanceDiff> {
-    let mut token_balance_diff_map: HashMap<u8, TokenBalanceDiff> = HashMap::new();
 
-    let post_token_balances = meta.post_token_balances.clone();
-    let pre_token_balances = meta.pre_token_balances.clone();
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccountRole {
+    pub signer: bool,
+    pub writable: bool,
+}
+
+/// A single instruction from the transaction, either top level or inner, with its
+/// data already decoded to raw bytes.
+#[derive(Debug, Clone)]
+pub struct FlatInstruction {
+    pub instruction_index: u8,
+    pub inner_instruction_index: Option<u8>,
+    /// Invocation depth reported by the runtime, `None` for top level instructions and for
+    /// inner instructions recorded before stack heights were tracked.
+    pub stack_height: Option<u32>,
+    pub program_id_index: u8,
+    pub accounts: Vec<u8>,
+    pub data: Vec<u8>,
+    /// The RPC's own decoding of a `jsonParsed` instruction, whose `data` is then empty.
+    pub parsed: Option<KnownInstruction<'static>>,
+}
+
+impl FlatInstruction {
+    /// The instruction decoded, if `program` is the token, system or associated token account
+    /// program.
+    pub fn known(&self, program: &Pubkey) -> Option<KnownInstruction<'_>> {
+        known_instruction(program, &self.data, self.parsed.as_ref())
+    }
+}
+
+/// A token, system or associated token account instruction, with its accounts in the order
+/// the program reads them.
+#[derive(Debug, Clone)]
+pub enum KnownInstruction<'a> {
+    Token(TokenInstruction<'a>),
+    System(SystemInstruction),
+    /// `Create`, or `CreateIdempotent` which is a no-op when the account already exists.
+    AssociatedToken { idempotent: bool },
+}
+
+/// `parsed` if the RPC decoded the instruction, otherwise `data` decoded by `program`.
+pub fn known_instruction<'a>(
+    program: &Pubkey,
+    data: &'a [u8],
+    parsed: Option<&KnownInstruction<'static>>,
+) -> Option<KnownInstruction<'a>> {
+    if let Some(parsed) = parsed {
+        return Some(parsed.clone());
+    }
+
+    match program.to_string().as_str() {
+        TOKEN_PROGRAM => match TokenInstruction::unpack(data) {
+            Ok(token_instruction) => Some(KnownInstruction::Token(token_instruction)),
+            Err(e) => {
+                eprintln!("Error unpacking token instruction: {:?}", e);
+                None
+            }
+        },
+        SYSTEM_PROGRAM => match bincode::deserialize::<SystemInstruction>(data) {
+            Ok(system_instruction) => Some(KnownInstruction::System(system_instruction)),
+            Err(e) => {
+                eprintln!("Error unpacking system instruction: {:?}", e);
+                None
+            }
+        },
+        // Create predates the instruction enum and may be sent with empty data.
+        ASSOCIATED_TOKEN_PROGRAM => match data.first() {
+            None | Some(&ATA_CREATE) => Some(KnownInstruction::AssociatedToken { idempotent: false }),
+            Some(&ATA_CREATE_IDEMPOTENT) => Some(KnownInstruction::AssociatedToken { idempotent: true }),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// A token account's balance as recorded in either form of status meta.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBalance<'a> {
+    pub account_index: u8,
+    pub mint: &'a str,
+    /// `None` for balances recorded before owners were added to the meta.
+    pub owner: Option<&'a str>,
+    pub decimals: u8,
+    /// Raw amount, a `u64` as a string.
+    pub amount: &'a str,
+    pub ui_amount: Option<f64>,
+}
+
+impl TokenBalance<'_> {
+    /// `amount` parsed, zero if the node sent something else.
+    pub fn raw_amount(&self) -> u64 {
+        self.amount.parse().unwrap_or(0)
+    }
+}
 
-    for post in post_token_balances.unwrap().iter() {
+impl<'a> From<&'a UiTransactionTokenBalance> for TokenBalance<'a> {
+    fn from(balance: &'a UiTransactionTokenBalance) -> Self {
+        TokenBalance {
+            account_index: balance.account_index,
+            mint: &balance.mint,
+            owner: match &balance.owner {
+                OptionSerializer::Some(owner) => Some(owner),
+                _ => None,
+            },
+            decimals: balance.ui_token_amount.decimals,
+            amount: &balance.ui_token_amount.amount,
+            ui_amount: balance.ui_token_amount.ui_amount,
+        }
+    }
+}
+
+impl<'a> From<&'a TransactionTokenBalance> for TokenBalance<'a> {
+    fn from(balance: &'a TransactionTokenBalance) -> Self {
+        TokenBalance {
+            account_index: balance.account_index,
+            mint: &balance.mint,
+            owner: (!balance.owner.is_empty()).then_some(balance.owner.as_str()),
+            decimals: balance.ui_token_amount.decimals,
+            amount: &balance.ui_token_amount.amount,
+            ui_amount: balance.ui_token_amount.ui_amount,
+        }
+    }
+}
+
+/// The balances the parsers read from a transaction's status meta, borrowed from the JSON-RPC
+/// (`UiTransactionStatusMeta`) or the runtime's (`TransactionStatusMeta`) form of it.
+#[derive(Debug, Clone)]
+pub struct Balances<'a> {
+    /// Lamports by account index.
+    pub pre_balances: &'a [u64],
+    pub post_balances: &'a [u64],
+    pub pre_token_balances: Vec<TokenBalance<'a>>,
+    pub post_token_balances: Vec<TokenBalance<'a>>,
+}
+
+impl<'a> From<&'a UiTransactionStatusMeta> for Balances<'a> {
+    fn from(meta: &'a UiTransactionStatusMeta) -> Self {
+        let token_balances = |balances: &'a OptionSerializer<Vec<UiTransactionTokenBalance>>| match balances {
+            OptionSerializer::Some(balances) => balances.iter().map(TokenBalance::from).collect(),
+            _ => vec![],
+        };
+        Balances {
+            pre_balances: &meta.pre_balances,
+            post_balances: &meta.post_balances,
+            pre_token_balances: token_balances(&meta.pre_token_balances),
+            post_token_balances: token_balances(&meta.post_token_balances),
+        }
+    }
+}
+
+impl<'a> From<&'a TransactionStatusMeta> for Balances<'a> {
+    fn from(meta: &'a TransactionStatusMeta) -> Self {
+        let token_balances = |balances: &'a Option<Vec<TransactionTokenBalance>>| {
+            balances.iter().flatten().map(TokenBalance::from).collect()
+        };
+        Balances {
+            pre_balances: &meta.pre_balances,
+            post_balances: &meta.post_balances,
+            pre_token_balances: token_balances(&meta.pre_token_balances),
+            post_token_balances: token_balances(&meta.post_token_balances),
+        }
+    }
+}
+
+pub fn create_balance_diff_map(balances: &Balances) -> HashMap<u8, TokenBalanceDiff> {
+    let mut token_balance_diff_map: HashMap<u8, TokenBalanceDiff> = HashMap::new();
+
+    for post in balances.post_token_balances.iter() {
         let token_account = post.account_index;
-        let token_amount = post.ui_token_amount.ui_amount.unwrap_or(0.0);
+        let token_amount = post.ui_amount.unwrap_or(0.0);
         token_balance_diff_map.insert(
             token_account,
             TokenBalanceDiff {
                 token_amount,
-                mint: post.mint.clone(),
-                decimals: post.ui_token_amount.decimals,
+                raw_amount: post.raw_amount(),
+                mint: post.mint.to_string(),
+                decimals: post.decimals,
             },
         );
     }
 
-    for pre in pre_token_balances.unwrap().iter() {
-        if token_balance_diff_map.get(&pre.account_index).is_none() {
+    for pre in balances.pre_token_balances.iter() {
+        if !token_balance_diff_map.contains_key(&pre.account_index) {
             continue;
         }
         let token_account = pre.account_index;
-        let token_amount = pre.ui_token_amount.ui_amount.unwrap_or(0.0);
+        let token_amount = pre.ui_amount.unwrap_or(0.0);
 
         let token_balance_map_entry = token_balance_diff_map.get_mut(&token_account).unwrap();
 
         let diff = token_balance_map_entry.token_amount - token_amount;
         token_balance_map_entry.token_amount = diff;
+        token_balance_map_entry.raw_amount = token_balance_map_entry.raw_amount.saturating_sub(pre.raw_amount());
     }
 
     token_balance_diff_map
 }
 
-pub fn create_native_balance_diff_map(meta: &UiTransactionStatusMeta) -> HashMap<u8, f64> {
-    let mut native_balance_diff_map: HashMap<u8, f64> = HashMap::new();
+/// An account's balance before and after the transaction in raw units, lamports for SOL.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceChange {
+    #[serde_as(as = "DisplayFromStr")]
+    pub account: Pubkey,
+    /// `"SOL"` for the lamport balance, like `TokenBalanceDiff::mint`.
+    pub mint: String,
+    /// Owner of a token account, `None` for SOL.
+    pub owner: Option<String>,
+    pub decimals: u8,
+    pub pre_amount: u64,
+    pub post_amount: u64,
+}
 
-    let post_balances = meta.post_balances.clone();
-    let pre_balances = meta.pre_balances.clone();
+impl BalanceChange {
+    pub fn change(&self) -> i128 {
+        self.post_amount as i128 - self.pre_amount as i128
+    }
+}
+
+/// Every SOL and token balance the transaction changed, lamports first, each in account order.
+/// Token accounts opened or closed by the transaction count from or to zero.
+pub fn create_balance_changes(
+    balances: &Balances,
+    account_keys: &HashMap<u8, Pubkey>,
+) -> Vec<BalanceChange> {
+    let mut balance_changes = Vec::<BalanceChange>::new();
+
+    for (account_index, (pre_amount, post_amount)) in
+        balances.pre_balances.iter().zip(balances.post_balances.iter()).enumerate()
+    {
+        let Some(account) = account_keys.get(&(account_index as u8)) else {
+            continue;
+        };
+        if pre_amount != post_amount {
+            balance_changes.push(BalanceChange {
+                account: *account,
+                mint: "SOL".to_string(),
+                owner: None,
+                decimals: 9,
+                pre_amount: *pre_amount,
+                post_amount: *post_amount,
+            });
+        }
+    }
+
+    let pre_token_balances = &balances.pre_token_balances;
+    let post_token_balances = &balances.post_token_balances;
+    // (account index, pre balance, post balance)
+    let mut token_balances = vec![];
+    for post in post_token_balances.iter() {
+        let pre = pre_token_balances.iter().find(|pre| pre.account_index == post.account_index);
+        token_balances.push((post.account_index, pre, Some(post)));
+    }
+    for pre in pre_token_balances.iter() {
+        if !post_token_balances.iter().any(|post| post.account_index == pre.account_index) {
+            token_balances.push((pre.account_index, Some(pre), None));
+        }
+    }
+    token_balances.sort_by_key(|(account_index, _, _)| *account_index);
 
-    for (account_index, post_amount) in post_balances.iter().enumerate() {
-        native_balance_diff_map.insert(account_index as u8, *post_amount as f64 / 1_000_000_000.0);
+    let raw_amount = |balance: Option<&TokenBalance>| balance.map_or(0, TokenBalance::raw_amount);
+    for (account_index, pre, post) in token_balances {
+        let Some(account) = account_keys.get(&account_index) else {
+            continue;
+        };
+        let Some(balance) = post.or(pre) else {
+            continue;
+        };
+        let (pre_amount, post_amount) = (raw_amount(pre), raw_amount(post));
+        if pre_amount == post_amount {
+            continue;
+        }
+        balance_changes.push(BalanceChange {
+            account: *account,
+            mint: balance.mint.to_string(),
+            owner: balance.owner.map(str::to_string),
+            decimals: balance.decimals,
+            pre_amount,
+            post_amount,
+        });
     }
 
-    for (account_index, pre_amount) in pre_balances.iter().enumerate() {
+    balance_changes
+}
+
+/// Lamports gained by each account, zero for accounts whose balance went down.
+pub fn create_native_balance_diff_map(balances: &Balances) -> HashMap<u8, u64> {
+    let mut native_balance_diff_map: HashMap<u8, u64> = HashMap::new();
+
+    for (account_index, post_amount) in balances.post_balances.iter().enumerate() {
+        native_balance_diff_map.insert(account_index as u8, *post_amount);
+    }
+
+    for (account_index, pre_amount) in balances.pre_balances.iter().enumerate() {
         if let Some(balance_map_entry) = native_balance_diff_map.get_mut(&(account_index as u8)) {
-            let pre_amount_sol = *pre_amount as f64 / 1_000_000_000.0;
-            *balance_map_entry = (*balance_map_entry - pre_amount_sol).max(0.0);
+            *balance_map_entry = balance_map_entry.saturating_sub(*pre_amount);
         }
     }
 
     native_balance_diff_map
 }
 
+/// Static keys followed by the keys loaded from lookup tables, taken from `meta` when it has
+/// them and from `lookup_table_resolver` otherwise.
 pub fn get_all_account_keys(
-    meta: &UiTransactionStatusMeta,
-    account_keys: &[Pubkey],
+    meta: Option<&UiTransactionStatusMeta>,
+    message: &VersionedMessage,
+    lookup_table_resolver: Option<&AddressLookupTableResolver>,
 ) -> HashMap<u8, Pubkey> {
     let mut account_keys_map: HashMap<u8, Pubkey> = HashMap::new();
     let mut index = 0;
-    for account in account_keys.iter() {
+    for account in message.static_account_keys().iter() {
         account_keys_map.insert(index, *account);
         index += 1;
     }
 
-    let loaded_addresses = &meta.loaded_addresses.clone().unwrap();
+    let lookups = message.address_table_lookups().unwrap_or_default();
+
+    let parse_keys = |keys: &[String]| keys.iter().map(|key| key.parse().ok()).collect::<Option<Vec<Pubkey>>>();
+    let meta_loaded_addresses = match meta.map(|meta| &meta.loaded_addresses) {
+        Some(OptionSerializer::Some(loaded_addresses)) => {
+            match (parse_keys(&loaded_addresses.writable), parse_keys(&loaded_addresses.readonly)) {
+                (Some(writable), Some(readonly)) => Some(LoadedAddresses { writable, readonly }),
+                _ => {
+                    eprintln!("Error: invalid key in meta loaded addresses");
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+    // Legacy messages have no lookups. Without meta, or with an older RPC response that omits
+    // `loadedAddresses`, the lookups are resolved against the tables themselves when possible.
+    let loaded_addresses = match meta_loaded_addresses {
+        Some(loaded_addresses) => loaded_addresses,
+        _ if lookups.is_empty() => return account_keys_map,
+        _ => match lookup_table_resolver.and_then(|resolver| resolver.resolve(lookups)) {
+            Some(loaded_addresses) => loaded_addresses,
+            None => {
+                eprintln!("Error: transaction uses address lookup tables but meta has no loaded addresses");
+                return account_keys_map;
+            }
+        },
+    };
 
     for writable in loaded_addresses.writable.iter() {
-        account_keys_map.insert(index, writable.parse().unwrap());
+        account_keys_map.insert(index, *writable);
         index += 1;
     }
     for read_only in loaded_addresses.readonly.iter() {
-        account_keys_map.insert(index, read_only.parse().unwrap());
+        account_keys_map.insert(index, *read_only);
         index += 1;
     }
 
     account_keys_map
 }
 
+/// Signer and writable flags for every account in `account_keys`. Static keys take their
+/// role from the message header, keys loaded from lookup tables are never signers and are
+/// writable when they came from a table's writable indexes.
+pub fn get_account_roles(
+    message: &VersionedMessage,
+    account_keys: &HashMap<u8, Pubkey>,
+) -> HashMap<u8, AccountRole> {
+    let mut account_roles: HashMap<u8, AccountRole> = HashMap::new();
+    let header = message.header();
+    let static_keys = message.static_account_keys().len();
+    let signers = header.num_required_signatures as usize;
+    let writable_signers = signers.saturating_sub(header.num_readonly_signed_accounts as usize);
+    let writable_non_signers = static_keys
+        .saturating_sub(signers)
+        .saturating_sub(header.num_readonly_unsigned_accounts as usize);
+    let loaded_writable: usize = message
+        .address_table_lookups()
+        .map(|lookups| lookups.iter().map(|lookup| lookup.writable_indexes.len()).sum())
+        .unwrap_or(0);
+
+    for index in account_keys.keys() {
+        let position = *index as usize;
+        let role = if position < signers {
+            AccountRole {
+                signer: true,
+                writable: position < writable_signers,
+            }
+        } else if position < static_keys {
+            AccountRole {
+                signer: false,
+                writable: position - signers < writable_non_signers,
+            }
+        } else {
+            AccountRole {
+                signer: false,
+                writable: position - static_keys < loaded_writable,
+            }
+        };
+        account_roles.insert(*index, role);
+    }
+
+    account_roles
+}
+
+pub(crate) fn read_u64(data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+pub(crate) fn read_pubkey(data: &[u8], offset: usize) -> Option<Pubkey> {
+    let bytes: [u8; 32] = data.get(offset..offset + 32)?.try_into().ok()?;
+    Some(Pubkey::new_from_array(bytes))
+}
+
 fn find_inner_instruction(
     inner_instructions: &OptionSerializer<Vec<UiInnerInstructions>>,
     instruction_index: u8,
 ) -> Option<UiInnerInstructions> {
-    let inner_instructions = inner_instructions.clone().unwrap();
+    let inner_instructions = match inner_instructions {
+        OptionSerializer::Some(inner_instructions) => inner_instructions,
+        _ => return None,
+    };
     for inner_instruction in inner_instructions.iter() {
         if inner_instruction.index == instruction_index {
             return Some(inner_instruction.clone());
@@ -121,51 +495,112 @@ fn find_inner_instruction(
     None
 }
 
-pub fn build_token_account_map(
+pub fn flatten_instructions(
     message_instructions: &[CompiledInstruction],
-    meta: &UiTransactionStatusMeta,
-    account_keys: &HashMap<u8, Pubkey>,
-) -> HashMap<Pubkey, Pubkey> {
-    // Token Account : User Account
-    let mut token_account_map: HashMap<Pubkey, Pubkey> = HashMap::new();
-    let inner_instructions = &meta.inner_instructions;
-
+    inner_instructions: &OptionSerializer<Vec<UiInnerInstructions>>,
+) -> Vec<FlatInstruction> {
+    let mut instructions: Vec<FlatInstruction> = vec![];
     for (instruction_index, instruction) in message_instructions.iter().enumerate() {
-        let user_token_tuple = find_user_account(
-            instruction.program_id_index,
-            &instruction.accounts,
-            &instruction.data,
-            &account_keys,
-        );
-        token_account_map.insert(user_token_tuple.0, user_token_tuple.1);
+        instructions.push(FlatInstruction {
+            instruction_index: instruction_index as u8,
+            inner_instruction_index: None,
+            stack_height: None,
+            program_id_index: instruction.program_id_index,
+            accounts: instruction.accounts.clone(),
+            data: instruction.data.clone(),
+            parsed: None,
+        });
 
         if let Some(inner_ix) = find_inner_instruction(inner_instructions, instruction_index as u8)
         {
-            for inner_instruction in inner_ix.instructions.iter() {
-                match inner_instruction {
-                    UiInstruction::Compiled(compiled_inner_instruction) => {
-                        let user_token_tuple = find_user_account(
-                            compiled_inner_instruction.program_id_index,
-                            &compiled_inner_instruction.accounts,
-                            &compiled_inner_instruction.data,
-                            &account_keys,
-                        );
-                        token_account_map.insert(user_token_tuple.0, user_token_tuple.1);
-                    }
-                    _ => {}
+            for (inner_instruction_index, inner_instruction) in
+                inner_ix.instructions.iter().enumerate()
+            {
+                if let UiInstruction::Compiled(compiled_inner_instruction) = inner_instruction {
+                    let data = match bs58::decode(&compiled_inner_instruction.data).into_vec() {
+                        Ok(data) => data,
+                        Err(e) => {
+                            eprintln!("Error decoding inner instruction data: {:?}", e);
+                            continue;
+                        }
+                    };
+                    instructions.push(FlatInstruction {
+                        instruction_index: instruction_index as u8,
+                        inner_instruction_index: Some(inner_instruction_index as u8),
+                        stack_height: compiled_inner_instruction.stack_height,
+                        program_id_index: compiled_inner_instruction.program_id_index,
+                        accounts: compiled_inner_instruction.accounts.clone(),
+                        data,
+                        parsed: None,
+                    });
                 }
             }
         }
     }
+    instructions
+}
 
-    let pre_token_balances = meta.pre_token_balances.clone();
-    for pre in pre_token_balances.unwrap().iter() {
-        let token_account = account_keys.get(&pre.account_index).unwrap();
-        let user_account = pre.owner.clone().unwrap();
-        token_account_map.insert(
-            *token_account,
-            Pubkey::from_str(user_account.as_str()).unwrap(),
-        );
+/// `flatten_instructions` for the runtime's own meta, whose inner instruction data is already
+/// raw bytes.
+pub fn flatten_native_instructions(
+    message_instructions: &[CompiledInstruction],
+    inner_instructions: Option<&[InnerInstructions]>,
+) -> Vec<FlatInstruction> {
+    let mut instructions: Vec<FlatInstruction> = vec![];
+    for (instruction_index, instruction) in message_instructions.iter().enumerate() {
+        instructions.push(FlatInstruction {
+            instruction_index: instruction_index as u8,
+            inner_instruction_index: None,
+            stack_height: None,
+            program_id_index: instruction.program_id_index,
+            accounts: instruction.accounts.clone(),
+            data: instruction.data.clone(),
+            parsed: None,
+        });
+
+        let inner_ix = inner_instructions
+            .unwrap_or_default()
+            .iter()
+            .find(|inner_ix| inner_ix.index == instruction_index as u8);
+        for (inner_instruction_index, inner_instruction) in
+            inner_ix.iter().flat_map(|inner_ix| inner_ix.instructions.iter()).enumerate()
+        {
+            instructions.push(FlatInstruction {
+                instruction_index: instruction_index as u8,
+                inner_instruction_index: Some(inner_instruction_index as u8),
+                stack_height: inner_instruction.stack_height,
+                program_id_index: inner_instruction.instruction.program_id_index,
+                accounts: inner_instruction.instruction.accounts.clone(),
+                data: inner_instruction.instruction.data.clone(),
+                parsed: None,
+            });
+        }
+    }
+    instructions
+}
+
+pub fn build_token_account_map(
+    instructions: &[FlatInstruction],
+    balances: &Balances,
+    account_keys: &HashMap<u8, Pubkey>,
+) -> HashMap<Pubkey, Pubkey> {
+    // Token Account : User Account
+    let mut token_account_map: HashMap<Pubkey, Pubkey> = HashMap::new();
+
+    for instruction in instructions.iter() {
+        let user_token_tuple = find_user_account(instruction, account_keys);
+        token_account_map.insert(user_token_tuple.0, user_token_tuple.1);
+    }
+
+    for pre in balances.pre_token_balances.iter() {
+        let Some(token_account) = account_keys.get(&pre.account_index) else {
+            continue;
+        };
+        // Balances recorded before owners were added to the meta have nothing to contribute.
+        let Some(Ok(user_account)) = pre.owner.map(Pubkey::from_str) else {
+            continue;
+        };
+        token_account_map.insert(*token_account, user_account);
     }
 
     token_account_map
@@ -184,242 +619,356 @@ fn find_account_index(
 }
 
 
-fn find_user_account<T: AsRef<[u8]>>(
-    program_id: u8,
-    instruction_accounts: &Vec<u8>,
-    instruction_data: T,
+fn find_user_account(
+    instruction: &FlatInstruction,
     account_keys: &HashMap<u8, Pubkey>,
 ) -> (Pubkey, Pubkey) {
     // Token Account : User Account
-
-    let program = account_keys
-        .get(&program_id)
-        .unwrap_or_else(|| panic!("Program not found"));
-
-    let instruction_bytes = instruction_data.as_ref();
-
-    let decoded_data = match bs58::decode(instruction_bytes).into_vec() {
-        Ok(data) => data,
-        Err(_) => instruction_bytes.to_vec(),
-    };
-
-    if program == &Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap() {
-        match TokenInstruction::unpack(&decoded_data) {
-            Ok(token_instruction) => match token_instruction {
-                TokenInstruction::InitializeAccount {} => {
-                    return (
-                        *account_keys.get(&instruction_accounts[0]).unwrap(),
-                        *account_keys.get(&instruction_accounts[2]).unwrap(),
-                    )
-                }
-                TokenInstruction::InitializeAccount2 { owner } => {
-                    return (*account_keys.get(&instruction_accounts[0]).unwrap(), owner)
-                }
-                TokenInstruction::InitializeAccount3 { owner } => {
-                    return (*account_keys.get(&instruction_accounts[0]).unwrap(), owner)
-                }
-                TokenInstruction::CloseAccount {} => {
-                    return (
-                        *account_keys.get(&instruction_accounts[0]).unwrap(),
-                        *account_keys.get(&instruction_accounts[1]).unwrap(),
-                    )
-                }
-                _ => {}
-            },
-            Err(e) => println!("Error unpacking token instruction: {:?}", e),
-        }
-    }
-
-    if program == &Pubkey::from_str("11111111111111111111111111111111").unwrap() {
-        match bincode::deserialize::<SystemInstruction>(&decoded_data) {
-            Ok(system_instruction) => match system_instruction {
-                SystemInstruction::CreateAccount { owner, .. } => {
-                    return (*account_keys.get(&instruction_accounts[1]).unwrap(), owner)
-                }
-                _ => {}
-            },
-            Err(e) => println!("Error unpacking system instruction: {:?}", e),
-        }
+    match account_parser::process_instruction(instruction, account_keys) {
+        Some(AccountEventKind::InitializeAccount { account, owner, .. }) => (account, owner),
+        Some(AccountEventKind::CreateAssociatedTokenAccount { account, wallet, .. }) => (account, wallet),
+        Some(AccountEventKind::CloseAccount { account, destination, .. }) => (account, destination),
+        Some(AccountEventKind::CreateAccount { account, owner, .. }) => (account, owner),
+        _ => (Pubkey::default(), Pubkey::default()),
     }
-
-    (Pubkey::default(), Pubkey::default())
 }
 
-pub fn find_parent_instruction(
-    _message_instruction: &CompiledInstruction,
-    _inner_instruction: Option<&UiInnerInstructions>,
-    _inner_instruction_index: Option<u8>,
-    account_keys: &HashMap<u8, Pubkey>,
-) -> (Pubkey,Vec<u8>) {
-    if _inner_instruction.is_some() {
-        for (inner_instruction_index,inner_instruction) in _inner_instruction.unwrap().instructions.iter().rev().enumerate() {
-            if inner_instruction_index as u8 <= _inner_instruction_index.unwrap() {
-                match inner_instruction {
-                    UiInstruction::Compiled(compiled_inner_instruction) => {
-                        let program_id = account_keys.get(&compiled_inner_instruction.program_id_index).unwrap();
-                        let program_accounts = compiled_inner_instruction.accounts.clone();
-
-                        if *program_id == Pubkey::from_str(RAYDIUM_V4).unwrap() {
-                            return (*program_id, program_accounts);
-                        }
-
-                    }
-                    _ => {}
-                }
-            }
-        }
-    }
-    let program_id = account_keys.get(&_message_instruction.program_id_index).unwrap();
-    if *program_id == Pubkey::from_str(RAYDIUM_V4).unwrap() {
-        return (*program_id, _message_instruction.accounts.clone());
-    }
-
-    (Pubkey::default(),Vec::new())
-
-
-
-}
 pub fn parse_instructions(
-    message_instructions: &[CompiledInstruction],
-    inner_instructions: &OptionSerializer<Vec<UiInnerInstructions>>,
+    instructions: &[FlatInstruction],
     balance_map: &HashMap<u8, TokenBalanceDiff>,
-    native_balance_map: &HashMap<u8, f64>,
+    native_balance_map: &HashMap<u8, u64>,
     account_keys: &HashMap<u8, Pubkey>,
     token_account_map: &HashMap<Pubkey, Pubkey>,
 ) -> Vec<Transfer> {
     let mut transfers: Vec<Transfer> = vec![];
-    for (instruction_index, instruction) in message_instructions.iter().enumerate() {
+    let instruction_tree = InstructionTree::build(instructions, account_keys);
+    for (node, ancestors) in instruction_tree.walk() {
         if let Some(mut transfer) = parse_instruction(
-            instruction.program_id_index,
-            &instruction.accounts,
-            &instruction.data,
+            node,
             balance_map,
             native_balance_map,
             account_keys,
-            &token_account_map,
+            token_account_map,
         ) {
-            let parent_instruction = find_parent_instruction(
-                instruction,
-                None,
-                None,
-                account_keys,
-            );
-            transfer.instruction_program_id = Some(parent_instruction.0);
-            transfer.instruction_input_accounts= Some(parent_instruction.1);
+            let swap_instruction = ancestors
+                .iter()
+                .rev()
+                .find(|ancestor| SWAP_PROGRAMS.contains(&ancestor.program_id.to_string().as_str()));
+            transfer.instruction_program_id =
+                Some(swap_instruction.map(|ancestor| ancestor.program_id).unwrap_or_default());
+            transfer.instruction_input_accounts =
+                Some(swap_instruction.map(|ancestor| ancestor.accounts.clone()).unwrap_or_default());
+            transfer.instruction_index = node.instruction_index;
+            transfer.inner_instruction_index = node.inner_instruction_index;
+            transfer.caller_program_id = ancestors.last().map(|caller| caller.program_id);
+            transfer.outer_program_id = ancestors.first().map(|outer| outer.program_id);
             transfers.push(transfer);
         }
-
-        if let Some(inner_ix) = find_inner_instruction(inner_instructions, instruction_index as u8)
-        {
-            for (inner_instruction_index, inner_instruction) in
-                inner_ix.instructions.iter().enumerate()
-            {
-                match inner_instruction {
-                    UiInstruction::Compiled(compiled_inner_instruction) => {
-                        if let Some(mut transfer) = parse_instruction(
-                            compiled_inner_instruction.program_id_index,
-                            &compiled_inner_instruction.accounts,
-                            &compiled_inner_instruction.data,
-                            balance_map,
-                            native_balance_map,
-                            account_keys,
-                            &token_account_map,
-                        ) {
-                            let parent_instruction =find_parent_instruction(
-                                instruction,
-                                Some(&inner_ix),
-                                Some(inner_instruction_index as u8),
-                                account_keys,
-                            );
-                            transfer.instruction_program_id = Some(parent_instruction.0);
-                            transfer.instruction_input_accounts= Some(parent_instruction.1);
-                            transfers.push(transfer);
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
     }
     transfers
 }
 
-fn parse_instruction<T: AsRef<[u8]>>(
-    program_id: u8,
-    instruction_accounts: &Vec<u8>,
-    instruction_data: T,
+fn parse_instruction(
+    node: &InstructionNode,
     balance_map: &HashMap<u8, TokenBalanceDiff>,
-    native_balance_map: &HashMap<u8, f64>,
+    native_balance_map: &HashMap<u8, u64>,
     account_keys: &HashMap<u8, Pubkey>,
     token_account_map: &HashMap<Pubkey, Pubkey>,
 ) -> Option<Transfer> {
-    let program = account_keys
-        .get(&program_id)
-        .unwrap_or_else(|| panic!("Program not found"));
+    let instruction_accounts = &node.accounts;
+
+    let Some(KnownInstruction::Token(token_instruction)) = node.known() else {
+        return None;
+    };
+    let destination_position = match token_instruction {
+        TokenInstruction::Transfer { .. } => 1,
+        TokenInstruction::TransferChecked { .. } => 2,
+        _ => return None,
+    };
+
+    let source_index = *instruction_accounts.first()?;
+    let destination_index = *instruction_accounts.get(destination_position)?;
+    let source = account_keys
+        .get(&source_index)
+        .cloned()
+        .unwrap_or_default();
+    let source_user =
+        token_account_map.get(&source).cloned().unwrap_or_default();
+    let destination = account_keys
+        .get(&destination_index)
+        .cloned()
+        .unwrap_or_default();
+    let destination_user = token_account_map
+        .get(&destination)
+        .cloned()
+        .unwrap_or_default();
+    // The owner of a token account that already existed is often not part of
+    // the transaction, its SOL balance is unknown then.
+    let destination_user_index = find_account_index(account_keys, &destination_user);
+
+    let token_balance_diff = balance_map
+        .get(&destination_index)
+        .cloned()
+        .or_else(|| {
+            native_balance_map
+                .get(&destination_user_index?)
+                .map(|&lamports| TokenBalanceDiff {
+                    token_amount: lamports as f64 / 1_000_000_000.0,
+                    raw_amount: lamports,
+                    mint: "SOL".to_string(),
+                    decimals: 0,
+                })
+        })
+        .unwrap_or(TokenBalanceDiff {
+            token_amount: 0.0,
+            raw_amount: 0,
+            mint: "".to_string(),
+            decimals: 0,
+        });
+
+    Some(Transfer {
+        instruction_index: 0,
+        inner_instruction_index: None,
+        instruction_program_id: None,
+        instruction_input_accounts: None,
+        caller_program_id: None,
+        outer_program_id: None,
+        token_balance_diff,
+        fee: None,
+        to_user_account: destination_user,
+        to_token_account: destination,
+        from_user_account: source_user,
+        from_token_account: source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use serde_json::json;
+    use solana_sdk::address_lookup_table::state::{AddressLookupTable, LookupTableMeta};
+    use solana_sdk::message::v0::{self, MessageAddressTableLookup};
+    use solana_sdk::message::{legacy, MessageHeader, VersionedMessage};
+    use solana_sdk::pubkey::Pubkey;
+    use crate::lookup_table::{AddressLookupTableResolver, InMemoryLookupTableSource};
+    use crate::offline;
+    use crate::pipeline;
+    use crate::ASSOCIATED_TOKEN_PROGRAM;
+    use super::{
+        build_token_account_map, create_balance_diff_map, create_native_balance_diff_map, get_account_roles,
+        get_all_account_keys, Balances, FlatInstruction, TokenBalance,
+    };
+
+    #[test]
+    fn account_keys_without_meta_come_from_the_lookup_tables() {
+        let (payer, program, table) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let table_data = AddressLookupTable {
+            meta: LookupTableMeta::default(),
+            addresses: Cow::Owned(vec![first, second]),
+        }
+        .serialize_for_tests()
+        .unwrap();
+        let mut source = InMemoryLookupTableSource::new();
+        source.insert(table, table_data);
+        let resolver = AddressLookupTableResolver::new(source);
+
+        let message = VersionedMessage::V0(v0::Message {
+            account_keys: vec![payer, program],
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: table,
+                writable_indexes: vec![1],
+                readonly_indexes: vec![0],
+            }],
+            ..v0::Message::default()
+        });
+
+        let account_keys = get_all_account_keys(None, &message, Some(&resolver));
+        let ordered: Vec<Pubkey> = (0..account_keys.len() as u8).map(|index| account_keys[&index]).collect();
+        assert_eq!(ordered, [payer, program, second, first]);
+
+        // Without a resolver only the static keys are known.
+        assert_eq!(get_all_account_keys(None, &message, None).len(), 2);
+    }
 
-    if program == &Pubkey::from_str("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA").unwrap() {
-        let instruction_bytes = instruction_data.as_ref();
+    #[test]
+    fn transfer_to_token_account_whose_owner_is_not_in_the_transaction() {
+        let token_balance = |account_index: u8, owner: &str, amount: &str, ui_amount: f64| {
+            json!({
+                "accountIndex": account_index,
+                "mint": "FqUwnBMN1shpeqKVm7W5fN73tvrjVr19TQFFgkoFFzhq",
+                "owner": owner,
+                "programId": "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                "uiTokenAmount": { "amount": amount, "decimals": 6, "uiAmount": ui_amount, "uiAmountString": "" },
+            })
+        };
+        let sender = "AWxggjuZRmWULwxwPeM6ZZxRtdDdekVq22mFRx2QbW7U";
+        // Only named in the token balances, like the owner of an associated token account that
+        // already existed.
+        let recipient = "FciD4i2WPEYinnKaCzFZAPTUsRxTCpJM6FyQmezmkkoj";
+        let response = json!({
+            "slot": 123,
+            "blockTime": 1700000000,
+            "transaction": {
+                "signatures": ["1111111111111111111111111111111111111111111111111111111111111111"],
+                "message": {
+                    "header": { "numRequiredSignatures": 1, "numReadonlySignedAccounts": 0, "numReadonlyUnsignedAccounts": 1 },
+                    "accountKeys": [
+                        sender,
+                        "3XyEru3CMmGyFogjqZLGR57ZfwBgVvzAea7CR62ZibPT",
+                        "QngDp1HjgnPaZfkvAJkTQNFzbiwEQJTG4WuVyD4aNcp",
+                        "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA",
+                    ],
+                    "recentBlockhash": "11111111111111111111111111111111",
+                    // Transfer { amount: 2_500_000 }
+                    "instructions": [{ "programIdIndex": 3, "accounts": [1, 2, 0], "data": "3gFAscqFEt1m" }],
+                },
+            },
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [1000000000, 2039280, 2039280, 1],
+                "postBalances": [999995000, 2039280, 2039280, 1],
+                "innerInstructions": [],
+                "preTokenBalances": [token_balance(1, sender, "5000000", 5.0), token_balance(2, recipient, "0", 0.0)],
+                "postTokenBalances": [
+                    token_balance(1, sender, "2500000", 2.5),
+                    token_balance(2, recipient, "2500000", 2.5),
+                ],
+                "loadedAddresses": { "writable": [], "readonly": [] },
+            },
+        });
+
+        let txs = offline::transactions_from_json(&response.to_string()).unwrap();
+        let parsed = pipeline::parse_confirmed_transaction(&txs[0], None).unwrap();
+        assert_eq!(parsed.transfers.len(), 1);
+        let transfer = &parsed.transfers[0];
+        assert_eq!(transfer.from_user_account.to_string(), sender);
+        assert_eq!(transfer.to_user_account.to_string(), recipient);
+        assert_eq!(transfer.token_balance_diff.token_amount, 2.5);
+        assert_eq!(transfer.token_balance_diff.raw_amount, 2_500_000);
+    }
 
-        let decoded_data = match bs58::decode(instruction_bytes).into_vec() {
-            Ok(data) => data,
-            Err(_) => instruction_bytes.to_vec(),
+    #[test]
+    fn raw_balance_diffs_are_exact_above_f64_precision() {
+        let balance = |amount: &'static str| TokenBalance {
+            account_index: 1,
+            mint: "FqUwnBMN1shpeqKVm7W5fN73tvrjVr19TQFFgkoFFzhq",
+            owner: None,
+            decimals: 9,
+            amount,
+            ui_amount: amount.parse::<f64>().ok().map(|amount| amount / 1e9),
         };
-        match TokenInstruction::unpack(&decoded_data) {
-            Ok(token_instruction) => {
-                match token_instruction {
-                    TokenInstruction::Transfer { amount } => {
-                        let source = account_keys
-                            .get(&instruction_accounts[0])
-                            .cloned()
-                            .unwrap_or_default();
-                        let source_user =
-                            token_account_map.get(&source).cloned().unwrap_or_default();
-                        let destination = account_keys
-                            .get(&instruction_accounts[1])
-                            .cloned()
-                            .unwrap_or_default();
-                        let destination_user = token_account_map
-                            .get(&destination)
-                            .cloned()
-                            .unwrap_or_default();
-                        let destination_user_index = find_account_index(account_keys, &destination_user).unwrap_or_else(|| panic!("Destination user not found"));
-
-
-                        let token_balance_diff = balance_map
-                            .get(&instruction_accounts[1])
-                            .cloned()
-                            .or_else(|| {
-                                native_balance_map
-                                    .get(&destination_user_index)
-                                    .map(|&amount| TokenBalanceDiff {
-                                        token_amount: amount as f64,
-                                        mint: "SOL".to_string(),
-                                        decimals: 0,
-                                    })
-                            })
-                            .unwrap_or(TokenBalanceDiff {
-                                token_amount: 0.0,
-                                mint: "".to_string(),
-                                decimals: 0,
-                            });
-
-                        let transfer = Transfer {
-                            instruction_program_id: None,
-                            instruction_input_accounts: None,
-                            token_balance_diff,
-                            to_user_account: destination_user,
-                            to_token_account: destination,
-                            from_user_account: source_user,
-                            from_token_account: source,
-                        };
-                        return Some(transfer);
-                    }
-                    _ => {}
-                }
-            }
-            Err(e) => println!("Error unpacking token instruction: {:?}", e),
+        let balances = Balances {
+            pre_balances: &[5_000, 1],
+            post_balances: &[9_007_199_254_745_993, 1],
+            pre_token_balances: vec![balance("1")],
+            post_token_balances: vec![balance("18446744073709551615")],
+        };
+
+        let diffs = create_balance_diff_map(&balances);
+        assert_eq!(diffs[&1].raw_amount, u64::MAX - 1);
+        let lamports = create_native_balance_diff_map(&balances);
+        assert_eq!(lamports[&0], 9_007_199_254_740_993);
+        assert_eq!(lamports[&1], 0);
+    }
+
+    #[test]
+    fn associated_token_accounts_map_to_their_wallet() {
+        let (funder, wallet, mint) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let (created, existing) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let account_keys = [funder, created, wallet, mint, existing, ASSOCIATED_TOKEN_PROGRAM.parse().unwrap()]
+            .into_iter()
+            .enumerate()
+            .map(|(index, key)| (index as u8, key))
+            .collect();
+        let create = |instruction_index: u8, token_account: u8, data: Vec<u8>| FlatInstruction {
+            instruction_index,
+            inner_instruction_index: None,
+            stack_height: None,
+            program_id_index: 5,
+            accounts: vec![0, token_account, 2, 3],
+            data,
+            parsed: None,
+        };
+        let instructions = [create(0, 1, vec![]), create(1, 4, vec![1])];
+        let balances = Balances {
+            pre_balances: &[],
+            post_balances: &[],
+            pre_token_balances: vec![],
+            post_token_balances: vec![],
+        };
+
+        let token_account_map = build_token_account_map(&instructions, &balances, &account_keys);
+
+        assert_eq!(token_account_map.get(&created), Some(&wallet));
+        assert_eq!(token_account_map.get(&existing), Some(&wallet));
+    }
+
+    /// (signer, writable) of the first `len` accounts.
+    fn roles(message: &VersionedMessage, len: u8) -> Vec<(bool, bool)> {
+        let account_keys = (0..len).map(|index| (index, Pubkey::new_unique())).collect();
+        let account_roles = get_account_roles(message, &account_keys);
+        (0..len).map(|index| (account_roles[&index].signer, account_roles[&index].writable)).collect()
+    }
+
+    /// Two signers, the second read-only, then two writable and one read-only account.
+    fn header() -> MessageHeader {
+        MessageHeader {
+            num_required_signatures: 2,
+            num_readonly_signed_accounts: 1,
+            num_readonly_unsigned_accounts: 1,
         }
     }
 
-    None
+    #[test]
+    fn legacy_account_roles_come_from_the_header() {
+        let message = VersionedMessage::Legacy(legacy::Message {
+            header: header(),
+            account_keys: (0..5).map(|_| Pubkey::new_unique()).collect(),
+            ..legacy::Message::default()
+        });
+
+        assert_eq!(
+            roles(&message, 5),
+            [(true, true), (true, false), (false, true), (false, true), (false, false)],
+        );
+    }
+
+    #[test]
+    fn loaded_accounts_are_writable_when_loaded_from_writable_indexes() {
+        let message = VersionedMessage::V0(v0::Message {
+            header: header(),
+            account_keys: (0..5).map(|_| Pubkey::new_unique()).collect(),
+            address_table_lookups: vec![
+                MessageAddressTableLookup {
+                    account_key: Pubkey::new_unique(),
+                    writable_indexes: vec![3],
+                    readonly_indexes: vec![0, 1],
+                },
+                MessageAddressTableLookup {
+                    account_key: Pubkey::new_unique(),
+                    writable_indexes: vec![7],
+                    readonly_indexes: vec![],
+                },
+            ],
+            ..v0::Message::default()
+        });
+
+        // Loaded keys follow the static ones, every table's writable keys before any read-only.
+        assert_eq!(
+            roles(&message, 8),
+            [
+                (true, true),
+                (true, false),
+                (false, true),
+                (false, true),
+                (false, false),
+                (false, true),
+                (false, true),
+                (false, false),
+            ],
+        );
+    }
 }
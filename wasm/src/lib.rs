@@ -0,0 +1,52 @@
+//! WebAssembly bindings for decoding transactions in the browser, built with
+//! [wasm-pack](https://rustwasm.github.io/wasm-pack/):
+//!
+//! ```sh
+//! wasm-pack build wasm --target web
+//! ```
+//!
+//! ```js
+//! import init, { parseTransaction } from "./pkg/solana_parser_wasm.js";
+//!
+//! await init();
+//! const parsed = parseTransaction(response); // a getTransaction string or object
+//! for (const swap of parsed.swaps) {
+//!     console.log(swap.token_in, swap.amount_in, swap.token_out, swap.amount_out);
+//! }
+//! ```
+//!
+//! Results are objects in the layout described in `docs/json-output.md`, amounts over 2^53
+//! lose precision as with any `JSON.parse`. The parser is built without the `rpc` feature, so
+//! nothing is fetched: lookup tables must already be resolved in the meta's `loadedAddresses`,
+//! which getTransaction always includes.
+
+use solana_parser::offline;
+use solana_parser::pipeline;
+use wasm_bindgen::prelude::*;
+
+/// Parses one getTransaction response, the JSON-RPC envelope or just its `result`, as a string
+/// or an already parsed object. Returns `null` for a `null` result or a transaction that fails
+/// to decode.
+#[wasm_bindgen(js_name = parseTransaction)]
+pub fn parse_transaction(transaction: JsValue) -> Result<JsValue, JsError> {
+    let json = match transaction.as_string() {
+        Some(json) => json,
+        // `undefined` and functions stringify to `undefined` rather than failing.
+        None => js_sys::JSON::stringify(&transaction)
+            .ok()
+            .and_then(|json| json.as_string())
+            .ok_or_else(|| JsError::new("expected a JSON string or an object"))?,
+    };
+
+    let txs = offline::transactions_from_json(&json)?;
+    let parsed = match txs.as_slice() {
+        [] => None,
+        [tx] => pipeline::parse_confirmed_transaction(tx, None),
+        _ => return Err(JsError::new(&format!("expected one transaction, found {}", txs.len()))),
+    };
+    match parsed {
+        Some(parsed) => js_sys::JSON::parse(&serde_json::to_string(&parsed)?)
+            .map_err(|_| JsError::new("could not convert the parsed transaction")),
+        None => Ok(JsValue::NULL),
+    }
+}